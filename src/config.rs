@@ -1,6 +1,11 @@
-use crate::module::{notifier::HasWebhookUrl, TaskStatus};
+use crate::module::{
+    notifier::{self, HasWebhookUrl},
+    recorder::{BackendKind, YTAState},
+    TaskOverrides, TaskStatus,
+};
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use ts_rs::TS;
 
 #[derive(Clone, TS, Serialize, Deserialize, Debug)]
@@ -9,10 +14,21 @@ pub struct Config {
     #[serde(default)]
     pub ytarchive: YtarchiveConfig,
     #[serde(default)]
+    pub ytdlp: YtdlpConfig,
+    #[serde(default)]
     pub scraper: ScraperConfig,
     pub notifier: Option<NotifierConfig>,
     #[serde(default)]
     pub webserver: Option<WebserverConfig>,
+    pub store: Option<StoreConfig>,
+    #[serde(default)]
+    pub hook: Vec<HookConfig>,
+    /// Named filters that channels and groups can refer to
+    #[serde(default)]
+    pub filter_set: HashMap<String, FilterSetConfig>,
+    /// Named groups of settings that channels can inherit
+    #[serde(default)]
+    pub group: HashMap<String, GroupConfig>,
     #[serde(default)]
     pub channel: Vec<ChannelConfig>,
 
@@ -25,9 +41,14 @@ impl Default for Config {
     fn default() -> Self {
         Config {
             ytarchive: YtarchiveConfig::default(),
+            ytdlp: YtdlpConfig::default(),
             scraper: ScraperConfig::default(),
             notifier: None,
             webserver: None,
+            store: None,
+            hook: Vec::new(),
+            filter_set: HashMap::new(),
+            group: HashMap::new(),
             channel: Vec::new(),
             config_path: String::new(),
         }
@@ -45,6 +66,31 @@ pub struct YtarchiveConfig {
     #[serde(default = "default_delay_start")]
     #[ts(type = "string")]
     pub delay_start: std::time::Duration,
+    /// Maximum number of recordings running at the same time. Tasks beyond
+    /// this limit wait in a queue. Unlimited if not set.
+    pub max_concurrent: Option<usize>,
+    #[serde(default)]
+    pub retry: RetryConfig,
+    /// How long to wait for running recordings to finish when shutting down.
+    #[serde(with = "humantime_serde")]
+    #[serde(default = "default_shutdown_grace_period")]
+    #[ts(type = "string")]
+    pub shutdown_grace_period: std::time::Duration,
+    #[serde(default)]
+    pub disk_space: DiskSpaceConfig,
+    /// Removes files left in the working directories by recordings that are
+    /// no longer running. Disabled if not present.
+    pub janitor: Option<JanitorConfig>,
+    #[serde(default)]
+    pub stall_timeout: StallTimeoutConfig,
+    /// Backends to record with. The next one is used when the previous one
+    /// ends in one of the `fallback_on` states.
+    #[serde(default = "default_backends")]
+    pub backends: Vec<BackendKind>,
+    #[serde(default = "default_fallback_on")]
+    pub fallback_on: Vec<YTAState>,
+    #[serde(default)]
+    pub task_log: TaskLogConfig,
 }
 
 impl Default for YtarchiveConfig {
@@ -55,6 +101,15 @@ impl Default for YtarchiveConfig {
             args: Vec::default(),
             quality: String::default(),
             delay_start: std::time::Duration::default(),
+            max_concurrent: None,
+            retry: RetryConfig::default(),
+            shutdown_grace_period: std::time::Duration::default(),
+            disk_space: DiskSpaceConfig::default(),
+            janitor: None,
+            stall_timeout: StallTimeoutConfig::default(),
+            backends: default_backends(),
+            fallback_on: default_fallback_on(),
+            task_log: TaskLogConfig::default(),
         }
     }
 }
@@ -63,17 +118,345 @@ fn default_delay_start() -> std::time::Duration {
     std::time::Duration::from_secs(1)
 }
 
+fn default_shutdown_grace_period() -> std::time::Duration {
+    std::time::Duration::from_secs(60)
+}
+
+impl YtarchiveConfig {
+    /// Returns true if a backend that ended in the given state should make
+    /// way for the next one.
+    pub fn should_fall_back(&self, state: &YTAState) -> bool {
+        self.fallback_on
+            .iter()
+            .any(|s| std::mem::discriminant(s) == std::mem::discriminant(state))
+    }
+}
+
+fn default_backends() -> Vec<BackendKind> {
+    vec![BackendKind::Ytarchive]
+}
+
+fn default_fallback_on() -> Vec<YTAState> {
+    vec![YTAState::Ended, YTAState::AlreadyProcessed]
+}
+
+#[derive(Clone, TS, Serialize, Deserialize, Debug, PartialEq)]
+#[ts(export)]
+pub struct YtdlpConfig {
+    #[serde(default = "default_ytdlp_executable_path")]
+    pub executable_path: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default = "default_ytdlp_format")]
+    pub format: String,
+}
+
+fn default_ytdlp_executable_path() -> String {
+    "yt-dlp".into()
+}
+
+fn default_ytdlp_format() -> String {
+    "bestvideo*+bestaudio/best".into()
+}
+
+impl Default for YtdlpConfig {
+    fn default() -> Self {
+        YtdlpConfig {
+            executable_path: default_ytdlp_executable_path(),
+            args: Vec::new(),
+            format: default_ytdlp_format(),
+        }
+    }
+}
+
+#[derive(Clone, TS, Serialize, Deserialize, Debug, PartialEq)]
+#[ts(export)]
+pub struct RetryConfig {
+    /// Maximum number of times ytarchive is started for a task, including the
+    /// first attempt.
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// Delay before the first retry. Doubles after each attempt.
+    #[serde(with = "humantime_serde")]
+    #[serde(default = "default_retry_backoff")]
+    #[ts(type = "string")]
+    pub backoff: std::time::Duration,
+    #[serde(with = "humantime_serde")]
+    #[serde(default = "default_retry_max_backoff")]
+    #[ts(type = "string")]
+    pub max_backoff: std::time::Duration,
+    /// States in which ytarchive can end that will trigger a retry.
+    #[serde(default = "default_retry_on")]
+    pub retry_on: Vec<YTAState>,
+}
+
+fn default_max_attempts() -> u32 {
+    1
+}
+
+fn default_retry_backoff() -> std::time::Duration {
+    std::time::Duration::from_secs(30)
+}
+
+fn default_retry_max_backoff() -> std::time::Duration {
+    std::time::Duration::from_secs(60 * 30)
+}
+
+fn default_retry_on() -> Vec<YTAState> {
+    vec![YTAState::Errored]
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            max_attempts: default_max_attempts(),
+            backoff: default_retry_backoff(),
+            max_backoff: default_retry_max_backoff(),
+            retry_on: default_retry_on(),
+        }
+    }
+}
+
+impl RetryConfig {
+    /// Returns true if a task that ended in the given state after the given
+    /// attempt should be started again.
+    pub fn should_retry(&self, state: &YTAState, attempt: u32) -> bool {
+        attempt < self.max_attempts
+            && self
+                .retry_on
+                .iter()
+                .any(|s| std::mem::discriminant(s) == std::mem::discriminant(state))
+    }
+
+    /// Returns how long to wait before starting the attempt after the given
+    /// one.
+    pub fn backoff(&self, attempt: u32) -> std::time::Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.backoff
+            .checked_mul(factor)
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff)
+    }
+}
+
+/// How long ytarchive may go without making progress before it's considered
+/// stalled and killed, for each state. Never if not set.
+#[derive(Clone, TS, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[ts(export)]
+pub struct StallTimeoutConfig {
+    /// Maximum time without new fragments while recording
+    #[serde(default, with = "humantime_serde")]
+    #[ts(type = "string | null")]
+    pub recording: Option<std::time::Duration>,
+    /// Maximum time without new output while muxing
+    #[serde(default, with = "humantime_serde")]
+    #[ts(type = "string | null")]
+    pub muxing: Option<std::time::Duration>,
+}
+
+impl StallTimeoutConfig {
+    /// Returns the timeout for the state, if it has one.
+    pub fn for_state(&self, state: &YTAState) -> Option<std::time::Duration> {
+        match state {
+            YTAState::Recording => self.recording,
+            YTAState::Muxing => self.muxing,
+            _ => None,
+        }
+    }
+}
+
+/// Free space needed to start recordings. Sizes are given like "20GiB" or
+/// "500MB", and no space is required if not set.
+#[derive(Clone, TS, Serialize, Deserialize, Debug, PartialEq)]
+#[ts(export)]
+pub struct DiskSpaceConfig {
+    /// Minimum free space in the working directory
+    pub min_free_working: Option<String>,
+    /// Minimum free space in the output directory of each channel
+    pub min_free_output: Option<String>,
+    /// How often free space is checked while tasks are queued or recording
+    #[serde(with = "humantime_serde")]
+    #[serde(default = "default_disk_check_interval")]
+    #[ts(type = "string")]
+    pub check_interval: std::time::Duration,
+}
+
+fn default_disk_check_interval() -> std::time::Duration {
+    std::time::Duration::from_secs(60)
+}
+
+impl Default for DiskSpaceConfig {
+    fn default() -> Self {
+        DiskSpaceConfig {
+            min_free_working: None,
+            min_free_output: None,
+            check_interval: default_disk_check_interval(),
+        }
+    }
+}
+
+impl DiskSpaceConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        self.min_free_working()?;
+        self.min_free_output()?;
+        if self.check_interval.is_zero() {
+            return Err("Disk space check interval must not be zero".into());
+        }
+        Ok(())
+    }
+
+    pub fn min_free_working(&self) -> Result<Option<u64>, String> {
+        self.min_free_working.as_deref().map(parse_size).transpose()
+    }
+
+    pub fn min_free_output(&self) -> Result<Option<u64>, String> {
+        self.min_free_output.as_deref().map(parse_size).transpose()
+    }
+}
+
+/// Where the output of the recording processes is kept. Each task gets its own
+/// log file, named after its video ID.
+#[derive(Clone, TS, Serialize, Deserialize, Debug, PartialEq)]
+#[ts(export)]
+pub struct TaskLogConfig {
+    #[serde(default = "default_task_log_directory")]
+    pub directory: String,
+    /// Size after which a log file is rotated, like "10MiB"
+    #[serde(default = "default_task_log_max_size")]
+    pub max_size: String,
+    /// Number of rotated files kept next to the current one
+    #[serde(default = "default_task_log_max_files")]
+    pub max_files: usize,
+    /// Number of recent lines of each task kept in memory
+    #[serde(default = "default_task_log_buffer_lines")]
+    pub buffer_lines: usize,
+}
+
+fn default_task_log_directory() -> String {
+    "./logs".into()
+}
+
+fn default_task_log_max_size() -> String {
+    "10MiB".into()
+}
+
+fn default_task_log_max_files() -> usize {
+    2
+}
+
+fn default_task_log_buffer_lines() -> usize {
+    1000
+}
+
+impl Default for TaskLogConfig {
+    fn default() -> Self {
+        TaskLogConfig {
+            directory: default_task_log_directory(),
+            max_size: default_task_log_max_size(),
+            max_files: default_task_log_max_files(),
+            buffer_lines: default_task_log_buffer_lines(),
+        }
+    }
+}
+
+impl TaskLogConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_size()? == 0 {
+            return Err("Task log max_size must not be zero".into());
+        }
+        Ok(())
+    }
+
+    pub fn max_size(&self) -> Result<u64, String> {
+        parse_size(&self.max_size)
+    }
+}
+
+#[derive(Clone, TS, Serialize, Deserialize, Debug, PartialEq)]
+#[ts(export)]
+pub struct JanitorConfig {
+    #[serde(with = "humantime_serde")]
+    #[serde(default = "default_janitor_interval")]
+    #[ts(type = "string")]
+    pub interval: std::time::Duration,
+    /// Files modified more recently than this are left alone
+    #[serde(with = "humantime_serde")]
+    #[serde(default = "default_janitor_min_age")]
+    #[ts(type = "string")]
+    pub min_age: std::time::Duration,
+    /// Move orphaned files here instead of deleting them
+    pub quarantine_directory: Option<String>,
+    /// Only log the files that would be removed
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+fn default_janitor_interval() -> std::time::Duration {
+    std::time::Duration::from_secs(60 * 60)
+}
+
+fn default_janitor_min_age() -> std::time::Duration {
+    std::time::Duration::from_secs(24 * 60 * 60)
+}
+
+impl Default for JanitorConfig {
+    fn default() -> Self {
+        JanitorConfig {
+            interval: default_janitor_interval(),
+            min_age: default_janitor_min_age(),
+            quarantine_directory: None,
+            dry_run: false,
+        }
+    }
+}
+
+impl JanitorConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.interval.is_zero() {
+            return Err("Janitor interval must not be zero".into());
+        }
+        Ok(())
+    }
+}
+
+/// Parses a size in bytes, such as "1.5GiB", "500MB" or "1024".
+pub fn parse_size(size: &str) -> Result<u64, String> {
+    let invalid = || format!("Invalid size: {}", size);
+    let split = size
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(size.len());
+    let (value, unit) = size.split_at(split);
+    let multiplier: u64 = match unit.trim() {
+        "" | "B" => 1,
+        "KB" => 1_000,
+        "MB" => 1_000_000,
+        "GB" => 1_000_000_000,
+        "TB" => 1_000_000_000_000,
+        "KiB" => 1 << 10,
+        "MiB" => 1 << 20,
+        "GiB" => 1 << 30,
+        "TiB" => 1 << 40,
+        _ => return Err(invalid()),
+    };
+    let value: f64 = value.trim().parse().map_err(|_| invalid())?;
+    Ok((value * multiplier as f64) as u64)
+}
+
 #[derive(Clone, TS, Serialize, Deserialize, Debug, PartialEq)]
 #[ts(export)]
 pub struct ScraperConfig {
     #[serde(default)]
     pub rss: ScraperRSSConfig,
+    /// Scrape the streams tab of each channel for live and upcoming videos.
+    /// Disabled if not present.
+    pub channel_page: Option<ScraperChannelPageConfig>,
 }
 
 impl Default for ScraperConfig {
     fn default() -> Self {
         ScraperConfig {
             rss: ScraperRSSConfig::default(),
+            channel_page: None,
         }
     }
 }
@@ -88,17 +471,68 @@ pub struct ScraperRSSConfig {
     #[serde(default = "default_ignore_older_than")]
     #[ts(type = "string")]
     pub ignore_older_than: std::time::Duration,
+    /// File where the IDs of already scraped videos are kept between
+    /// restarts. If not present, they are only kept in memory.
+    pub scraped_path: Option<String>,
+    #[serde(with = "humantime_serde")]
+    #[serde(default = "default_scraped_retention")]
+    #[ts(type = "string")]
+    pub scraped_retention: std::time::Duration,
+    /// Mark videos found in each channel's outpath as already scraped.
+    #[serde(default)]
+    pub seed_from_outpath: bool,
 }
 
 fn default_ignore_older_than() -> std::time::Duration {
     std::time::Duration::from_secs(60 * 60 * 24)
 }
 
+fn default_scraped_retention() -> std::time::Duration {
+    std::time::Duration::from_secs(60 * 60 * 24 * 7)
+}
+
 impl Default for ScraperRSSConfig {
     fn default() -> Self {
         ScraperRSSConfig {
             poll_interval: std::time::Duration::default(),
             ignore_older_than: std::time::Duration::default(),
+            scraped_path: None,
+            scraped_retention: default_scraped_retention(),
+            seed_from_outpath: false,
+        }
+    }
+}
+
+#[derive(Clone, TS, Serialize, Deserialize, Debug, PartialEq)]
+#[ts(export)]
+pub struct ScraperChannelPageConfig {
+    #[serde(with = "humantime_serde")]
+    #[serde(default = "default_channel_page_poll_interval")]
+    #[ts(type = "string")]
+    pub poll_interval: std::time::Duration,
+    /// File where the IDs of already scraped videos are kept between
+    /// restarts. If not present, they are only kept in memory.
+    pub scraped_path: Option<String>,
+    #[serde(with = "humantime_serde")]
+    #[serde(default = "default_scraped_retention")]
+    #[ts(type = "string")]
+    pub scraped_retention: std::time::Duration,
+    /// Mark videos found in each channel's outpath as already scraped.
+    #[serde(default)]
+    pub seed_from_outpath: bool,
+}
+
+fn default_channel_page_poll_interval() -> std::time::Duration {
+    std::time::Duration::from_secs(5 * 60)
+}
+
+impl Default for ScraperChannelPageConfig {
+    fn default() -> Self {
+        ScraperChannelPageConfig {
+            poll_interval: default_channel_page_poll_interval(),
+            scraped_path: None,
+            scraped_retention: default_scraped_retention(),
+            seed_from_outpath: false,
         }
     }
 }
@@ -108,6 +542,9 @@ impl Default for ScraperRSSConfig {
 pub struct NotifierConfig {
     pub discord: Option<DiscordConfig>,
     pub slack: Option<SlackConfig>,
+    /// Generic webhooks, keyed by a name of your choice
+    #[serde(default)]
+    pub webhook: HashMap<String, WebhookConfig>,
 }
 
 impl NotifierConfig {
@@ -118,6 +555,11 @@ impl NotifierConfig {
         if let Some(slack) = &self.slack {
             slack.validate()?;
         }
+        for (name, webhook) in &self.webhook {
+            webhook
+                .validate()
+                .map_err(|e| format!("Invalid webhook notifier config {}: {}", name, e))?;
+        }
         Ok(())
     }
 }
@@ -169,80 +611,624 @@ impl SlackConfig {
     }
 }
 
-impl HasWebhookUrl for SlackConfig {
-    fn webhook_url(&self) -> Option<&String> {
-        self.webhook_url.as_ref()
+impl HasWebhookUrl for SlackConfig {
+    fn webhook_url(&self) -> Option<&String> {
+        self.webhook_url.as_ref()
+    }
+
+    fn webhook_url_file(&self) -> Option<&String> {
+        self.webhook_url_file.as_ref()
+    }
+}
+
+#[derive(Clone, TS, Serialize, Deserialize, Debug, PartialEq)]
+#[ts(export)]
+pub struct WebhookConfig {
+    pub webhook_url: Option<String>,
+    pub webhook_url_file: Option<String>,
+    #[serde(default = "default_webhook_method")]
+    pub method: String,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// Template for the request body. `{{path}}` placeholders are replaced by
+    /// the matching field of the notification. If not set, the whole
+    /// notification is sent as JSON.
+    pub body: Option<String>,
+    pub notify_on: Vec<TaskStatus>,
+}
+
+fn default_webhook_method() -> String {
+    "POST".to_string()
+}
+
+impl WebhookConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.webhook_url.is_none() && self.webhook_url_file.is_none() {
+            return Err("Either webhook_url or webhook_url_file must be set".to_string());
+        }
+        if reqwest::Method::from_bytes(self.method.to_uppercase().as_bytes()).is_err() {
+            return Err(format!("Invalid HTTP method: {}", self.method));
+        }
+        if let Some(body) = &self.body {
+            notifier::validate_template(body)?;
+        }
+        Ok(())
+    }
+}
+
+impl HasWebhookUrl for WebhookConfig {
+    fn webhook_url(&self) -> Option<&String> {
+        self.webhook_url.as_ref()
+    }
+
+    fn webhook_url_file(&self) -> Option<&String> {
+        self.webhook_url_file.as_ref()
+    }
+}
+
+#[derive(Clone, TS, Serialize, Deserialize, Debug)]
+#[ts(export)]
+pub struct WebserverConfig {
+    pub bind_address: Option<String>,
+    pub unix_path: Option<String>,
+    #[serde(default = "default_as_true")]
+    pub allow_config_edit: bool,
+    /// Credentials required to access the web UI and API. If unset, anyone
+    /// who can reach the webserver has full access.
+    pub auth: Option<AuthConfig>,
+    /// Number of finished tasks kept in memory for the web UI. The ones that
+    /// finished first are dropped. The history API reads them from the task
+    /// store if there is one.
+    #[serde(default = "default_history_limit")]
+    pub history_limit: usize,
+}
+
+fn default_as_true() -> bool {
+    true
+}
+
+fn default_history_limit() -> usize {
+    1000
+}
+
+impl Default for WebserverConfig {
+    fn default() -> Self {
+        WebserverConfig {
+            bind_address: None,
+            unix_path: None,
+            allow_config_edit: true,
+            auth: None,
+            history_limit: default_history_limit(),
+        }
+    }
+}
+
+#[derive(Clone, TS, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[ts(export)]
+pub struct AuthConfig {
+    /// Static bearer tokens, sent as `Authorization: Bearer <token>`
+    #[serde(default)]
+    pub token: Vec<TokenConfig>,
+    /// HTTP basic auth users
+    #[serde(default)]
+    pub user: Vec<UserConfig>,
+}
+
+impl AuthConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        for token in &self.token {
+            if token.token.is_none() && token.token_file.is_none() {
+                return Err(
+                    "Either token or token_file must be set for webserver auth tokens".to_string(),
+                );
+            }
+        }
+        for user in &self.user {
+            if user.username.is_empty() || user.username.contains(':') {
+                return Err(format!(
+                    "Invalid username for webserver auth user: {:?}",
+                    user.username
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, TS, Serialize, Deserialize, Debug, PartialEq)]
+#[ts(export)]
+pub struct TokenConfig {
+    #[serde(skip_serializing)]
+    #[ts(skip)]
+    pub token: Option<String>,
+    pub token_file: Option<String>,
+    #[serde(default)]
+    pub scope: AuthScope,
+}
+
+impl TokenConfig {
+    /// Gets the token from either the token option or from a file,
+    /// prioritizing the file option
+    pub async fn get_token(&self) -> Result<String> {
+        if let Some(file) = &self.token_file {
+            let token = tokio::fs::read_to_string(file)
+                .await
+                .with_context(|| format!("Failed to read token from file: {}", file))?;
+            return Ok(token.trim().to_string());
+        }
+
+        self.token
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("No token configured"))
+    }
+}
+
+#[derive(Clone, TS, Serialize, Deserialize, Debug, PartialEq)]
+#[ts(export)]
+pub struct UserConfig {
+    pub username: String,
+    /// bcrypt hash of the password, e.g. generated with `htpasswd -nbB`
+    #[serde(skip_serializing)]
+    #[ts(skip)]
+    pub password_hash: String,
+    #[serde(default)]
+    pub scope: AuthScope,
+}
+
+/// The level of access granted to a set of credentials.
+#[derive(
+    Clone, Copy, TS, Serialize, Deserialize, Debug, Default, PartialEq, Eq, PartialOrd, Ord,
+)]
+#[serde(rename_all = "lowercase")]
+#[ts(export)]
+pub enum AuthScope {
+    /// Can view tasks and the web UI
+    #[default]
+    Read,
+    /// Can additionally create and cancel tasks, and view and edit the config
+    Admin,
+}
+
+#[derive(Clone, TS, Serialize, Deserialize, Debug, PartialEq)]
+#[ts(export)]
+pub struct StoreConfig {
+    /// Path to the file where tasks and their status history are stored.
+    pub path: String,
+}
+
+#[derive(Clone, TS, Serialize, Deserialize, Debug, PartialEq)]
+#[ts(export)]
+pub struct HookConfig {
+    /// The program to run and its arguments. It's not run through a shell.
+    pub command: Vec<String>,
+    /// Task events that trigger the hook.
+    pub on: Vec<TaskStatus>,
+    /// How long the command can run before it's killed.
+    #[serde(with = "humantime_serde")]
+    #[serde(default = "default_hook_timeout")]
+    #[ts(type = "string")]
+    pub timeout: std::time::Duration,
+}
+
+fn default_hook_timeout() -> std::time::Duration {
+    std::time::Duration::from_secs(60 * 5)
+}
+
+impl HookConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.command.is_empty() {
+            return Err("command must not be empty for hooks".to_string());
+        }
+        // Hook failures are reported as warnings, so a hook that runs on
+        // warnings could trigger itself forever
+        if self.on.contains(&TaskStatus::Warning) {
+            return Err("Hooks cannot run on warnings".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, TS, Serialize, Deserialize, Debug)]
+#[ts(export)]
+pub struct ChannelConfig {
+    pub id: String,
+    pub name: String,
+    /// Name of the group to inherit settings from
+    pub group: Option<String>,
+    /// Name of the filter set to use if the channel doesn't set its own
+    /// filters
+    pub filter_set: Option<String>,
+    /// Regexes matched against the title. A video matches if any of them
+    /// does. If `filter` is set as well, both need to match.
+    #[serde(with = "optional_regexes")]
+    #[serde(default)]
+    #[ts(type = "string[] | null")]
+    pub filters: Option<Vec<regex::Regex>>,
+    pub match_description: Option<bool>,
+    pub filter: Option<ChannelFilterConfig>,
+    pub outpath: Option<String>,
+    /// Overrides the ytarchive quality
+    pub quality: Option<String>,
+    /// Replaces the ytarchive args
+    pub args: Option<Vec<String>>,
+    /// Appended to the ytarchive args, e.g. for cookies
+    pub extra_args: Option<Vec<String>>,
+    /// Overrides the ytarchive working directory
+    pub working_directory: Option<String>,
+    /// Overrides the backends to record with
+    pub backends: Option<Vec<BackendKind>>,
+    /// Overrides the yt-dlp format
+    pub ytdlp_format: Option<String>,
+    /// Appended to the yt-dlp args, e.g. for cookies
+    pub ytdlp_extra_args: Option<Vec<String>>,
+    /// If not present, will be fetched during runtime.
+    pub picture_url: Option<String>,
+    /// Tasks from channels with a higher priority leave the recorder queue
+    /// first.
+    #[serde(default)]
+    pub priority: i32,
+}
+
+impl Default for ChannelConfig {
+    fn default() -> Self {
+        ChannelConfig {
+            id: String::default(),
+            name: String::default(),
+            group: Option::default(),
+            filter_set: Option::default(),
+            filters: Option::default(),
+            match_description: Option::default(),
+            filter: Option::default(),
+            outpath: Option::default(),
+            quality: Option::default(),
+            args: Option::default(),
+            extra_args: Option::default(),
+            working_directory: Option::default(),
+            backends: Option::default(),
+            ytdlp_format: Option::default(),
+            ytdlp_extra_args: Option::default(),
+            picture_url: Option::default(),
+            priority: i32::default(),
+        }
+    }
+}
+
+/// Settings shared by the channels of a group. Channels can override any of
+/// them.
+#[derive(Clone, TS, Serialize, Deserialize, Debug, Default)]
+#[ts(export)]
+pub struct GroupConfig {
+    pub filter_set: Option<String>,
+    #[serde(with = "optional_regexes")]
+    #[serde(default)]
+    #[ts(type = "string[] | null")]
+    pub filters: Option<Vec<regex::Regex>>,
+    pub match_description: Option<bool>,
+    pub filter: Option<ChannelFilterConfig>,
+    pub outpath: Option<String>,
+    pub quality: Option<String>,
+    pub args: Option<Vec<String>>,
+    pub extra_args: Option<Vec<String>>,
+    pub working_directory: Option<String>,
+    pub backends: Option<Vec<BackendKind>>,
+    pub ytdlp_format: Option<String>,
+    pub ytdlp_extra_args: Option<Vec<String>>,
+}
+
+#[derive(Clone, TS, Serialize, Deserialize, Debug, Default)]
+#[ts(export)]
+pub struct FilterSetConfig {
+    #[serde(with = "optional_regexes")]
+    #[serde(default)]
+    #[ts(type = "string[] | null")]
+    pub filters: Option<Vec<regex::Regex>>,
+    pub match_description: Option<bool>,
+    pub filter: Option<ChannelFilterConfig>,
+}
+
+/// serde_regex can deserialize an optional list of regexes, but not serialize
+/// it.
+mod optional_regexes {
+    use regex::Regex;
+    use serde::{Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        value: &Option<Vec<Regex>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        value
+            .as_ref()
+            .map(|regexes| regexes.iter().map(Regex::as_str).collect::<Vec<_>>())
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Vec<Regex>>, D::Error> {
+        serde_regex::deserialize(deserializer)
+    }
+}
+
+/// The settings of a channel after applying its group and filter sets
+#[derive(Clone, Debug, Default)]
+pub struct ResolvedChannelConfig {
+    pub id: String,
+    pub name: String,
+    pub filters: Vec<regex::Regex>,
+    pub match_description: bool,
+    pub filter: Option<ChannelFilterConfig>,
+    pub outpath: String,
+    /// Overrides for the ytarchive config, carried on the channel's tasks
+    pub overrides: TaskOverrides,
+    pub picture_url: Option<String>,
+}
+
+impl ResolvedChannelConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        crate::module::recorder::outpath::validate(&self.outpath)
+            .map_err(|e| format!("Invalid outpath for channel {}: {}", self.name, e))?;
+        if self.overrides.backends.as_ref().is_some_and(Vec::is_empty) {
+            return Err(format!("No backends for channel {}", self.name));
+        }
+        if let Some(schedule) = self.filter.as_ref().and_then(|f| f.schedule.as_ref()) {
+            schedule
+                .validate()
+                .map_err(|e| format!("Invalid schedule for channel {}: {}", self.name, e))?;
+        }
+        Ok(())
+    }
+}
+
+impl Config {
+    /// Resolves the settings of a channel. Each setting is taken from the
+    /// first place that sets it: the channel, its filter set, its group, and
+    /// then the group's filter set.
+    pub fn resolve_channel(
+        &self,
+        channel: &ChannelConfig,
+    ) -> Result<ResolvedChannelConfig, String> {
+        let group = channel
+            .group
+            .as_ref()
+            .map(|name| {
+                self.group
+                    .get(name)
+                    .ok_or_else(|| format!("Unknown group {} for channel {}", name, channel.name))
+            })
+            .transpose()?;
+        let filter_set = |name: Option<&String>| {
+            name.map(|name| {
+                self.filter_set.get(name).ok_or_else(|| {
+                    format!("Unknown filter set {} for channel {}", name, channel.name)
+                })
+            })
+            .transpose()
+        };
+        let channel_set = filter_set(channel.filter_set.as_ref())?;
+        let group_set = filter_set(group.and_then(|g| g.filter_set.as_ref()))?;
+
+        // Filter settings, in order of precedence
+        let layers = [
+            Some((&channel.filters, channel.match_description, &channel.filter)),
+            channel_set.map(|s| (&s.filters, s.match_description, &s.filter)),
+            group.map(|g| (&g.filters, g.match_description, &g.filter)),
+            group_set.map(|s| (&s.filters, s.match_description, &s.filter)),
+        ];
+        let layers = layers.iter().flatten();
+
+        Ok(ResolvedChannelConfig {
+            id: channel.id.clone(),
+            name: channel.name.clone(),
+            filters: layers
+                .clone()
+                .find_map(|&(filters, _, _)| filters.clone())
+                .unwrap_or_default(),
+            match_description: layers
+                .clone()
+                .find_map(|&(_, match_description, _)| match_description)
+                .unwrap_or_default(),
+            filter: layers.clone().find_map(|&(_, _, filter)| filter.clone()),
+            outpath: channel
+                .outpath
+                .clone()
+                .or_else(|| group.and_then(|g| g.outpath.clone()))
+                .ok_or_else(|| format!("No outpath for channel {}", channel.name))?,
+            overrides: TaskOverrides {
+                quality: channel
+                    .quality
+                    .clone()
+                    .or_else(|| group.and_then(|g| g.quality.clone())),
+                args: channel
+                    .args
+                    .clone()
+                    .or_else(|| group.and_then(|g| g.args.clone())),
+                extra_args: channel
+                    .extra_args
+                    .clone()
+                    .or_else(|| group.and_then(|g| g.extra_args.clone()))
+                    .unwrap_or_default(),
+                working_directory: channel
+                    .working_directory
+                    .clone()
+                    .or_else(|| group.and_then(|g| g.working_directory.clone())),
+                backends: channel
+                    .backends
+                    .clone()
+                    .or_else(|| group.and_then(|g| g.backends.clone())),
+                ytdlp_format: channel
+                    .ytdlp_format
+                    .clone()
+                    .or_else(|| group.and_then(|g| g.ytdlp_format.clone())),
+                ytdlp_extra_args: channel
+                    .ytdlp_extra_args
+                    .clone()
+                    .or_else(|| group.and_then(|g| g.ytdlp_extra_args.clone()))
+                    .unwrap_or_default(),
+            },
+            picture_url: channel.picture_url.clone(),
+        })
     }
 
-    fn webhook_url_file(&self) -> Option<&String> {
-        self.webhook_url_file.as_ref()
+    /// Returns the resolved settings of every channel. Channels that can't be
+    /// resolved are skipped, though they are rejected when loading the
+    /// config.
+    pub fn channels(&self) -> Vec<ResolvedChannelConfig> {
+        self.channel
+            .iter()
+            .filter_map(|channel| {
+                self.resolve_channel(channel)
+                    .map_err(|e| error!("{}", e))
+                    .ok()
+            })
+            .collect()
     }
 }
 
-#[derive(Clone, TS, Serialize, Deserialize, Debug)]
+/// Structured filter of a channel. A video matches if it matches any of the
+/// include rules (or there are none), none of the exclude rules, and the
+/// schedule.
+#[derive(Clone, TS, Serialize, Deserialize, Debug, Default)]
 #[ts(export)]
-pub struct WebserverConfig {
-    pub bind_address: Option<String>,
-    pub unix_path: Option<String>,
-    #[serde(default = "default_as_true")]
-    pub allow_config_edit: bool,
+pub struct ChannelFilterConfig {
+    #[serde(default)]
+    pub include: Vec<FilterRule>,
+    #[serde(default)]
+    pub exclude: Vec<FilterRule>,
+    pub schedule: Option<ScheduleConfig>,
 }
 
-fn default_as_true() -> bool {
-    true
+#[derive(Clone, TS, Serialize, Deserialize, Debug)]
+#[ts(export)]
+#[serde(untagged)]
+pub enum FilterRule {
+    /// Matches if all of the rules match
+    All { all: Vec<FilterRule> },
+    /// Matches if any of the rules match
+    Any { any: Vec<FilterRule> },
+    /// Matches if the pattern matches the field
+    Match {
+        #[serde(default)]
+        field: FilterField,
+        #[serde(with = "serde_regex")]
+        #[ts(type = "string")]
+        pattern: regex::Regex,
+    },
 }
 
-impl Default for WebserverConfig {
-    fn default() -> Self {
-        WebserverConfig {
-            bind_address: None,
-            unix_path: None,
-            allow_config_edit: true,
-        }
-    }
+#[derive(Clone, Copy, TS, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[ts(export)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterField {
+    #[default]
+    Title,
+    Description,
+    /// Matches if any of the tags of the video matches
+    Tag,
 }
 
-#[derive(Clone, TS, Serialize, Deserialize, Debug)]
+/// Restricts recordings to streams scheduled to start in a time window.
+/// Times and days are in the time zone given by `utc_offset`.
+#[derive(Clone, TS, Serialize, Deserialize, Debug, Default, PartialEq)]
 #[ts(export)]
-pub struct ChannelConfig {
-    pub id: String,
-    pub name: String,
-    #[serde(with = "serde_regex")]
-    #[ts(type = "string[]")]
-    pub filters: Vec<regex::Regex>,
+pub struct ScheduleConfig {
+    /// Such as "+09:00". UTC if not present.
+    pub utc_offset: Option<String>,
+    /// Earliest start time, such as "18:00"
+    pub after: Option<String>,
+    /// Latest start time, such as "02:00". If it is before `after`, the
+    /// window wraps around midnight, and counts as part of the day it starts.
+    pub before: Option<String>,
+    /// Days the stream may start on, such as ["sat", "sun"]. Any day if
+    /// empty.
     #[serde(default)]
-    pub match_description: bool,
-    pub outpath: String,
-    /// If not present, will be fetched during runtime.
-    pub picture_url: Option<String>,
+    #[ts(type = "string[]")]
+    pub days: Vec<chrono::Weekday>,
 }
 
-impl Default for ChannelConfig {
-    fn default() -> Self {
-        ChannelConfig {
-            id: String::default(),
-            name: String::default(),
-            filters: Vec::default(),
-            match_description: bool::default(),
-            outpath: String::default(),
-            picture_url: Option::default(),
+impl ScheduleConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        self.utc_offset()?;
+        self.after()?;
+        self.before()?;
+        Ok(())
+    }
+
+    pub fn utc_offset(&self) -> Result<chrono::FixedOffset, String> {
+        match &self.utc_offset {
+            Some(offset) => offset
+                .parse()
+                .map_err(|_| format!("Invalid UTC offset: {}", offset)),
+            None => Ok(chrono::FixedOffset::east_opt(0).expect("UTC is a valid offset")),
         }
     }
+
+    pub fn after(&self) -> Result<Option<chrono::NaiveTime>, String> {
+        parse_time(self.after.as_deref())
+    }
+
+    pub fn before(&self) -> Result<Option<chrono::NaiveTime>, String> {
+        parse_time(self.before.as_deref())
+    }
+}
+
+fn parse_time(time: Option<&str>) -> Result<Option<chrono::NaiveTime>, String> {
+    time.map(|time| {
+        chrono::NaiveTime::parse_from_str(time, "%H:%M")
+            .map_err(|_| format!("Invalid time, expected HH:MM: {}", time))
+    })
+    .transpose()
 }
 
 pub async fn load_config(path: &str) -> Result<Config> {
     let config = tokio::fs::read_to_string(path).await?;
     let mut config: Config = toml::from_str(&config)?;
     config.config_path = path.to_string();
-
-    if let Some(notifier) = &config.notifier {
-        notifier.validate().map_err(|e| anyhow::anyhow!(e))?;
-    }
-
+    config.validate().map_err(|e| anyhow::anyhow!(e))?;
     Ok(config)
 }
 
 impl Config {
+    /// Checks the parts of the config that can't be checked while
+    /// deserializing, e.g. the channels' groups and outpath templates.
+    fn validate(&self) -> Result<(), String> {
+        if let Some(notifier) = &self.notifier {
+            notifier.validate()?;
+        }
+
+        if let Some(auth) = self.webserver.as_ref().and_then(|ws| ws.auth.as_ref()) {
+            auth.validate()?;
+        }
+
+        for hook in &self.hook {
+            hook.validate()?;
+        }
+
+        self.ytarchive.disk_space.validate()?;
+
+        if self.ytarchive.backends.is_empty() {
+            return Err("At least one backend is needed in ytarchive.backends".into());
+        }
+
+        if let Some(janitor) = &self.ytarchive.janitor {
+            janitor.validate()?;
+        }
+
+        self.ytarchive.task_log.validate()?;
+
+        for channel in &self.channel {
+            self.resolve_channel(channel)
+                .and_then(|channel| channel.validate())?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the path the config was loaded from, or an empty string if it
+    /// wasn't loaded from a file.
+    pub fn config_path(&self) -> &str {
+        &self.config_path
+    }
+
     /// Reads the config file and replaces the current config with the new one.
     pub async fn reload(&mut self) -> Result<()> {
         info!("Reloading config");
@@ -265,10 +1251,14 @@ impl Config {
     /// Writes the provided TOML string to the config path, and reloads the
     /// config.
     pub async fn set_source_toml(&mut self, source_toml: &str) -> Result<()> {
-        // Try to deserialize the provided TOML string. If it fails, we don't
-        // want to write it to the config file.
-        let _: Config =
+        // Try to deserialize and validate the provided TOML string. If it
+        // fails, we don't want to write it to the config file.
+        let config: Config =
             toml::from_str(source_toml).context("Failed to deserialize provided TOML")?;
+        config
+            .validate()
+            .map_err(|e| anyhow::anyhow!(e))
+            .context("Invalid config")?;
 
         // Write the provided TOML string to the config file.
         tokio::fs::write(&self.config_path, source_toml)
@@ -290,6 +1280,8 @@ mod tests {
         let config = Config::default();
         assert!(config.notifier.is_none());
         assert!(config.webserver.is_none());
+        assert!(config.store.is_none());
+        assert!(config.hook.is_empty());
         assert!(config.channel.is_empty());
         assert!(config.config_path.is_empty());
     }
@@ -302,6 +1294,12 @@ mod tests {
         assert!(yt.args.is_empty());
         assert!(yt.quality.is_empty());
         assert_eq!(yt.delay_start, Duration::default());
+        assert!(yt.max_concurrent.is_none());
+        assert_eq!(yt.retry, RetryConfig::default());
+        assert_eq!(yt.shutdown_grace_period, Duration::default());
+        assert_eq!(yt.backends, vec![BackendKind::Ytarchive]);
+        assert!(yt.should_fall_back(&YTAState::Ended));
+        assert!(!yt.should_fall_back(&YTAState::Errored));
     }
 
     #[test]
@@ -309,6 +1307,13 @@ mod tests {
         let scraper = ScraperConfig::default();
         assert_eq!(scraper.rss.poll_interval, Duration::default());
         assert_eq!(scraper.rss.ignore_older_than, Duration::default());
+        assert!(scraper.rss.scraped_path.is_none());
+        assert_eq!(
+            scraper.rss.scraped_retention,
+            Duration::from_secs(60 * 60 * 24 * 7)
+        );
+        assert!(!scraper.rss.seed_from_outpath);
+        assert!(scraper.channel_page.is_none());
     }
 
     #[test]
@@ -317,6 +1322,7 @@ mod tests {
         assert!(ws.bind_address.is_none());
         assert!(ws.unix_path.is_none());
         assert!(ws.allow_config_edit);
+        assert!(ws.auth.is_none());
     }
 
     #[test]
@@ -324,10 +1330,15 @@ mod tests {
         let ch = ChannelConfig::default();
         assert!(ch.id.is_empty());
         assert!(ch.name.is_empty());
-        assert!(ch.filters.is_empty());
-        assert!(!ch.match_description);
-        assert!(ch.outpath.is_empty());
+        assert!(ch.group.is_none());
+        assert!(ch.filter_set.is_none());
+        assert!(ch.filters.is_none());
+        assert!(ch.match_description.is_none());
+        assert!(ch.outpath.is_none());
+        assert!(ch.quality.is_none());
+        assert!(ch.args.is_none());
         assert!(ch.picture_url.is_none());
+        assert_eq!(ch.priority, 0);
     }
 
     #[test]
@@ -341,6 +1352,233 @@ mod tests {
 
         let config: YtarchiveConfig = toml::from_str(toml_str).unwrap();
         assert_eq!(config.delay_start, Duration::from_secs(1));
+        assert_eq!(config.shutdown_grace_period, Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_deserialize_retry_config() {
+        let toml_str = r#"
+            [ytarchive]
+            executable_path = "/usr/bin/ytarchive"
+            working_directory = "/tmp"
+            args = []
+            quality = "best"
+
+            [ytarchive.retry]
+            max_attempts = 3
+            backoff = "10s"
+            retry_on = ["Errored", "Interrupted"]
+        "#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+        let retry = config.ytarchive.retry;
+        assert_eq!(retry.max_attempts, 3);
+        assert_eq!(retry.backoff, Duration::from_secs(10));
+        assert_eq!(retry.max_backoff, Duration::from_secs(60 * 30));
+        assert_eq!(
+            retry.retry_on,
+            vec![YTAState::Errored, YTAState::Interrupted]
+        );
+    }
+
+    #[test]
+    fn test_deserialize_disk_space_config() {
+        let toml_str = r#"
+            [ytarchive]
+            executable_path = "/usr/bin/ytarchive"
+            working_directory = "/tmp"
+            args = []
+            quality = "best"
+
+            [ytarchive.disk_space]
+            min_free_working = "20GiB"
+            min_free_output = "1.5 TB"
+        "#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+        let disk_space = config.ytarchive.disk_space;
+        assert!(disk_space.validate().is_ok());
+        assert_eq!(disk_space.min_free_working(), Ok(Some(20 << 30)));
+        assert_eq!(disk_space.min_free_output(), Ok(Some(1_500_000_000_000)));
+        assert_eq!(disk_space.check_interval, Duration::from_secs(60));
+
+        // No space is required by default
+        let disk_space = DiskSpaceConfig::default();
+        assert_eq!(disk_space.min_free_working(), Ok(None));
+        assert_eq!(disk_space.min_free_output(), Ok(None));
+
+        assert_eq!(parse_size("1024"), Ok(1024));
+        assert_eq!(parse_size("512MiB"), Ok(512 << 20));
+        assert!(parse_size("10 parsecs").is_err());
+        assert!(parse_size("GiB").is_err());
+    }
+
+    #[test]
+    fn test_deserialize_task_log_config() {
+        let toml_str = r#"
+            [ytarchive]
+            executable_path = "/usr/bin/ytarchive"
+            working_directory = "/tmp"
+            args = []
+            quality = "best"
+
+            [ytarchive.task_log]
+            directory = "/var/log/hoshinova"
+            max_size = "1MiB"
+        "#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+        let task_log = config.ytarchive.task_log;
+        assert!(task_log.validate().is_ok());
+        assert_eq!(task_log.directory, "/var/log/hoshinova");
+        assert_eq!(task_log.max_size(), Ok(1 << 20));
+        assert_eq!(task_log.max_files, 2);
+        assert_eq!(task_log.buffer_lines, 1000);
+
+        let task_log = TaskLogConfig {
+            max_size: "0B".into(),
+            ..Default::default()
+        };
+        assert!(task_log.validate().is_err());
+    }
+
+    #[test]
+    fn test_deserialize_stall_timeout_config() {
+        let toml_str = r#"
+            [ytarchive]
+            executable_path = "/usr/bin/ytarchive"
+            working_directory = "/tmp"
+            args = []
+            quality = "best"
+
+            [ytarchive.stall_timeout]
+            recording = "5m"
+        "#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+        let stall_timeout = config.ytarchive.stall_timeout;
+        assert_eq!(
+            stall_timeout.for_state(&YTAState::Recording),
+            Some(Duration::from_secs(5 * 60))
+        );
+        assert_eq!(stall_timeout.for_state(&YTAState::Muxing), None);
+        assert_eq!(stall_timeout.for_state(&YTAState::Waiting(None)), None);
+
+        // Serializing keeps the unset timeouts empty
+        let toml = toml::to_string(&stall_timeout).unwrap();
+        let parsed: StallTimeoutConfig = toml::from_str(&toml).unwrap();
+        assert_eq!(parsed, stall_timeout);
+    }
+
+    #[test]
+    fn test_retry_config_policy() {
+        let retry = RetryConfig {
+            max_attempts: 3,
+            backoff: Duration::from_secs(10),
+            max_backoff: Duration::from_secs(30),
+            retry_on: vec![YTAState::Errored],
+        };
+
+        assert!(retry.should_retry(&YTAState::Errored, 1));
+        assert!(retry.should_retry(&YTAState::Errored, 2));
+        assert!(!retry.should_retry(&YTAState::Errored, 3));
+        assert!(!retry.should_retry(&YTAState::Interrupted, 1));
+        assert!(!retry.should_retry(&YTAState::Finished, 1));
+
+        assert_eq!(retry.backoff(1), Duration::from_secs(10));
+        assert_eq!(retry.backoff(2), Duration::from_secs(20));
+        assert_eq!(retry.backoff(3), Duration::from_secs(30));
+        assert_eq!(retry.backoff(100), Duration::from_secs(30));
+
+        // Retries are disabled by default
+        assert!(!RetryConfig::default().should_retry(&YTAState::Errored, 1));
+    }
+
+    #[test]
+    fn test_deserialize_scraper_channel_page() {
+        let config: ScraperConfig = toml::from_str("").unwrap();
+        assert!(config.channel_page.is_none());
+
+        let toml_str = r#"
+            [channel_page]
+            scraped_path = "./channel_page.json"
+        "#;
+        let config: ScraperConfig = toml::from_str(toml_str).unwrap();
+        let channel_page = config.channel_page.unwrap();
+        assert_eq!(channel_page.poll_interval, Duration::from_secs(5 * 60));
+        assert_eq!(
+            channel_page.scraped_path.as_deref(),
+            Some("./channel_page.json")
+        );
+        assert_eq!(
+            channel_page.scraped_retention,
+            Duration::from_secs(60 * 60 * 24 * 7)
+        );
+    }
+
+    #[test]
+    fn test_deserialize_channel_filter() {
+        let toml_str = r#"
+            id = "UCxxxxxxxxxxxxxxxxxxxxxx"
+            name = "Channel"
+            outpath = "./videos"
+
+            [filter]
+            include = [
+                { pattern = "(?i)karaoke" },
+                { all = [
+                    { field = "title", pattern = "(?i)unarchived" },
+                    { any = [{ field = "tag", pattern = "(?i)singing" }] },
+                ] },
+            ]
+            exclude = [{ field = "description", pattern = "(?i)#shorts" }]
+
+            [filter.schedule]
+            utc_offset = "+09:00"
+            after = "18:00"
+            before = "02:00"
+            days = ["sat", "sun"]
+        "#;
+
+        let channel: ChannelConfig = toml::from_str(toml_str).unwrap();
+        assert!(channel.filters.is_none());
+        Config::default()
+            .resolve_channel(&channel)
+            .unwrap()
+            .validate()
+            .unwrap();
+
+        let filter = channel.filter.unwrap();
+        assert!(matches!(
+            &filter.include[0],
+            FilterRule::Match { field: FilterField::Title, pattern } if pattern.as_str() == "(?i)karaoke"
+        ));
+        assert!(matches!(&filter.include[1], FilterRule::All { all } if all.len() == 2));
+        assert!(matches!(
+            &filter.exclude[0],
+            FilterRule::Match {
+                field: FilterField::Description,
+                ..
+            }
+        ));
+
+        let schedule = filter.schedule.unwrap();
+        assert_eq!(schedule.utc_offset().unwrap().local_minus_utc(), 9 * 3600);
+        assert_eq!(
+            schedule.days,
+            vec![chrono::Weekday::Sat, chrono::Weekday::Sun]
+        );
+
+        let invalid = ScheduleConfig {
+            after: Some("25:00".into()),
+            ..ScheduleConfig::default()
+        };
+        assert!(invalid.validate().is_err());
+        let invalid = ScheduleConfig {
+            utc_offset: Some("JST".into()),
+            ..ScheduleConfig::default()
+        };
+        assert!(invalid.validate().is_err());
     }
 
     #[test]
@@ -351,6 +1589,12 @@ mod tests {
 
         let config: ScraperRSSConfig = toml::from_str(toml_str).unwrap();
         assert_eq!(config.ignore_older_than, Duration::from_secs(60 * 60 * 24));
+        assert_eq!(
+            config.scraped_retention,
+            Duration::from_secs(60 * 60 * 24 * 7)
+        );
+        assert!(config.scraped_path.is_none());
+        assert!(!config.seed_from_outpath);
     }
 
     #[test]
@@ -477,6 +1721,49 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_deserialize_webhook_config() {
+        let toml_str = r#"
+            [notifier.webhook.tooling]
+            webhook_url = "https://example.com/hook"
+            method = "put"
+            headers = { Authorization = "Bearer abc" }
+            body = '{"title": "{{task.title}}"}'
+            notify_on = ["done"]
+
+            [notifier.webhook.other]
+            webhook_url_file = "/path/to/webhook.txt"
+            notify_on = ["failed"]
+        "#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+        let notifier = config.notifier.unwrap();
+        assert!(notifier.validate().is_ok());
+        assert_eq!(notifier.webhook.len(), 2);
+
+        let tooling = &notifier.webhook["tooling"];
+        assert_eq!(tooling.method, "put");
+        assert_eq!(tooling.headers["Authorization"], "Bearer abc");
+        assert_eq!(
+            tooling.body,
+            Some(r#"{"title": "{{task.title}}"}"#.to_string())
+        );
+        assert_eq!(tooling.notify_on, vec![TaskStatus::Done]);
+
+        let other = &notifier.webhook["other"];
+        assert_eq!(other.method, "POST");
+        assert!(other.headers.is_empty());
+        assert!(other.body.is_none());
+
+        let mut invalid = other.clone();
+        invalid.method = "NOT A METHOD".to_string();
+        assert!(invalid.validate().is_err());
+
+        let mut invalid = tooling.clone();
+        invalid.body = Some(r#"{"title": "{{task.title"}"#.to_string());
+        assert!(invalid.validate().is_err());
+    }
+
     #[test]
     fn test_deserialize_channel_config() {
         let toml_str = r#"
@@ -487,6 +1774,7 @@ mod tests {
             match_description = true
             outpath = "./downloads1"
             picture_url = "http://example.com/pic1.jpg"
+            priority = 10
 
             [[channel]]
             id = "456"
@@ -496,8 +1784,9 @@ mod tests {
         "#;
 
         let config: Config = toml::from_str(toml_str).unwrap();
+        let channels = config.channels();
 
-        let channel1 = &config.channel[0];
+        let channel1 = &channels[0];
         assert_eq!(channel1.id, "123");
         assert_eq!(channel1.name, "Test Channel 1");
         assert_eq!(channel1.filters.len(), 2);
@@ -510,7 +1799,7 @@ mod tests {
             Some("http://example.com/pic1.jpg".to_string())
         );
 
-        let channel2 = &config.channel[1];
+        let channel2 = &channels[1];
         assert_eq!(channel2.id, "456");
         assert_eq!(channel2.name, "Test Channel 2");
         assert_eq!(channel2.filters.len(), 1);
@@ -518,6 +1807,111 @@ mod tests {
         assert_eq!(channel2.outpath, "./downloads2");
         assert_eq!(channel2.picture_url, None);
         assert_eq!(channel2.match_description, false);
+        assert_eq!(config.channel[0].priority, 10);
+        assert_eq!(config.channel[1].priority, 0);
+    }
+
+    #[test]
+    fn test_resolve_channel() {
+        let toml_str = r#"
+            [ytarchive]
+            executable_path = "ytarchive"
+            working_directory = "temp"
+            args = ["--vp9"]
+            quality = "best"
+
+            [filter_set.karaoke]
+            filters = ["(?i)karaoke"]
+            match_description = true
+
+            [filter_set.everything]
+            filters = [".*"]
+
+            [group.hololive]
+            filter_set = "karaoke"
+            outpath = "./videos/hololive"
+            args = ["--cookies", "cookies.txt"]
+            ytdlp_extra_args = ["--cookies", "cookies.txt"]
+
+            [[channel]]
+            id = "1"
+            name = "Inherits everything"
+            group = "hololive"
+
+            [[channel]]
+            id = "2"
+            name = "Overrides some"
+            group = "hololive"
+            filter_set = "everything"
+            outpath = "./videos/other"
+            quality = "audio_only"
+            extra_args = ["--add-metadata"]
+            working_directory = "/mnt/temp"
+            backends = ["ytarchive", "yt-dlp"]
+            ytdlp_format = "bestaudio"
+
+            [[channel]]
+            id = "3"
+            name = "No group"
+            filters = ["(?i)unarchived"]
+            outpath = "./videos/none"
+
+            [[channel]]
+            id = "4"
+            name = "Unknown group"
+            group = "nope"
+        "#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+
+        let channel = config.resolve_channel(&config.channel[0]).unwrap();
+        assert_eq!(channel.filters[0].as_str(), "(?i)karaoke");
+        assert!(channel.match_description);
+        assert_eq!(channel.outpath, "./videos/hololive");
+        assert_eq!(
+            channel.overrides,
+            TaskOverrides {
+                args: Some(vec!["--cookies".into(), "cookies.txt".into()]),
+                ytdlp_extra_args: vec!["--cookies".into(), "cookies.txt".into()],
+                ..TaskOverrides::default()
+            }
+        );
+
+        // The channel's own filter set takes precedence over the group's, and
+        // settings it doesn't have still come from the group's filter set
+        let channel = config.resolve_channel(&config.channel[1]).unwrap();
+        assert_eq!(channel.filters[0].as_str(), ".*");
+        assert!(channel.match_description);
+        assert_eq!(channel.outpath, "./videos/other");
+        assert_eq!(
+            channel.overrides,
+            TaskOverrides {
+                quality: Some("audio_only".into()),
+                args: Some(vec!["--cookies".into(), "cookies.txt".into()]),
+                extra_args: vec!["--add-metadata".into()],
+                working_directory: Some("/mnt/temp".into()),
+                backends: Some(vec![BackendKind::Ytarchive, BackendKind::Ytdlp]),
+                ytdlp_format: Some("bestaudio".into()),
+                ytdlp_extra_args: vec!["--cookies".into(), "cookies.txt".into()],
+            }
+        );
+
+        let channel = config.resolve_channel(&config.channel[2]).unwrap();
+        assert_eq!(channel.filters[0].as_str(), "(?i)unarchived");
+        assert!(!channel.match_description);
+        assert_eq!(channel.overrides, TaskOverrides::default());
+
+        assert!(config.resolve_channel(&config.channel[3]).is_err());
+        assert_eq!(config.channels().len(), 3);
+
+        // Serializing keeps the settings that are not set empty
+        let toml = toml::to_string(&config).unwrap();
+        let config: Config = toml::from_str(&toml).unwrap();
+        assert!(config.channel[0].filters.is_none());
+        assert_eq!(
+            config.filter_set["karaoke"].filters.as_ref().unwrap()[0].as_str(),
+            "(?i)karaoke"
+        );
     }
 
     #[test]
@@ -529,6 +1923,7 @@ mod tests {
             args = ["--test"]
             quality = "high"
             delay_start = "5s"
+            max_concurrent = 4
 
             [scraper.rss]
             poll_interval = "10s"
@@ -546,11 +1941,114 @@ mod tests {
         let config: Config = toml::from_str(toml_str).unwrap();
 
         assert_eq!(config.ytarchive.delay_start, Duration::from_secs(5));
+        assert_eq!(config.ytarchive.max_concurrent, Some(4));
         assert_eq!(config.scraper.rss.poll_interval, Duration::from_secs(10));
         assert_eq!(config.webserver.unwrap().allow_config_edit, true);
         assert_eq!(config.channel.len(), 1);
-        assert_eq!(config.channel[0].match_description, false);
+        assert_eq!(config.channel[0].match_description, None);
         assert_eq!(config.notifier, None);
+        assert_eq!(config.store, None);
+    }
+
+    #[test]
+    fn test_deserialize_store_config() {
+        let toml_str = r#"
+            [store]
+            path = "./hoshinova.jsonl"
+        "#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+        assert_eq!(config.store.unwrap().path, "./hoshinova.jsonl");
+    }
+
+    #[test]
+    fn test_deserialize_hook_config() {
+        let toml_str = r#"
+            [[hook]]
+            command = ["/usr/local/bin/upload.sh", "--verbose"]
+            on = ["done"]
+
+            [[hook]]
+            command = ["sh", "-c", "echo $HOSHINOVA_VIDEO_ID >> failed.txt"]
+            on = ["failed"]
+            timeout = "10s"
+        "#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+        assert_eq!(config.hook.len(), 2);
+        assert_eq!(
+            config.hook[0].command,
+            vec!["/usr/local/bin/upload.sh", "--verbose"]
+        );
+        assert_eq!(config.hook[0].on, vec![TaskStatus::Done]);
+        assert_eq!(config.hook[0].timeout, Duration::from_secs(60 * 5));
+        assert_eq!(config.hook[1].timeout, Duration::from_secs(10));
+        assert!(config.hook.iter().all(|hook| hook.validate().is_ok()));
+
+        let mut invalid = config.hook[0].clone();
+        invalid.on.push(TaskStatus::Warning);
+        assert!(invalid.validate().is_err());
+
+        invalid.command.clear();
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn test_deserialize_auth_config() {
+        let toml_str = r#"
+            [webserver]
+            bind_address = "0.0.0.0:1104"
+
+            [[webserver.auth.token]]
+            token = "t0ps3cret"
+
+            [[webserver.auth.token]]
+            token_file = "/run/secrets/hoshinova_token"
+            scope = "admin"
+
+            [[webserver.auth.user]]
+            username = "admin"
+            password_hash = "$2y$05$abcdefghijklmnopqrstuu"
+            scope = "admin"
+        "#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+        let auth = config.webserver.unwrap().auth.unwrap();
+        assert!(auth.validate().is_ok());
+        assert_eq!(auth.token.len(), 2);
+        assert_eq!(auth.token[0].token, Some("t0ps3cret".to_string()));
+        assert_eq!(auth.token[0].scope, AuthScope::Read);
+        assert_eq!(auth.token[1].scope, AuthScope::Admin);
+        assert_eq!(auth.user[0].username, "admin");
+        assert_eq!(auth.user[0].scope, AuthScope::Admin);
+
+        // Secrets must never be sent to API clients
+        let json = serde_json::to_string(&auth).unwrap();
+        assert!(!json.contains("t0ps3cret"));
+        assert!(!json.contains("$2y$"));
+    }
+
+    #[test]
+    fn test_auth_config_validation() {
+        let cfg = AuthConfig {
+            token: vec![TokenConfig {
+                token: None,
+                token_file: None,
+                scope: AuthScope::Read,
+            }],
+            user: vec![],
+        };
+        assert!(cfg.validate().is_err());
+
+        let cfg = AuthConfig {
+            token: vec![],
+            user: vec![UserConfig {
+                username: "a:b".to_string(),
+                password_hash: String::new(),
+                scope: AuthScope::Read,
+            }],
+        };
+        assert!(cfg.validate().is_err());
     }
 
     #[tokio::test]
@@ -563,4 +2061,26 @@ mod tests {
         let result = config.reload().await;
         assert!(result.is_err());
     }
+    #[tokio::test]
+    async fn test_set_source_toml_invalid() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let original = "[ytarchive]\n";
+        std::fs::write(file.path(), original).unwrap();
+        let mut config = Config {
+            config_path: file.path().to_string_lossy().into_owned(),
+            ..Config::default()
+        };
+
+        // Parses, but the group doesn't exist
+        let invalid = r#"
+            [[channel]]
+            id = "UC"
+            name = "Channel"
+            group = "missing"
+            outpath = "./videos"
+        "#;
+        let err = config.set_source_toml(invalid).await.unwrap_err();
+        assert!(format!("{:#}", err).contains("missing"), "{:#}", err);
+        assert_eq!(std::fs::read_to_string(file.path()).unwrap(), original);
+    }
 }
@@ -1,14 +1,15 @@
 #[macro_use]
 extern crate log;
-use crate::module::Module;
+use crate::module::{recorder::BackendKind, Message, Module};
 use crate::msgbus::MessageBus;
 use anyhow::{anyhow, Result};
 use clap::Parser;
 use env_logger::Builder;
 use std::{process::Command, sync::Arc};
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, RwLock};
 
 mod config;
+mod metrics;
 mod module;
 mod msgbus;
 mod youtube;
@@ -58,14 +59,15 @@ fn test_ffmpeg() -> Result<String> {
         .join(" "))
 }
 
-fn test_ytarchive(path: &str) -> Result<String> {
+fn test_backend(name: &str, path: &str) -> Result<String> {
     let cmd = Command::new(path)
         .arg("--version")
         .output()
-        .map_err(|e| anyhow!("Failed to execute ytarchive: {}", e))?;
+        .map_err(|e| anyhow!("Failed to execute {}: {}", name, e))?;
     if !cmd.status.success() {
         return Err(anyhow!(
-            "Failed to determine ytarchive version: {}",
+            "Failed to determine {} version: {}",
+            name,
             cmd.status
         ));
     }
@@ -73,6 +75,23 @@ fn test_ytarchive(path: &str) -> Result<String> {
     Ok(stdout.trim().to_string())
 }
 
+/// Waits for SIGINT, or SIGTERM on unix.
+async fn wait_for_signal() -> Result<()> {
+    #[cfg(unix)]
+    {
+        let mut sigterm =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+        tokio::select! {
+            res = tokio::signal::ctrl_c() => res?,
+            _ = sigterm.recv() => (),
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await.map_err(|e| e.into())
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // Initialize logging
@@ -93,21 +112,33 @@ async fn main() -> Result<()> {
         .map_err(|e| anyhow!("Failed to read config file: {}", e))?;
     debug!("{:?}", config);
 
-    // Make sure ffmpeg and ytarchive are installed
+    // Make sure ffmpeg, ytarchive and the other backends in use are installed
     debug!("Found {}", test_ffmpeg()?);
     debug!(
         "Found {}",
-        test_ytarchive(&config.ytarchive.executable_path)?
+        test_backend("ytarchive", &config.ytarchive.executable_path)?
     );
+    let uses_ytdlp = config
+        .channels()
+        .iter()
+        .filter_map(|channel| channel.overrides.backends.as_ref())
+        .chain([&config.ytarchive.backends])
+        .any(|backends| backends.contains(&BackendKind::Ytdlp));
+    if uses_ytdlp {
+        debug!(
+            "Found yt-dlp {}",
+            test_backend("yt-dlp", &config.ytdlp.executable_path)?
+        );
+    }
 
     // Set up message bus
     let mut bus = MessageBus::new(65_536);
 
     // Set up modules
     macro_rules! run_module {
-        ($bus:expr, $module:expr) => {{
+        ($bus:expr, $name:expr, $module:expr) => {{
             let tx = $bus.add_tx();
-            let mut rx = $bus.add_rx();
+            let mut rx = $bus.add_rx($name);
             let module = $module;
             tokio::spawn(async move {
                 if let Err(e) = module.run(&tx, &mut rx).await {
@@ -117,24 +148,66 @@ async fn main() -> Result<()> {
         }};
     }
 
+    // Task output is streamed on its own channel, so that it can't fill the
+    // queues of the bus
+    let (task_output, _) = broadcast::channel(1024);
+
+    // The webserver lists the tasks of the store in the history
+    let stored_tasks = module::store::StoredTasks::default();
+
     let config = Arc::new(RwLock::new(config));
-    let h_scraper = run_module!(bus, module::scraper::RSS::new(config.clone()));
-    let h_recorder = run_module!(bus, module::recorder::YTArchive::new(config.clone()));
+    let h_scraper = run_module!(bus, "scraper", module::scraper::RSS::new(config.clone()));
+    let h_channel_page = run_module!(
+        bus,
+        "channel_page",
+        module::scraper::ChannelPage::new(config.clone())
+    );
+    let h_recorder = run_module!(
+        bus,
+        "recorder",
+        module::recorder::YTArchive::new(config.clone()).with_output(task_output.clone())
+    );
     let h_notifier = run_module!(
         bus,
+        "notifier",
         module::notifier::NotificationSystem::new(config.clone())
     );
-    let h_webserver = run_module!(bus, module::web::WebServer::new(config.clone()));
+    let h_webserver = run_module!(
+        bus,
+        "webserver",
+        module::web::WebServer::new(config.clone())
+            .with_output(task_output)
+            .with_stored_tasks(stored_tasks.clone())
+    );
+    let h_store = run_module!(
+        bus,
+        "store",
+        module::store::TaskStore::new(config.clone()).with_tasks(stored_tasks)
+    );
+    let h_hooks = run_module!(bus, "hooks", module::hooks::Hooks::new(config.clone()));
 
     // Listen for signals
     let closer = bus.add_tx();
     let h_signal = tokio::spawn(async move {
-        tokio::signal::ctrl_c()
+        wait_for_signal()
             .await
-            .expect("Unable to listen for SIGINT");
+            .expect("Unable to listen for signals");
 
         info!("Received signal, shutting down");
-        closer.close().await.expect("Failed to close message bus");
+        closer
+            .send(Message::Shutdown)
+            .await
+            .expect("Failed to send shutdown message");
+
+        // Close immediately on a second signal
+        tokio::select! {
+            _ = closer.closed() => (),
+            res = wait_for_signal() => {
+                res.expect("Unable to listen for signals");
+                warn!("Received another signal, shutting down immediately");
+                closer.close().await.expect("Failed to close message bus");
+            }
+        }
     });
 
     // Start message dispatcher
@@ -143,11 +216,14 @@ async fn main() -> Result<()> {
     // Wait for all tasks to finish
     futures::try_join!(
         h_scraper,
+        h_channel_page,
         h_recorder,
         h_notifier,
         h_signal,
         h_bus,
         h_webserver,
+        h_store,
+        h_hooks,
     )
     .map(|_| ())
     .map_err(|e| anyhow!("Task errored: {}", e))
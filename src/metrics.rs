@@ -0,0 +1,236 @@
+//! A minimal registry of operational metrics, exported in the Prometheus text
+//! format by the webserver.
+
+use lazy_static::lazy_static;
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{Arc, Mutex},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Counter,
+    Gauge,
+}
+
+/// Describes a metric. Its values are kept in the global registry, one for
+/// each set of labels.
+pub struct Metric {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub kind: Kind,
+}
+
+pub const TASKS: Metric = Metric {
+    name: "hoshinova_tasks",
+    help: "Number of tasks by recording state",
+    kind: Kind::Gauge,
+};
+pub const QUEUED_TASKS: Metric = Metric {
+    name: "hoshinova_queued_tasks",
+    help: "Number of tasks waiting for a free recording slot",
+    kind: Kind::Gauge,
+};
+pub const TASK_VIDEO_FRAGMENTS: Metric = Metric {
+    name: "hoshinova_task_video_fragments",
+    help: "Video fragments downloaded by each active task",
+    kind: Kind::Gauge,
+};
+pub const TASK_AUDIO_FRAGMENTS: Metric = Metric {
+    name: "hoshinova_task_audio_fragments",
+    help: "Audio fragments downloaded by each active task",
+    kind: Kind::Gauge,
+};
+pub const TASK_DOWNLOADED_BYTES: Metric = Metric {
+    name: "hoshinova_task_downloaded_bytes",
+    help: "Bytes downloaded by each active task",
+    kind: Kind::Gauge,
+};
+pub const RSS_POLLS: Metric = Metric {
+    name: "hoshinova_rss_polls_total",
+    help: "Number of times the RSS feed of each channel was fetched",
+    kind: Kind::Counter,
+};
+pub const RSS_POLL_FAILURES: Metric = Metric {
+    name: "hoshinova_rss_poll_failures_total",
+    help: "Number of times fetching the RSS feed of each channel failed",
+    kind: Kind::Counter,
+};
+pub const CHANNEL_PAGE_POLLS: Metric = Metric {
+    name: "hoshinova_channel_page_polls_total",
+    help: "Number of times the streams page of each channel was fetched",
+    kind: Kind::Counter,
+};
+pub const CHANNEL_PAGE_POLL_FAILURES: Metric = Metric {
+    name: "hoshinova_channel_page_poll_failures_total",
+    help: "Number of times fetching the streams page of each channel failed",
+    kind: Kind::Counter,
+};
+pub const NOTIFICATIONS: Metric = Metric {
+    name: "hoshinova_notifications_total",
+    help: "Number of notifications sent by each notifier, by result",
+    kind: Kind::Counter,
+};
+pub const YTARCHIVE_EXITS: Metric = Metric {
+    name: "hoshinova_ytarchive_exits_total",
+    help: "Number of times ytarchive exited, by exit code",
+    kind: Kind::Counter,
+};
+pub const BUS_QUEUE_DEPTH: Metric = Metric {
+    name: "hoshinova_bus_queue_depth",
+    help: "Number of messages waiting in each message bus queue",
+    kind: Kind::Gauge,
+};
+
+type Labels = Vec<(String, String)>;
+type Collector = Arc<dyn Fn() -> Option<f64> + Send + Sync>;
+
+enum Value {
+    Static(f64),
+    /// Computed when the metrics are rendered. Removed once it returns None.
+    Collected(Collector),
+}
+
+struct Family {
+    help: &'static str,
+    kind: Kind,
+    values: BTreeMap<Labels, Value>,
+}
+
+lazy_static! {
+    static ref REGISTRY: Mutex<BTreeMap<&'static str, Family>> = Mutex::new(BTreeMap::new());
+}
+
+impl Metric {
+    fn update(&self, labels: &[(&str, &str)], f: impl FnOnce(&mut Value)) {
+        let labels = labels
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let mut registry = REGISTRY.lock().unwrap();
+        let family = registry.entry(self.name).or_insert_with(|| Family {
+            help: self.help,
+            kind: self.kind,
+            values: BTreeMap::new(),
+        });
+        f(family.values.entry(labels).or_insert(Value::Static(0.0)));
+    }
+
+    /// Increments the value with the given labels by one.
+    pub fn inc(&self, labels: &[(&str, &str)]) {
+        self.update(labels, |value| match value {
+            Value::Static(n) => *n += 1.0,
+            Value::Collected(_) => *value = Value::Static(1.0),
+        });
+    }
+
+    /// Sets the value with the given labels.
+    pub fn set(&self, labels: &[(&str, &str)], n: f64) {
+        self.update(labels, |value| *value = Value::Static(n));
+    }
+
+    /// Sets a function that computes the value with the given labels each
+    /// time the metrics are rendered.
+    pub fn collect(
+        &self,
+        labels: &[(&str, &str)],
+        f: impl Fn() -> Option<f64> + Send + Sync + 'static,
+    ) {
+        self.update(labels, |value| *value = Value::Collected(Arc::new(f)));
+    }
+
+    /// Removes all values of the metric.
+    pub fn clear(&self) {
+        if let Some(family) = REGISTRY.lock().unwrap().get_mut(self.name) {
+            family.values.clear();
+        }
+    }
+}
+
+/// Escapes a label value for the text format.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Renders all metrics in the Prometheus text exposition format.
+pub fn render() -> String {
+    let mut registry = REGISTRY.lock().unwrap();
+    let mut out = String::new();
+    for (name, family) in registry.iter_mut() {
+        // Compute collected values, and drop the ones that are gone
+        let mut values = vec![];
+        family.values.retain(|labels, value| {
+            let n = match value {
+                Value::Static(n) => Some(*n),
+                Value::Collected(f) => f(),
+            };
+            if let Some(n) = n {
+                values.push((labels.clone(), n));
+            }
+            n.is_some()
+        });
+        if values.is_empty() {
+            continue;
+        }
+
+        let kind = match family.kind {
+            Kind::Counter => "counter",
+            Kind::Gauge => "gauge",
+        };
+        let _ = writeln!(out, "# HELP {} {}", name, family.help);
+        let _ = writeln!(out, "# TYPE {} {}", name, kind);
+        for (labels, n) in values {
+            let _ = write!(out, "{}", name);
+            if !labels.is_empty() {
+                let labels: Vec<String> = labels
+                    .iter()
+                    .map(|(k, v)| format!("{}=\"{}\"", k, escape(v)))
+                    .collect();
+                let _ = write!(out, "{{{}}}", labels.join(","));
+            }
+            let _ = writeln!(out, " {}", n);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        const TEST_COUNTER: Metric = Metric {
+            name: "test_counter_total",
+            help: "A test counter",
+            kind: Kind::Counter,
+        };
+        const TEST_GAUGE: Metric = Metric {
+            name: "test_gauge",
+            help: "A test gauge",
+            kind: Kind::Gauge,
+        };
+
+        TEST_COUNTER.inc(&[("channel", "a \"quoted\" name")]);
+        TEST_COUNTER.inc(&[("channel", "a \"quoted\" name")]);
+        TEST_COUNTER.inc(&[("channel", "b")]);
+        TEST_GAUGE.set(&[], 2.5);
+        TEST_GAUGE.collect(&[("queue", "gone")], || None);
+
+        let out = render();
+        assert!(out.contains("# HELP test_counter_total A test counter\n"));
+        assert!(out.contains("# TYPE test_counter_total counter\n"));
+        assert!(out.contains("test_counter_total{channel=\"a \\\"quoted\\\" name\"} 2\n"));
+        assert!(out.contains("test_counter_total{channel=\"b\"} 1\n"));
+        assert!(out.contains("# TYPE test_gauge gauge\n"));
+        assert!(out.contains("test_gauge 2.5\n"));
+        assert!(!out.contains("gone"));
+
+        TEST_GAUGE.clear();
+        assert!(!render().contains("test_gauge"));
+    }
+}
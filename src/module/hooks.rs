@@ -0,0 +1,247 @@
+use super::{Message, Module, Notification, TaskStatus};
+use crate::{
+    config::{Config, HookConfig},
+    msgbus::BusTx,
+};
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use std::{process::Stdio, sync::Arc};
+use tokio::{
+    io::AsyncWriteExt,
+    process::Command,
+    select,
+    sync::{mpsc, RwLock},
+    task::JoinSet,
+};
+
+/// Hooks runs the user commands configured in `[[hook]]` when a task
+/// reaches one of their events.
+pub struct Hooks {
+    config: Arc<RwLock<Config>>,
+}
+
+impl Hooks {
+    /// Environment variables describing the notification, passed to every
+    /// hook in addition to the notification as JSON on stdin.
+    fn env(notification: &Notification) -> Vec<(&'static str, String)> {
+        let task = &notification.task;
+        let mut env = vec![
+            ("HOSHINOVA_EVENT", notification.status.as_str().to_string()),
+            ("HOSHINOVA_VIDEO_ID", task.video_id.clone()),
+            ("HOSHINOVA_TITLE", task.title.clone()),
+            ("HOSHINOVA_CHANNEL_ID", task.channel_id.clone()),
+            ("HOSHINOVA_CHANNEL_NAME", task.channel_name.clone()),
+            ("HOSHINOVA_OUTPUT_DIRECTORY", task.output_directory.clone()),
+            ("HOSHINOVA_ATTEMPT", notification.attempt.to_string()),
+        ];
+        if let Some(output_file) = notification
+            .yta_status
+            .as_ref()
+            .and_then(|status| status.output_file())
+        {
+            env.push(("HOSHINOVA_OUTPUT_FILE", output_file.clone()));
+        }
+        if let Some(message) = &notification.message {
+            env.push(("HOSHINOVA_MESSAGE", message.clone()));
+        }
+        env
+    }
+
+    /// Runs a single hook to completion, and returns an error if it could not
+    /// be started, timed out or exited unsuccessfully.
+    async fn run_hook(hook: &HookConfig, notification: &Notification) -> Result<()> {
+        let task_name = format!(
+            "[{}][{}][{}]",
+            notification.task.video_id, notification.task.channel_name, notification.task.title
+        );
+        let (program, args) = hook
+            .command
+            .split_first()
+            .ok_or(anyhow!("Hook command is empty"))?;
+        let input = serde_json::to_vec(notification).context("Failed to serialize notification")?;
+
+        debug!("{} Running hook {}", task_name, program);
+        let mut child = Command::new(program)
+            .args(args)
+            .envs(Self::env(notification))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("Failed to start hook {}", program))?;
+
+        // Write the input while reading the output, in case the hook doesn't
+        // read its stdin at all
+        let stdin = child.stdin.take();
+        let write = async move {
+            if let Some(mut stdin) = stdin {
+                if let Err(e) = stdin.write_all(&input).await {
+                    debug!("Failed to write hook input: {}", e);
+                }
+            }
+        };
+
+        // Dropping the child on timeout kills it
+        let (output, _) = tokio::time::timeout(
+            hook.timeout,
+            futures::future::join(child.wait_with_output(), write),
+        )
+        .await
+        .map_err(|_| {
+            anyhow!(
+                "Hook {} timed out after {}",
+                program,
+                humantime::format_duration(hook.timeout)
+            )
+        })?;
+        let output = output.with_context(|| format!("Failed to wait for hook {}", program))?;
+
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            info!("{}[hook:out] {}", task_name, line);
+        }
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        for line in stderr.lines() {
+            warn!("{}[hook:err] {}", task_name, line);
+        }
+
+        if !output.status.success() {
+            return Err(match stderr.lines().last() {
+                Some(line) => anyhow!("Hook {} {}: {}", program, output.status, line),
+                None => anyhow!("Hook {} {}", program, output.status),
+            });
+        }
+
+        debug!("{} Hook {} finished", task_name, program);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Module for Hooks {
+    fn new(config: Arc<RwLock<Config>>) -> Self {
+        Self { config }
+    }
+
+    async fn run(&self, tx: &BusTx<Message>, rx: &mut mpsc::Receiver<Message>) -> Result<()> {
+        let mut running = JoinSet::new();
+
+        loop {
+            let msg = select! {
+                msg = rx.recv() => msg,
+                Some(_) = running.join_next(), if !running.is_empty() => continue,
+            };
+            let notification = match msg {
+                Some(Message::ToNotify(notification)) => notification,
+                Some(_) => continue,
+                None => break,
+            };
+
+            // Hook failures are reported as warnings themselves
+            if notification.status == TaskStatus::Warning {
+                continue;
+            }
+
+            let hooks = self.config.read().await.hook.clone();
+            for hook in hooks {
+                if !hook.on.contains(&notification.status) {
+                    continue;
+                }
+
+                let notification = notification.clone();
+                let tx = tx.clone();
+                running.spawn(async move {
+                    if let Err(e) = Self::run_hook(&hook, &notification).await {
+                        error!(
+                            "[{}][{}][{}] {:#}",
+                            notification.task.video_id,
+                            notification.task.channel_name,
+                            notification.task.title,
+                            e
+                        );
+                        let _ = tx
+                            .send(Message::ToNotify(Notification {
+                                status: TaskStatus::Warning,
+                                message: Some(format!("{:#}", e)),
+                                ..notification
+                            }))
+                            .await;
+                    }
+                });
+            }
+        }
+
+        // Let running hooks finish before exiting
+        while running.join_next().await.is_some() {}
+
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::module::{recorder::YTAStatus, Task};
+    use std::time::Duration;
+    use tempfile::NamedTempFile;
+
+    fn notification() -> Notification {
+        Notification {
+            task: Task::test("IKKar5SS29E"),
+            status: TaskStatus::Done,
+            attempt: 1,
+            yta_status: Some(YTAStatus::new()),
+            message: None,
+        }
+    }
+
+    fn hook(script: &str, timeout: Duration) -> HookConfig {
+        HookConfig {
+            command: vec!["sh".into(), "-c".into(), script.into()],
+            on: vec![TaskStatus::Done],
+            timeout,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_hook() {
+        let file = NamedTempFile::new().unwrap();
+        let script = format!(
+            "echo $HOSHINOVA_EVENT $HOSHINOVA_VIDEO_ID > {0} && cat >> {0}",
+            file.path().display()
+        );
+        Hooks::run_hook(&hook(&script, Duration::from_secs(10)), &notification())
+            .await
+            .unwrap();
+
+        let output = std::fs::read_to_string(file.path()).unwrap();
+        let (env, stdin) = output.split_once('\n').unwrap();
+        assert_eq!(env, "done IKKar5SS29E");
+        let stdin: serde_json::Value = serde_json::from_str(stdin).unwrap();
+        assert_eq!(stdin["task"]["title"], "Title");
+        assert_eq!(stdin["status"], "done");
+    }
+
+    #[tokio::test]
+    async fn test_run_hook_failure() {
+        let err = Hooks::run_hook(
+            &hook("echo oops >&2; exit 3", Duration::from_secs(10)),
+            &notification(),
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("oops"));
+
+        let err = Hooks::run_hook(
+            &hook("sleep 10", Duration::from_millis(100)),
+            &notification(),
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("timed out"));
+
+        let mut missing = hook("", Duration::from_secs(10));
+        missing.command = vec!["/nonexistent/hook".into()];
+        assert!(Hooks::run_hook(&missing, &notification()).await.is_err());
+    }
+}
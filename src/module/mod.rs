@@ -1,4 +1,4 @@
-use self::recorder::YTAStatus;
+use self::recorder::{BackendKind, YTAStatus};
 use crate::{config::Config, msgbus::BusTx};
 use anyhow::Result;
 use async_trait::async_trait;
@@ -7,9 +7,11 @@ use std::{fmt::Debug, sync::Arc};
 use tokio::sync::{mpsc, RwLock};
 use ts_rs::TS;
 
+pub mod hooks;
 pub mod notifier;
 pub mod recorder;
 pub mod scraper;
+pub mod store;
 pub mod web;
 
 #[derive(Debug, Clone, TS)]
@@ -18,6 +20,12 @@ pub enum Message {
     ToRecord(Task),
     ToNotify(Notification),
     RecordingStatus(RecordingStatus),
+    QueueStatus(QueueStatus),
+    /// Stops a queued or running recording, identified by its video ID.
+    CancelTask(String),
+    /// Asks the modules to shut down. The recorder closes the bus once the
+    /// running recordings have been stopped.
+    Shutdown,
 }
 
 #[derive(Debug, Clone, TS, Serialize, Deserialize)]
@@ -30,22 +38,90 @@ pub struct Task {
     pub channel_id: String,
     pub channel_picture: Option<String>,
     pub output_directory: String,
+    #[serde(default)]
+    pub overrides: TaskOverrides,
 }
 
-#[derive(Debug, Clone, TS)]
+#[cfg(test)]
+impl Task {
+    /// A task for use in tests.
+    pub fn test(video_id: &str) -> Self {
+        Self {
+            title: "Title".into(),
+            video_id: video_id.into(),
+            video_picture: "".into(),
+            channel_name: "Channel".into(),
+            channel_id: "UC".into(),
+            channel_picture: None,
+            output_directory: "./videos".into(),
+            overrides: TaskOverrides::default(),
+        }
+    }
+}
+
+/// Settings that override the global ytarchive config for a single task
+#[derive(Debug, Clone, Default, PartialEq, TS, Serialize, Deserialize)]
+#[ts(export)]
+pub struct TaskOverrides {
+    pub quality: Option<String>,
+    /// Replaces the ytarchive args
+    pub args: Option<Vec<String>>,
+    /// Appended to the ytarchive args
+    #[serde(default)]
+    pub extra_args: Vec<String>,
+    pub working_directory: Option<String>,
+    /// Replaces the backends to record with
+    pub backends: Option<Vec<BackendKind>>,
+    /// Replaces the yt-dlp format
+    pub ytdlp_format: Option<String>,
+    /// Appended to the yt-dlp args
+    #[serde(default)]
+    pub ytdlp_extra_args: Vec<String>,
+}
+
+#[derive(Debug, Clone, TS, Serialize)]
 #[ts(export)]
 pub struct Notification {
     pub task: Task,
     pub status: TaskStatus,
+    /// The recording attempt this notification is about, starting from 1.
+    pub attempt: u32,
+    /// The latest status reported by ytarchive, if any.
+    pub yta_status: Option<YTAStatus>,
+    /// Additional details, e.g. why the task failed.
+    pub message: Option<String>,
 }
 
-#[derive(Debug, Clone, TS)]
+#[derive(Debug, Clone, TS, Serialize)]
 #[ts(export)]
 pub struct RecordingStatus {
     pub task: Task,
     pub status: YTAStatus,
 }
 
+/// A line of output of a task's recording process, or a note from the
+/// recorder about it, prefixed with the time it was written. Output is
+/// streamed on its own channel rather than the bus, so that a busy process
+/// can't fill the queues of the other modules.
+#[derive(Debug, Clone, TS, Serialize)]
+#[ts(export)]
+pub struct TaskOutput {
+    pub video_id: String,
+    pub line: String,
+}
+
+/// The position of a task in the recorder queue.
+#[derive(Debug, Clone, TS)]
+#[ts(export)]
+pub struct QueueStatus {
+    pub task: Task,
+    /// Number of tasks ahead of this one, or None if it has left the queue.
+    pub position: Option<usize>,
+    /// Why the task can't start yet even if there is a free slot, e.g.
+    /// because of low disk space.
+    pub held_reason: Option<String>,
+}
+
 #[derive(Debug, Clone, PartialEq, TS)]
 #[ts(export)]
 pub enum TaskStatus {
@@ -53,6 +129,21 @@ pub enum TaskStatus {
     Recording,
     Done,
     Failed,
+    /// Something went wrong outside of the recording itself, e.g. a hook
+    /// failed.
+    Warning,
+}
+
+impl TaskStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TaskStatus::Waiting => "waiting",
+            TaskStatus::Recording => "recording",
+            TaskStatus::Done => "done",
+            TaskStatus::Failed => "failed",
+            TaskStatus::Warning => "warning",
+        }
+    }
 }
 
 impl Serialize for TaskStatus {
@@ -60,12 +151,7 @@ impl Serialize for TaskStatus {
     where
         S: serde::Serializer,
     {
-        serializer.serialize_str(match self {
-            TaskStatus::Waiting => "waiting",
-            TaskStatus::Recording => "recording",
-            TaskStatus::Done => "done",
-            TaskStatus::Failed => "failed",
-        })
+        serializer.serialize_str(self.as_str())
     }
 }
 
@@ -80,9 +166,10 @@ impl<'de> serde::Deserialize<'de> for TaskStatus {
             "recording" => Ok(TaskStatus::Recording),
             "done" => Ok(TaskStatus::Done),
             "failed" => Ok(TaskStatus::Failed),
+            "warning" => Ok(TaskStatus::Warning),
             _ => Err(serde::de::Error::unknown_variant(
                 &s,
-                &["waiting", "recording", "done", "failed"],
+                &["waiting", "recording", "done", "failed", "warning"],
             )),
         }
     }
@@ -1,6 +1,7 @@
 use super::{Notifier, WebhookNotifier};
 use crate::{
     config::Config,
+    metrics,
     module::{Notification, TaskStatus},
     APP_NAME, APP_USER_AGENT,
 };
@@ -89,17 +90,28 @@ impl Notifier for Discord {
             TaskStatus::Recording => ("Recording", 0x58b9ff),
             TaskStatus::Done => ("Done", 0x45eb45),
             TaskStatus::Failed => ("Failed", 0xeb4545),
+            TaskStatus::Warning => ("Warning", 0xeb9a45),
+        };
+        let title = match notification.attempt {
+            1 => title.to_string(),
+            n => format!("{} (attempt {})", title, n),
         };
         let timestamp = chrono::Utc::now().to_rfc3339();
 
         let message = WebhookMessage {
             content: "".into(),
             embeds: vec![DiscordEmbed {
-                title: title.into(),
-                description: format!(
-                    "[{}](https://youtu.be/{})",
-                    notification.task.title, notification.task.video_id
-                ),
+                title,
+                description: match &notification.message {
+                    Some(message) => format!(
+                        "[{}](https://youtu.be/{})\n{}",
+                        notification.task.title, notification.task.video_id, message
+                    ),
+                    None => format!(
+                        "[{}](https://youtu.be/{})",
+                        notification.task.title, notification.task.video_id
+                    ),
+                },
                 color,
                 author: DiscordEmbedAuthor {
                     name: notification.task.channel_name.clone(),
@@ -131,11 +143,16 @@ impl Notifier for Discord {
             Ok(res) => {
                 if res.status().is_success() {
                     debug!("Sent Discord webhook");
+                    metrics::NOTIFICATIONS.inc(&[("notifier", "discord"), ("result", "success")]);
                 } else {
                     error!("Failed to send Discord webhook: {}", res.status());
+                    metrics::NOTIFICATIONS.inc(&[("notifier", "discord"), ("result", "failure")]);
                 }
             }
-            Err(e) => error!("Failed to send Discord webhook: {}", e),
+            Err(e) => {
+                error!("Failed to send Discord webhook: {}", e);
+                metrics::NOTIFICATIONS.inc(&[("notifier", "discord"), ("result", "failure")]);
+            }
         }
 
         Ok(())
@@ -6,12 +6,14 @@ use async_trait::async_trait;
 use std::sync::Arc;
 use tokio::sync::{mpsc, RwLock};
 
-// Import the Discord and Slack notifiers
+// Import the Discord, Slack and generic webhook notifiers
 mod discord_notifier;
 mod slack_notifier;
+mod webhook_notifier;
 
 pub use discord_notifier::Discord;
 pub use slack_notifier::Slack;
+pub use webhook_notifier::{validate_template, Webhook};
 
 #[async_trait]
 pub trait Notifier: Send + Sync {
@@ -54,9 +56,10 @@ impl NotificationSystem {
     pub fn new(config: Arc<RwLock<Config>>) -> Self {
         let discord = Box::new(Discord::new(config.clone())) as Box<dyn Notifier>;
         let slack = Box::new(Slack::new(config.clone())) as Box<dyn Notifier>;
+        let webhook = Box::new(Webhook::new(config.clone())) as Box<dyn Notifier>;
 
         Self {
-            notifiers: vec![discord, slack],
+            notifiers: vec![discord, slack, webhook],
         }
     }
 
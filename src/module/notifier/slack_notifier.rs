@@ -1,6 +1,7 @@
 use super::{Notifier, WebhookNotifier};
 use crate::{
     config::Config,
+    metrics,
     module::{Notification, TaskStatus},
     APP_USER_AGENT,
 };
@@ -71,6 +72,16 @@ impl Notifier for Slack {
             TaskStatus::Recording => ("Recording", "#58b9ff"),
             TaskStatus::Done => ("Done", "#45eb45"),
             TaskStatus::Failed => ("Failed", "#eb4545"),
+            TaskStatus::Warning => ("Warning", "#eb9a45"),
+        };
+
+        let pretext = match notification.attempt {
+            1 => pretext.to_string(),
+            n => format!("{} (attempt {})", pretext, n),
+        };
+        let pretext = match &notification.message {
+            Some(message) => format!("{}: {}", pretext, message),
+            None => pretext,
         };
 
         let message = SlackMessage {
@@ -78,7 +89,7 @@ impl Notifier for Slack {
             attachments: vec![SlackAttachment {
                 fallback: format!("{} - {}", pretext, notification.task.title),
                 color: color.into(),
-                pretext: pretext.into(),
+                pretext,
                 title: format!(
                     "{} - {}",
                     notification.task.channel_name,
@@ -101,11 +112,16 @@ impl Notifier for Slack {
             Ok(res) => {
                 if res.status().is_success() {
                     debug!("Sent Slack webhook");
+                    metrics::NOTIFICATIONS.inc(&[("notifier", "slack"), ("result", "success")]);
                 } else {
                     error!("Failed to send Slack webhook: {}", res.status());
+                    metrics::NOTIFICATIONS.inc(&[("notifier", "slack"), ("result", "failure")]);
                 }
             }
-            Err(e) => error!("Failed to send Slack webhook: {}", e),
+            Err(e) => {
+                error!("Failed to send Slack webhook: {}", e);
+                metrics::NOTIFICATIONS.inc(&[("notifier", "slack"), ("result", "failure")]);
+            }
         }
 
         Ok(())
@@ -0,0 +1,231 @@
+use super::{Notifier, WebhookNotifier};
+use crate::{
+    config::{Config, WebhookConfig},
+    metrics,
+    module::Notification,
+    APP_USER_AGENT,
+};
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use reqwest::{Client, Method};
+use serde_json::Value;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+pub struct Webhook {
+    config: Arc<RwLock<Config>>,
+    client: Client,
+}
+
+impl Webhook {
+    pub fn new(config: Arc<RwLock<Config>>) -> Self {
+        let client = Client::builder()
+            .user_agent(APP_USER_AGENT)
+            .build()
+            .expect("Failed to create client");
+        Self { config, client }
+    }
+
+    /// Sends the notification to a single configured webhook
+    async fn send(
+        &self,
+        name: &str,
+        cfg: &WebhookConfig,
+        notification: &Notification,
+    ) -> Result<()> {
+        let webhook_url = Self::get_webhook_url(cfg).await?;
+        let method = Method::from_bytes(cfg.method.to_uppercase().as_bytes())
+            .with_context(|| format!("Invalid HTTP method: {}", cfg.method))?;
+
+        let context =
+            serde_json::to_value(notification).context("Failed to serialize notification")?;
+        let body = match &cfg.body {
+            Some(template) => render_template(template, &context)?,
+            None => context.to_string(),
+        };
+
+        let mut req = self.client.request(method, &webhook_url);
+        if !cfg
+            .headers
+            .keys()
+            .any(|key| key.eq_ignore_ascii_case("Content-Type"))
+        {
+            req = req.header("Content-Type", "application/json");
+        }
+        for (key, value) in &cfg.headers {
+            req = req.header(key, value);
+        }
+
+        let res = req
+            .body(body)
+            .send()
+            .await
+            .context("Failed to send request")?;
+        if !res.status().is_success() {
+            return Err(anyhow!("Server responded with {}", res.status()));
+        }
+
+        debug!("Sent webhook {}", name);
+        Ok(())
+    }
+}
+
+/// A webhook template split at its placeholders.
+struct Template<'a> {
+    /// The text before each `{{path}}` placeholder, with its path
+    parts: Vec<(&'a str, &'a str)>,
+    /// The text after the last placeholder
+    rest: &'a str,
+}
+
+fn parse_template(template: &str) -> Result<Template<'_>, String> {
+    let mut parts = vec![];
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        let end = after
+            .find("}}")
+            .ok_or("Unclosed placeholder in webhook template")?;
+        let path = after[..end].trim();
+        if path.is_empty() {
+            return Err("Empty placeholder in webhook template".into());
+        }
+        parts.push((&rest[..start], path));
+        rest = &after[end + 2..];
+    }
+    Ok(Template { parts, rest })
+}
+
+/// Checks that the placeholders of a webhook template are well-formed.
+pub fn validate_template(template: &str) -> Result<(), String> {
+    parse_template(template).map(|_| ())
+}
+
+/// Replaces every `{{path}}` in the template with the value at the
+/// dot-separated path in the context. Strings are inserted JSON-escaped but
+/// without surrounding quotes, so they can be placed inside a JSON string.
+/// Any other value is inserted as JSON, and fields that are missing, e.g.
+/// because a parent is null, as `null`.
+fn render_template(template: &str, context: &Value) -> Result<String> {
+    let Template { parts, rest } = parse_template(template).map_err(|e| anyhow!(e))?;
+    let mut output = String::with_capacity(template.len());
+    for (text, path) in parts {
+        output.push_str(text);
+        let value = path
+            .split('.')
+            .try_fold(context, |value, key| match value {
+                Value::Array(items) => key.parse::<usize>().ok().and_then(|i| items.get(i)),
+                _ => value.get(key),
+            })
+            .unwrap_or(&Value::Null);
+        let json = value.to_string();
+        match value {
+            Value::String(_) => output.push_str(&json[1..json.len() - 1]),
+            _ => output.push_str(&json),
+        }
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
+#[async_trait]
+impl WebhookNotifier for Webhook {
+    type Config = WebhookConfig;
+}
+
+#[async_trait]
+impl Notifier for Webhook {
+    async fn send_notification(&self, notification: &Notification) -> Result<()> {
+        let webhooks = {
+            let cfg = self.config.read().await;
+            match &cfg.notifier {
+                Some(notifier) => notifier.webhook.clone(),
+                None => return Ok(()), // Skip if no notifier config
+            }
+        };
+
+        for (name, cfg) in &webhooks {
+            if !cfg.notify_on.contains(&notification.status) {
+                debug!(
+                    "Webhook {} not notifying on status {:?}",
+                    name, notification.status
+                );
+                continue;
+            }
+
+            // Don't let one broken webhook prevent the others from being sent
+            let notifier = format!("webhook.{}", name);
+            match self.send(name, cfg, notification).await {
+                Ok(()) => {
+                    metrics::NOTIFICATIONS.inc(&[("notifier", &notifier), ("result", "success")])
+                }
+                Err(e) => {
+                    error!("Failed to send webhook {}: {:?}", name, e);
+                    metrics::NOTIFICATIONS.inc(&[("notifier", &notifier), ("result", "failure")]);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::module::{recorder::YTAStatus, Task, TaskStatus};
+
+    fn notification() -> Notification {
+        Notification {
+            task: Task {
+                title: "Title with \"quotes\"".into(),
+                ..Task::test("IKKar5SS29E")
+            },
+            status: TaskStatus::Done,
+            attempt: 2,
+            yta_status: Some(YTAStatus::new()),
+            message: None,
+        }
+    }
+
+    #[test]
+    fn test_render_template() {
+        let context = serde_json::to_value(notification()).unwrap();
+
+        let body = render_template(
+            r#"{"title": "{{ task.title }}", "status": "{{status}}", "attempt": {{attempt}}, "picture": {{task.channel_picture}}, "state": "{{yta_status.state}}"}"#,
+            &context,
+        )
+        .unwrap();
+        let body: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body["title"], "Title with \"quotes\"");
+        assert_eq!(body["status"], "done");
+        assert_eq!(body["attempt"], 2);
+        assert_eq!(body["picture"], Value::Null);
+        assert_eq!(body["state"], "Idle");
+
+        // Objects are inserted as JSON
+        let body = render_template("{{task}}", &context).unwrap();
+        let task: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(task["video_id"], "IKKar5SS29E");
+
+        // Missing fields and fields of null values are null
+        assert_eq!(render_template("{{task.nope}}", &context).unwrap(), "null");
+        let mut without_status = notification();
+        without_status.yta_status = None;
+        let context = serde_json::to_value(without_status).unwrap();
+        assert_eq!(
+            render_template("\"{{yta_status.state}}\"", &context).unwrap(),
+            "\"null\""
+        );
+
+        assert!(render_template("{{task.title", &context).is_err());
+        assert!(validate_template("{{task.title").is_err());
+        assert!(validate_template("{{ }}").is_err());
+        assert!(validate_template("{{task.title}} {{status}}").is_ok());
+        assert_eq!(
+            render_template("no placeholders", &context).unwrap(),
+            "no placeholders"
+        );
+    }
+}
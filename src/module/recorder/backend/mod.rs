@@ -0,0 +1,70 @@
+use super::YTAStatus;
+use crate::{config::Config, module::Task};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+mod ytarchive;
+mod ytdlp;
+
+pub use ytarchive::Ytarchive;
+pub use ytdlp::Ytdlp;
+
+/// A program that records a task. The recorder runs it in the working
+/// directory and reads its output line by line.
+pub trait Backend: Send + Sync {
+    /// Returns the executable and the arguments to record the task with.
+    fn command(&self, cfg: &Config, task: &Task) -> (String, Vec<String>);
+
+    /// Updates the status with a line of output of the process.
+    fn parse_line(&self, status: &mut YTAStatus, line: &str);
+}
+
+/// The backends that can be selected in the config.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, TS, Serialize, Deserialize)]
+#[ts(export)]
+pub enum BackendKind {
+    #[default]
+    #[serde(rename = "ytarchive")]
+    Ytarchive,
+    #[serde(rename = "yt-dlp")]
+    Ytdlp,
+}
+
+impl BackendKind {
+    pub fn backend(&self) -> &'static dyn Backend {
+        match self {
+            BackendKind::Ytarchive => &Ytarchive,
+            BackendKind::Ytdlp => &Ytdlp,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BackendKind::Ytarchive => "ytarchive",
+            BackendKind::Ytdlp => "yt-dlp",
+        }
+    }
+}
+
+/// Parses a duration in `[[HH:]MM:]SS[.xx]` notation into seconds, as printed
+/// by ffmpeg and yt-dlp.
+fn parse_clock(s: &str) -> Option<f64> {
+    s.split(':').try_fold(0.0, |total, part| {
+        let part: f64 = part.parse().ok()?;
+        (part >= 0.0).then_some(total * 60.0 + part)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_clock() {
+        assert_eq!(parse_clock("00:20:14.97"), Some(1214.97));
+        assert_eq!(parse_clock("02:00"), Some(120.0));
+        assert_eq!(parse_clock("42"), Some(42.0));
+        assert_eq!(parse_clock("N/A"), None);
+        assert_eq!(parse_clock(""), None);
+    }
+}
@@ -0,0 +1,199 @@
+use super::Backend;
+use crate::{
+    config::Config,
+    module::{
+        recorder::{YTAState, YTAStatus},
+        Task,
+    },
+};
+use parser::Event;
+
+mod parser;
+
+pub struct Ytarchive;
+
+impl Backend for Ytarchive {
+    /// Builds the ytarchive command line, applying the overrides of the task.
+    fn command(&self, cfg: &Config, task: &Task) -> (String, Vec<String>) {
+        let cfg = &cfg.ytarchive;
+        let overrides = &task.overrides;
+        let mut args = overrides.args.clone().unwrap_or_else(|| cfg.args.clone());
+        args.extend(overrides.extra_args.iter().cloned());
+
+        // Add the --wait flag if not present
+        if !args.contains(&"-w".to_string()) && !args.contains(&"--wait".to_string()) {
+            args.push("--wait".to_string());
+        }
+
+        args.extend(vec![
+            format!("https://youtu.be/{}", task.video_id),
+            overrides
+                .quality
+                .clone()
+                .unwrap_or_else(|| cfg.quality.clone()),
+        ]);
+        (cfg.executable_path.clone(), args)
+    }
+
+    fn parse_line(&self, status: &mut YTAStatus, line: &str) {
+        status.last_output = Some(line.to_string());
+        status.last_update = chrono::Utc::now();
+
+        match parser::parse(line) {
+            Event::Version(version) => {
+                status.version.get_or_insert(version);
+            }
+            Event::Scheduled { start, .. } => status.state = YTAState::Waiting(start),
+            Event::Waiting | Event::Retry { .. } => {
+                // Keep the scheduled start time if there is one
+                if !matches!(status.state, YTAState::Waiting(_)) {
+                    status.state = YTAState::Waiting(None);
+                }
+            }
+            Event::Quality(quality) => {
+                status.video_quality.get_or_insert(quality);
+            }
+            Event::Progress {
+                video_fragments,
+                audio_fragments,
+                total_size,
+            } => {
+                status.state = YTAState::Recording;
+                status.set_fragments(video_fragments, audio_fragments);
+                if let Some(total_size) = total_size {
+                    status.set_total_size(total_size);
+                }
+            }
+            Event::MuxingStarted => status.state = YTAState::Muxing,
+            Event::MuxingProgress { time, speed, .. } => {
+                status.state = YTAState::Muxing;
+                status.set_muxing_progress(time, speed);
+            }
+            Event::MuxingDuration(seconds) => status.set_muxing_duration(seconds),
+            Event::FinalFile(path) => {
+                status.finish_muxing();
+                status.state = YTAState::Finished;
+                status.output_file = Some(path);
+            }
+            Event::AlreadyProcessed => status.state = YTAState::AlreadyProcessed,
+            Event::Ended => status.state = YTAState::Ended,
+            Event::Interrupted => status.state = YTAState::Interrupted,
+            Event::Error {
+                message,
+                fatal: true,
+            } => {
+                warn!("ytarchive failed: {}", message);
+                status.state = YTAState::Errored;
+            }
+            Event::Error {
+                message,
+                fatal: false,
+            } => warn!("ytarchive error: {}", message),
+            Event::Warning(message) => debug!("ytarchive warning: {}", message),
+            Event::DownloadFinished | Event::Ignored => (),
+            Event::Unknown(line) => warn!("Unknown ytarchive output: {}", line),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command() {
+        let mut cfg = Config::default();
+        cfg.ytarchive.executable_path = "ytarchive".into();
+        cfg.ytarchive.args = vec!["--vp9".into()];
+        cfg.ytarchive.quality = "best".into();
+        let mut task = Task::test("IKKar5SS29E");
+        assert_eq!(
+            Ytarchive.command(&cfg, &task),
+            (
+                "ytarchive".into(),
+                vec![
+                    "--vp9".into(),
+                    "--wait".into(),
+                    "https://youtu.be/IKKar5SS29E".into(),
+                    "best".into()
+                ]
+            )
+        );
+
+        task.overrides.extra_args = vec!["--cookies".into(), "cookies.txt".into()];
+        task.overrides.quality = Some("audio_only".into());
+        assert_eq!(
+            Ytarchive.command(&cfg, &task).1,
+            vec![
+                "--vp9",
+                "--cookies",
+                "cookies.txt",
+                "--wait",
+                "https://youtu.be/IKKar5SS29E",
+                "audio_only"
+            ]
+        );
+
+        task.overrides.args = Some(vec!["-w".into()]);
+        assert_eq!(
+            Ytarchive.command(&cfg, &task).1,
+            vec![
+                "-w",
+                "--cookies",
+                "cookies.txt",
+                "https://youtu.be/IKKar5SS29E",
+                "audio_only"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_transcript() {
+        let mut status = YTAStatus::new();
+        let mut lines = include_str!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/ytarchive/0.3.1-scheduled.log"
+        ))
+        .lines();
+        for line in lines.by_ref().take(3) {
+            Ytarchive.parse_line(&mut status, line);
+        }
+        // Being late doesn't forget when the stream was scheduled
+        assert!(matches!(status.state, YTAState::Waiting(Some(_))));
+
+        for line in lines.by_ref().take(9) {
+            Ytarchive.parse_line(&mut status, line);
+        }
+        // ffmpeg doesn't print the length, so the percentage isn't known
+        // while muxing
+        assert_eq!(status.state, YTAState::Muxing);
+        assert_eq!(status.muxing_speed, Some(194.0));
+        assert_eq!(status.muxing_percent, None);
+        assert_eq!(status.recorded_seconds, None);
+
+        for line in lines {
+            Ytarchive.parse_line(&mut status, line);
+        }
+        assert_eq!(status.state, YTAState::Finished);
+        assert_eq!(status.version, Some("0.3.1-15663af".into()));
+        assert_eq!(status.video_quality, Some("1080p60 (h264)".into()));
+        assert_eq!(status.video_fragments, Some(1215));
+        assert_eq!(status.total_size, Some("133.12MiB".into()));
+        assert_eq!(status.downloaded_bytes, Some(139_586_437));
+        assert_eq!(status.recorded_seconds, Some(1214.97));
+        assert_eq!(status.muxing_speed, Some(201.0));
+        assert_eq!(status.muxing_percent, Some(100.0));
+
+        let mut status = YTAStatus::new();
+        for line in include_str!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/ytarchive/0.4.0-errored.log"
+        ))
+        .lines()
+        {
+            Ytarchive.parse_line(&mut status, line);
+        }
+        assert_eq!(status.state, YTAState::Errored);
+        assert_eq!(status.video_fragments, Some(20));
+    }
+}
@@ -0,0 +1,362 @@
+use super::super::parse_clock;
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::borrow::Cow;
+
+/// Something ytarchive reported in a line of its output.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// The version of ytarchive, e.g. `0.4.0-ad4bc7c`
+    Version(String),
+    /// The stream is scheduled to start later
+    Scheduled {
+        start: Option<DateTime<Utc>>,
+        seconds_remaining: Option<u64>,
+    },
+    /// Waiting for a stream that should have started already
+    Waiting,
+    /// Checked again whether the stream has started
+    Retry {
+        count: u32,
+        waited_seconds: Option<u64>,
+    },
+    /// The quality being downloaded, e.g. `1080p60 (h264)`
+    Quality(String),
+    /// Fragments downloaded so far. Audio only and video only downloads
+    /// leave the other count out.
+    Progress {
+        video_fragments: Option<u32>,
+        audio_fragments: Option<u32>,
+        total_size: Option<String>,
+    },
+    DownloadFinished,
+    MuxingStarted,
+    /// Progress reported by ffmpeg while muxing. `time` is how much of the
+    /// recording has been muxed in seconds, and `speed` how many times
+    /// faster than real time that happens.
+    MuxingProgress {
+        size: Option<String>,
+        time: Option<f64>,
+        speed: Option<f64>,
+    },
+    /// The length of the recording in seconds, printed by ffmpeg before
+    /// muxing when its log level allows it
+    MuxingDuration(f64),
+    FinalFile(String),
+    /// The stream has already been processed into a regular video
+    AlreadyProcessed,
+    /// The stream has ended and is being processed
+    Ended,
+    Interrupted,
+    /// An error, which ends the recording if it's fatal
+    Error {
+        message: String,
+        fatal: bool,
+    },
+    Warning(String),
+    /// Output that is known but not useful
+    Ignored,
+    Unknown(String),
+}
+
+/// Errors after which ytarchive gives up on the recording.
+const FATAL_ERRORS: &[&str] = &[
+    "Error retrieving player response",
+    "unable to retrieve",
+    "error writing the muxcmd file",
+    "Something must have gone wrong with ffmpeg",
+    "At least one error occurred",
+];
+
+/// Output that doesn't tell anything about the state of the recording.
+const IGNORED: &[&str] = &[
+    "Loaded cookie file",
+    "Video Title: ",
+    "Channel: ",
+    "Waiting for this time to elapse",
+];
+
+/// Parses a line of ytarchive output.
+///
+/// Sample output:
+///
+///   ytarchive 0.3.1-15663af
+///   Stream starts at 2022-03-14T14:00:00+00:00 in 11075 seconds. Waiting for this time to elapse...
+///   Stream is 30 seconds late...
+///   Selected quality: 1080p60 (h264)
+///   Video Fragments: 1215; Audio Fragments: 1215; Total Downloaded: 133.12MiB
+///   Download Finished
+///   Muxing final file...
+///   Final file: /path/to/output.mp4
+///
+/// Newer versions prefix most lines with a timestamp, such as
+/// `2024/04/16 16:25:31`.
+pub fn parse(line: &str) -> Event {
+    lazy_static! {
+        static ref TIMESTAMP: Regex = Regex::new(r"^\d{4}/\d{2}/\d{2} \d{2}:\d{2}:\d{2}\s*")
+            .expect("Failed to compile timestamp regex");
+        static ref SCHEDULED: Regex = Regex::new(r"^Stream starts at (\S+) in (\d+) seconds")
+            .expect("Failed to compile scheduled start regex");
+        static ref RETRIES: Regex =
+            Regex::new(r"^Retries: (\d+)(?:.*Total time waited: (\d+) seconds)?")
+                .expect("Failed to compile retries regex");
+        static ref MUXING_SIZE: Regex =
+            Regex::new(r"size=\s*(\S+)").expect("Failed to compile muxing size regex");
+        static ref MUXING_TIME: Regex =
+            Regex::new(r"time=\s*(\S+)").expect("Failed to compile muxing time regex");
+        static ref MUXING_SPEED: Regex =
+            Regex::new(r"speed=\s*([\d.]+)x").expect("Failed to compile muxing speed regex");
+        static ref MUXING_DURATION: Regex =
+            Regex::new(r"^Duration: ([\d:.]+)").expect("Failed to compile muxing duration regex");
+    }
+
+    let line = strip_ansi(line);
+    let line = TIMESTAMP.replace(line.trim(), "");
+    let line = line.trim();
+
+    if line.starts_with("Video Fragments:") || line.starts_with("Audio Fragments:") {
+        let mut video_fragments = None;
+        let mut audio_fragments = None;
+        let mut total_size = None;
+        for (key, value) in line.split(';').filter_map(|part| part.split_once(':')) {
+            let value = value.trim();
+            match key.trim() {
+                "Video Fragments" => video_fragments = value.parse().ok(),
+                "Audio Fragments" => audio_fragments = value.parse().ok(),
+                "Total Downloaded" => total_size = Some(value.to_string()),
+                _ => (),
+            }
+        }
+        Event::Progress {
+            video_fragments,
+            audio_fragments,
+            total_size,
+        }
+    } else if let Some(version) = line.strip_prefix("ytarchive ") {
+        Event::Version(version.trim().to_string())
+    } else if let Some(captures) = SCHEDULED.captures(line) {
+        Event::Scheduled {
+            start: DateTime::parse_from_rfc3339(&captures[1])
+                .ok()
+                .map(|d| d.into()),
+            seconds_remaining: captures[2].parse().ok(),
+        }
+    } else if let Some(captures) = RETRIES.captures(line) {
+        Event::Retry {
+            count: captures[1].parse().unwrap_or_default(),
+            waited_seconds: captures.get(2).and_then(|m| m.as_str().parse().ok()),
+        }
+    } else if line.starts_with("Stream is ") || line.starts_with("Waiting for stream") {
+        Event::Waiting
+    } else if let Some(quality) = line.strip_prefix("Selected quality: ") {
+        Event::Quality(quality.to_string())
+    } else if line.starts_with("Download Finished") {
+        Event::DownloadFinished
+    } else if line.starts_with("Muxing final file") {
+        Event::MuxingStarted
+    } else if line.starts_with("size=") || line.starts_with("frame=") {
+        Event::MuxingProgress {
+            size: MUXING_SIZE.captures(line).map(|c| c[1].to_string()),
+            time: MUXING_TIME.captures(line).and_then(|c| parse_clock(&c[1])),
+            speed: MUXING_SPEED.captures(line).and_then(|c| c[1].parse().ok()),
+        }
+    } else if let Some(duration) = MUXING_DURATION
+        .captures(line)
+        .and_then(|c| parse_clock(&c[1]))
+    {
+        Event::MuxingDuration(duration)
+    } else if let Some(path) = line.strip_prefix("Final file: ") {
+        Event::FinalFile(path.to_string())
+    } else if line.starts_with("Livestream has been processed") {
+        Event::AlreadyProcessed
+    } else if line.starts_with("Livestream has ended and is being processed")
+        || line.contains("use yt-dlp to download it.")
+    {
+        Event::Ended
+    } else if line.contains("User Interrupt") {
+        Event::Interrupted
+    } else if FATAL_ERRORS.iter().any(|error| line.contains(error)) {
+        Event::Error {
+            message: line.trim_start_matches("ERROR: ").to_string(),
+            fatal: true,
+        }
+    } else if let Some(message) = line.strip_prefix("ERROR: ") {
+        Event::Error {
+            message: message.to_string(),
+            fatal: false,
+        }
+    } else if let Some(message) = line.strip_prefix("WARNING: ") {
+        Event::Warning(message.to_string())
+    } else if line.is_empty() || IGNORED.iter().any(|ignored| line.starts_with(ignored)) {
+        Event::Ignored
+    } else {
+        Event::Unknown(line.to_string())
+    }
+}
+
+/// Removes terminal escape sequences, such as colors and the "erase line"
+/// ytarchive ends progress lines with.
+fn strip_ansi(s: &str) -> Cow<'_, str> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"\x1b(?:\[[0-9;?]*[ -/]*[@-~]|\][^\x07]*\x07)")
+            .expect("Failed to compile ANSI stripping regex");
+    }
+    RE.replace_all(s, "")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    /// Parses a transcript, checking that every line is understood.
+    fn parse_transcript(transcript: &str) -> Vec<Event> {
+        transcript
+            .lines()
+            .map(|line| {
+                let event = parse(line);
+                assert!(
+                    !matches!(event, Event::Unknown(_)),
+                    "Unknown output: {}",
+                    line
+                );
+                event
+            })
+            .filter(|event| *event != Event::Ignored)
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_line() {
+        assert_eq!(
+            parse("Stream starts at 2022-03-14T14:00:00+00:00 in 11075 seconds. Waiting for this time to elapse..."),
+            Event::Scheduled {
+                start: Some(Utc.with_ymd_and_hms(2022, 3, 14, 14, 0, 0).unwrap()),
+                seconds_remaining: Some(11075),
+            }
+        );
+        // Lines that are too short used to panic
+        assert_eq!(
+            parse("Stream starts at soon"),
+            Event::Unknown("Stream starts at soon".into())
+        );
+        assert_eq!(
+            parse("Selected quality: \x1b[1m1080p60 (h264)\x1b[0m"),
+            Event::Quality("1080p60 (h264)".into())
+        );
+        assert_eq!(
+            parse("2024/04/16 16:25:31 Final file: /videos/a b.mp4"),
+            Event::FinalFile("/videos/a b.mp4".into())
+        );
+        assert_eq!(
+            parse("Audio Fragments: 20; Total Downloaded: 512B\x1b[K"),
+            Event::Progress {
+                video_fragments: None,
+                audio_fragments: Some(20),
+                total_size: Some("512B".into()),
+            }
+        );
+        assert_eq!(parse("2024/04/16 16:25:31"), Event::Ignored);
+        assert_eq!(
+            parse("  Duration: 00:20:14.97, start: 0.000000, bitrate: 917 kb/s"),
+            Event::MuxingDuration(1214.97)
+        );
+    }
+
+    #[test]
+    fn test_transcript_0_3_1() {
+        let events = parse_transcript(include_str!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/ytarchive/0.3.1-scheduled.log"
+        )));
+        assert_eq!(events[0], Event::Version("0.3.1-15663af".into()));
+        assert_eq!(
+            events[1],
+            Event::Scheduled {
+                start: Some(Utc.with_ymd_and_hms(2022, 3, 14, 14, 0, 0).unwrap()),
+                seconds_remaining: Some(11075),
+            }
+        );
+        assert!(events.contains(&Event::Waiting));
+        assert!(events.contains(&Event::Quality("1080p60 (h264)".into())));
+        assert!(events.contains(&Event::Progress {
+            video_fragments: Some(1215),
+            audio_fragments: Some(1215),
+            total_size: Some("133.12MiB".into()),
+        }));
+        assert!(events.contains(&Event::MuxingStarted));
+        assert!(events.contains(&Event::MuxingProgress {
+            size: Some("136192kB".into()),
+            time: Some(1214.97),
+            speed: Some(201.0),
+        }));
+        assert_eq!(
+            events.last(),
+            Some(&Event::FinalFile(
+                "/home/user/temp/20220314 Karaoke [Moona] (IKKar5SS29E).mp4".into()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_transcript_0_4_0() {
+        let events = parse_transcript(include_str!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/ytarchive/0.4.0-retries.log"
+        )));
+        assert_eq!(events[0], Event::Version("0.4.0-ad4bc7c".into()));
+        assert!(events.contains(&Event::Retry {
+            count: 2,
+            waited_seconds: Some(60),
+        }));
+        assert!(events.contains(&Event::Warning(
+            "Fragment 42 for video has not been downloaded after 10 tries, skipping".into()
+        )));
+        assert!(events.contains(&Event::Progress {
+            video_fragments: Some(360),
+            audio_fragments: Some(360),
+            total_size: Some("1.02GiB".into()),
+        }));
+        assert_eq!(
+            events.last(),
+            Some(&Event::FinalFile(
+                "/temp/20240416 Singing [Moona] (IKKar5SS29E).mp4".into()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_transcript_0_4_0_errors() {
+        let events = parse_transcript(include_str!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/ytarchive/0.4.0-errored.log"
+        )));
+        assert!(events.contains(&Event::Error {
+            message: "Failed to download fragment 12: context deadline exceeded".into(),
+            fatal: false,
+        }));
+        assert_eq!(
+            events.last(),
+            Some(&Event::Error {
+                message: "At least one error occurred during download".into(),
+                fatal: true,
+            })
+        );
+    }
+
+    #[test]
+    fn test_transcript_ended() {
+        let events = parse_transcript(include_str!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/ytarchive/0.3.2-ended.log"
+        )));
+        assert_eq!(events.last(), Some(&Event::Ended));
+
+        let events = parse_transcript(include_str!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/ytarchive/0.4.0-processed.log"
+        )));
+        assert_eq!(events.last(), Some(&Event::AlreadyProcessed));
+    }
+}
@@ -0,0 +1,239 @@
+use super::{parse_clock, Backend};
+use crate::{
+    config::Config,
+    module::{
+        recorder::{YTAState, YTAStatus},
+        Task,
+    },
+};
+use lazy_static::lazy_static;
+use regex::Regex;
+
+/// Printed by yt-dlp once the file is in its final location, through
+/// `--print`.
+const FINAL_FILE: &str = "Final file: ";
+
+pub struct Ytdlp;
+
+impl Backend for Ytdlp {
+    fn command(&self, cfg: &Config, task: &Task) -> (String, Vec<String>) {
+        let cfg = &cfg.ytdlp;
+        let overrides = &task.overrides;
+        let mut args = cfg.args.clone();
+        args.extend(overrides.ytdlp_extra_args.iter().cloned());
+        args.extend(
+            [
+                "--newline",
+                "--no-colors",
+                "--progress",
+                "--no-simulate",
+                // --print would hide everything else, including the
+                // progress and the merging
+                "--no-quiet",
+                "--print",
+                &format!("after_move:{}%(filepath)s", FINAL_FILE),
+                "--format",
+                overrides.ytdlp_format.as_ref().unwrap_or(&cfg.format),
+                "--",
+                &format!("https://www.youtube.com/watch?v={}", task.video_id),
+            ]
+            .map(String::from),
+        );
+        (cfg.executable_path.clone(), args)
+    }
+
+    /// Parses a line of output from yt-dlp.
+    ///
+    /// Sample output:
+    ///
+    ///   [info] IKKar5SS29E: Downloading 1 format(s): 299+140
+    ///   [download]  12.3% of ~ 1.23GiB at  5.00MiB/s ETA 02:00 (frag 12/100)
+    ///   [download] 100% of  123.45MiB in 00:00:20 at 6.00MiB/s
+    ///   [Merger] Merging formats into "title [IKKar5SS29E].mp4"
+    ///   Final file: title [IKKar5SS29E].mp4
+    ///   ERROR: [youtube] IKKar5SS29E: Video unavailable
+    fn parse_line(&self, status: &mut YTAStatus, line: &str) {
+        lazy_static! {
+            static ref PROGRESS: Regex = Regex::new(
+                r"^\[download\]\s+(?P<percent>[\d.]+)% of\s+~?\s*(?P<size>[\d.]+)(?P<unit>[KMGT]?i?B)"
+            )
+            .expect("Failed to compile yt-dlp progress regex");
+            static ref ETA: Regex =
+                Regex::new(r" ETA (\S+)").expect("Failed to compile yt-dlp ETA regex");
+            static ref FRAGMENT: Regex =
+                Regex::new(r"\(frag (\d+)/\d+\)").expect("Failed to compile yt-dlp fragment regex");
+            static ref FORMAT: Regex = Regex::new(r"^\[info\] .*: Downloading \d+ format\(s\): (.+)$")
+                .expect("Failed to compile yt-dlp format regex");
+        }
+
+        status.last_output = Some(line.to_string());
+        status.last_update = chrono::Utc::now();
+
+        if let Some(captures) = PROGRESS.captures(line) {
+            status.state = YTAState::Recording;
+            let total = size_bytes(&captures["size"], &captures["unit"]);
+            let percent: Option<f64> = captures["percent"].parse().ok();
+            if let (Some(total), Some(percent)) = (total, percent) {
+                let downloaded = total * percent / 100.0;
+                status.total_size = Some(format!("{:.2}MiB", downloaded / (1u64 << 20) as f64));
+                status.set_downloaded_bytes(downloaded as u64);
+            }
+            status.eta_seconds = ETA.captures(line).and_then(|c| parse_clock(&c[1]));
+            if let Some(captures) = FRAGMENT.captures(line) {
+                status.video_fragments = captures[1].parse().ok();
+            }
+        } else if let Some(captures) = FORMAT.captures(line) {
+            status.video_quality = Some(captures[1].to_string());
+        } else if line.starts_with("[Merger]")
+            || line.starts_with("[FixupM3u8]")
+            || line.starts_with("[FixupM4a]")
+        {
+            status.state = YTAState::Muxing;
+        } else if let Some(path) = line.strip_prefix(FINAL_FILE) {
+            status.state = YTAState::Finished;
+            status.output_file = Some(path.to_string());
+        } else if line.starts_with("ERROR: Interrupted by user") {
+            status.state = YTAState::Interrupted;
+        } else if line.starts_with("ERROR:") {
+            status.state = YTAState::Errored;
+        } else {
+            // yt-dlp says a lot, most of which isn't relevant
+            trace!("Ignored yt-dlp output: {}", line);
+        }
+    }
+}
+
+/// Converts a size reported by yt-dlp, e.g. `1.23` and `GiB`, to bytes.
+fn size_bytes(value: &str, unit: &str) -> Option<f64> {
+    let multiplier: u64 = match unit {
+        "B" => 1,
+        "KiB" => 1 << 10,
+        "MiB" => 1 << 20,
+        "GiB" => 1 << 30,
+        "TiB" => 1 << 40,
+        "KB" => 1_000,
+        "MB" => 1_000_000,
+        "GB" => 1_000_000_000,
+        "TB" => 1_000_000_000_000,
+        _ => return None,
+    };
+    Some(value.parse::<f64>().ok()? * multiplier as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command() {
+        let mut cfg = Config::default();
+        cfg.ytdlp.args = vec!["--cookies".into(), "cookies.txt".into()];
+        let mut task = Task::test("IKKar5SS29E");
+        let (executable, args) = Ytdlp.command(&cfg, &task);
+        assert_eq!(executable, "yt-dlp");
+        assert_eq!(args[..2], ["--cookies", "cookies.txt"]);
+        assert!(args.contains(&"after_move:Final file: %(filepath)s".to_string()));
+        assert!(args.contains(&"--no-quiet".to_string()));
+        assert_eq!(
+            args.last().unwrap(),
+            "https://www.youtube.com/watch?v=IKKar5SS29E"
+        );
+
+        // The yt-dlp overrides apply, but not the ytarchive ones
+        task.overrides.extra_args = vec!["--vp9".into()];
+        task.overrides.ytdlp_extra_args = vec!["--cookies".into(), "channel.txt".into()];
+        task.overrides.ytdlp_format = Some("bestaudio".into());
+        let (_, args) = Ytdlp.command(&cfg, &task);
+        assert_eq!(
+            args[..4],
+            ["--cookies", "cookies.txt", "--cookies", "channel.txt"]
+        );
+        assert!(!args.contains(&"--vp9".to_string()));
+        let format = args.iter().position(|arg| arg == "--format").unwrap();
+        assert_eq!(args[format + 1], "bestaudio");
+    }
+
+    #[test]
+    fn test_parse_line() {
+        let mut status = YTAStatus::new();
+        let lines = [
+            "[youtube] Extracting URL: https://www.youtube.com/watch?v=IKKar5SS29E",
+            "[info] IKKar5SS29E: Downloading 1 format(s): 299+140",
+        ];
+        for line in lines {
+            Ytdlp.parse_line(&mut status, line);
+        }
+        assert_eq!(status.state, YTAState::Idle);
+        assert_eq!(status.video_quality, Some("299+140".into()));
+
+        Ytdlp.parse_line(
+            &mut status,
+            "[download]  50.0% of ~   2.00GiB at    5.00MiB/s ETA 02:00 (frag 12/100)",
+        );
+        assert_eq!(status.state, YTAState::Recording);
+        assert_eq!(status.video_fragments, Some(12));
+        assert_eq!(status.downloaded_bytes(), Some(1 << 30));
+        assert_eq!(status.eta_seconds, Some(120.0));
+
+        Ytdlp.parse_line(
+            &mut status,
+            "[download] 100% of  123.45MiB in 00:00:20 at 6.00MiB/s",
+        );
+        assert_eq!(status.total_size, Some("123.45MiB".into()));
+
+        Ytdlp.parse_line(
+            &mut status,
+            "[Merger] Merging formats into \"title [IKKar5SS29E].mp4\"",
+        );
+        assert_eq!(status.state, YTAState::Muxing);
+
+        Ytdlp.parse_line(&mut status, "Final file: title [IKKar5SS29E].mp4");
+        assert_eq!(status.state, YTAState::Finished);
+        assert_eq!(
+            status.output_file,
+            Some("title [IKKar5SS29E].mp4".to_string())
+        );
+
+        let mut status = YTAStatus::new();
+        Ytdlp.parse_line(
+            &mut status,
+            "ERROR: [youtube] IKKar5SS29E: Video unavailable",
+        );
+        assert_eq!(status.state, YTAState::Errored);
+
+        let mut status = YTAStatus::new();
+        Ytdlp.parse_line(&mut status, "ERROR: Interrupted by user");
+        assert_eq!(status.state, YTAState::Interrupted);
+    }
+
+    #[test]
+    fn test_parse_transcript() {
+        let mut status = YTAStatus::new();
+        let mut states = vec![];
+        for line in include_str!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/ytdlp/2024.08.06-vod.log"
+        ))
+        .lines()
+        {
+            Ytdlp.parse_line(&mut status, line);
+            if states.last() != Some(&status.state) {
+                states.push(status.state.clone());
+            }
+        }
+        assert_eq!(
+            states,
+            vec![
+                YTAState::Idle,
+                YTAState::Recording,
+                YTAState::Muxing,
+                YTAState::Finished
+            ]
+        );
+        assert_eq!(status.video_quality, Some("299+140".into()));
+        assert_eq!(
+            status.output_file,
+            Some("/home/hoshinova/temp/【歌枠】Karaoke [IKKar5SS29E].mp4".into())
+        );
+    }
+}
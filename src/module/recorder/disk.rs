@@ -0,0 +1,210 @@
+use super::outpath;
+use crate::{config::Config, module::Task};
+use anyhow::Result;
+use chrono::{DateTime, Local, Utc};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use ts_rs::TS;
+
+/// Free space on the file system that holds a directory.
+#[derive(Debug, Clone, TS, Serialize)]
+#[ts(export)]
+pub struct DiskUsage {
+    /// The configured directory, which might not exist yet
+    pub path: String,
+    /// What the directory is used for, either "working" or "output"
+    pub kind: String,
+    #[ts(type = "number | null")]
+    pub free_bytes: Option<u64>,
+    #[ts(type = "number | null")]
+    pub total_bytes: Option<u64>,
+    /// The configured threshold, if any
+    #[ts(type = "number | null")]
+    pub min_free_bytes: Option<u64>,
+}
+
+/// Returns the free and total space in bytes on the file system that holds
+/// the path. Paths that don't exist yet are looked up through the closest
+/// ancestor that does.
+pub fn space(path: &Path) -> Result<(u64, u64)> {
+    let path = existing_ancestor(path);
+
+    #[cfg(unix)]
+    {
+        use anyhow::Context;
+        use std::os::unix::ffi::OsStrExt;
+
+        let c_path = std::ffi::CString::new(path.as_os_str().as_bytes())
+            .context("Path contains a null byte")?;
+        // SAFETY: statvfs only writes to the struct it's given
+        let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+        if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| format!("Failed to get free space of {}", path.display()));
+        }
+        let block_size = stat.f_frsize as u64;
+        Ok((
+            stat.f_bavail as u64 * block_size,
+            stat.f_blocks as u64 * block_size,
+        ))
+    }
+
+    #[cfg(not(unix))]
+    Err(anyhow::anyhow!(
+        "Free space of {} can't be checked on this platform",
+        path.display()
+    ))
+}
+
+fn existing_ancestor(path: &Path) -> PathBuf {
+    let path = if path.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        path
+    };
+    path.ancestors()
+        .find(|p| !p.as_os_str().is_empty() && p.exists())
+        .unwrap_or(Path::new("."))
+        .to_path_buf()
+}
+
+/// Checks that the directories the task will write to have enough free
+/// space. Returns the reason it can't start if not. Directories whose free
+/// space can't be read are assumed to have enough. The output directory is
+/// rendered with the given date, like when the recording is moved there.
+pub fn check(config: &Config, task: &Task, date: &DateTime<Utc>) -> Option<String> {
+    let disk_space = &config.ytarchive.disk_space;
+    let working_directory = task
+        .overrides
+        .working_directory
+        .as_ref()
+        .unwrap_or(&config.ytarchive.working_directory);
+    let output_directory =
+        outpath::render(&task.output_directory, task, &date.with_timezone(&Local))
+            .unwrap_or_else(|_| PathBuf::from(&task.output_directory));
+
+    [
+        (
+            "working",
+            PathBuf::from(working_directory),
+            disk_space.min_free_working().ok().flatten(),
+        ),
+        (
+            "output",
+            output_directory,
+            disk_space.min_free_output().ok().flatten(),
+        ),
+    ]
+    .into_iter()
+    .find_map(|(kind, path, min_free)| {
+        let min_free = min_free?;
+        let free = match space(&path) {
+            Ok((free, _)) => free,
+            Err(e) => {
+                debug!("{:?}", e);
+                return None;
+            }
+        };
+        (free < min_free).then(|| {
+            format!(
+                "Low disk space in the {} directory {}: {} free, {} required",
+                kind,
+                path.display(),
+                format_size(free),
+                format_size(min_free),
+            )
+        })
+    })
+}
+
+/// Reports the free space of the working directory, the working directory
+/// overrides and the output directory of each channel.
+pub fn usage(config: &Config) -> Vec<DiskUsage> {
+    let disk_space = &config.ytarchive.disk_space;
+    let channels = config.channels();
+
+    let working = std::iter::once(&config.ytarchive.working_directory)
+        .chain(
+            channels
+                .iter()
+                .filter_map(|c| c.overrides.working_directory.as_ref()),
+        )
+        .map(|path| {
+            (
+                "working",
+                path,
+                disk_space.min_free_working().ok().flatten(),
+            )
+        });
+    let output = channels.iter().map(|c| {
+        (
+            "output",
+            &c.outpath,
+            disk_space.min_free_output().ok().flatten(),
+        )
+    });
+
+    let mut usage: Vec<DiskUsage> = vec![];
+    for (kind, path, min_free_bytes) in working.chain(output) {
+        if usage.iter().any(|u| u.kind == kind && &u.path == path) {
+            continue;
+        }
+        let space = space(Path::new(path)).map_err(|e| debug!("{:?}", e)).ok();
+        usage.push(DiskUsage {
+            path: path.clone(),
+            kind: kind.to_string(),
+            free_bytes: space.map(|(free, _)| free),
+            total_bytes: space.map(|(_, total)| total),
+            min_free_bytes,
+        });
+    }
+    usage
+}
+
+fn format_size(bytes: u64) -> String {
+    format!("{:.2}GiB", bytes as f64 / (1u64 << 30) as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn task(output_directory: &str) -> Task {
+        Task {
+            output_directory: output_directory.into(),
+            ..Task::test("IKKar5SS29E")
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_check() {
+        let dir = TempDir::new().unwrap();
+        let mut config = Config::default();
+        config.ytarchive.working_directory = dir.path().to_string_lossy().into_owned();
+        let output = dir.path().join("{channel_name}/{date:%Y}");
+        let task = task(&output.to_string_lossy());
+        let date = "2020-06-15T12:00:00Z".parse().unwrap();
+
+        // Directories that don't exist yet are checked through their parent
+        let (free, total) = space(&dir.path().join("missing/dir")).unwrap();
+        assert!(free <= total);
+
+        assert_eq!(check(&config, &task, &date), None);
+
+        config.ytarchive.disk_space.min_free_working = Some("1B".into());
+        assert_eq!(check(&config, &task, &date), None);
+
+        config.ytarchive.disk_space.min_free_output = Some("1000000TiB".into());
+        let reason = check(&config, &task, &date).unwrap();
+        assert!(reason.contains("output directory"), "{}", reason);
+        // The output directory is rendered with the given date
+        assert!(reason.contains("Channel/2020"), "{}", reason);
+
+        config.ytarchive.disk_space.min_free_output = None;
+        config.ytarchive.disk_space.min_free_working = Some("1000000TiB".into());
+        let reason = check(&config, &task, &date).unwrap();
+        assert!(reason.contains("working directory"), "{}", reason);
+    }
+}
@@ -0,0 +1,401 @@
+use super::{outpath, YTArchive};
+use crate::config::{Config, JanitorConfig};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Serialize;
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+use ts_rs::TS;
+
+/// A file in a working directory that doesn't belong to any active task.
+#[derive(Debug, Clone, TS, Serialize)]
+#[ts(export)]
+pub struct Orphan {
+    pub path: String,
+    #[ts(type = "number")]
+    pub size_bytes: u64,
+    pub modified: DateTime<Utc>,
+}
+
+/// Returns the working directory and the working directory overrides of the
+/// channels, without duplicates.
+pub fn working_directories(config: &Config) -> Vec<PathBuf> {
+    let mut dirs = vec![PathBuf::from(&config.ytarchive.working_directory)];
+    for channel in config.channels() {
+        if let Some(dir) = channel.overrides.working_directory {
+            let dir = PathBuf::from(dir);
+            if !dirs.contains(&dir) {
+                dirs.push(dir);
+            }
+        }
+    }
+    dirs
+}
+
+/// Returns the files and directories hoshinova keeps that may be inside a
+/// working directory, e.g. when it's `.`: the quarantine directory, the
+/// output directories of the channels up to their first placeholder, the task
+/// logs, the task store, the files of scraped videos, the config file and any
+/// file passed to ytarchive or yt-dlp, such as cookies.
+pub fn protected_paths(config: &Config) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = config
+        .channels()
+        .iter()
+        .map(|channel| {
+            Path::new(&channel.outpath)
+                .components()
+                .take_while(|c| !c.as_os_str().to_string_lossy().contains('{'))
+                .collect()
+        })
+        .collect();
+    paths.extend(
+        [
+            config
+                .ytarchive
+                .janitor
+                .as_ref()
+                .and_then(|janitor| janitor.quarantine_directory.clone()),
+            Some(config.ytarchive.task_log.directory.clone()),
+            config.store.as_ref().map(|store| store.path.clone()),
+            Some(config.config_path().to_string()).filter(|path| !path.is_empty()),
+            config.scraper.rss.scraped_path.clone(),
+            config
+                .scraper
+                .channel_page
+                .as_ref()
+                .and_then(|channel_page| channel_page.scraped_path.clone()),
+        ]
+        .into_iter()
+        .flatten()
+        .map(PathBuf::from),
+    );
+
+    // Relative paths in the args are resolved from the working directory of
+    // the task, which may be any of them
+    let channels = config.channels();
+    let args = config
+        .ytarchive
+        .args
+        .iter()
+        .chain(&config.ytdlp.args)
+        .chain(channels.iter().flat_map(|channel| {
+            let overrides = &channel.overrides;
+            overrides
+                .args
+                .iter()
+                .flatten()
+                .chain(&overrides.extra_args)
+                .chain(&overrides.ytdlp_extra_args)
+        }));
+    let dirs = working_directories(config);
+    for arg in args {
+        paths.push(PathBuf::from(arg));
+        paths.extend(dirs.iter().map(|dir| dir.join(arg)));
+    }
+    paths
+}
+
+/// Returns whether a file name looks like a leftover of ytarchive or yt-dlp:
+/// it contains a video ID and ends with one of their temporary or partial
+/// file extensions.
+fn is_leftover(name: &str) -> bool {
+    lazy_static! {
+        static ref VIDEO_ID_RE: Regex =
+            Regex::new(r"(?:^|[^0-9A-Za-z_-])[0-9A-Za-z_-]{11}(?:[^0-9A-Za-z_-]|$)")
+                .expect("Failed to compile video ID regex");
+        // ytarchive: .ts, .f299.ts, .frag, .tmp
+        // yt-dlp: .part, .part-Frag12, .ytdl, .temp.mp4, .f299.mp4
+        static ref EXTENSION_RE: Regex = Regex::new(
+            r"(?:\.ts|\.frag\d*|\.tmp|\.part|\.part-Frag\d+(?:\.part)?|\.ytdl|\.temp\.\w+|\.f\d+(?:-\w+)?\.\w+)$"
+        )
+        .expect("Failed to compile leftover extension regex");
+    }
+    EXTENSION_RE.is_match(name) && VIDEO_ID_RE.is_match(name)
+}
+
+/// Finds the leftovers of ytarchive and yt-dlp in the directories that were
+/// last modified more than `min_age` ago and whose path doesn't contain the
+/// video ID of any active task. The files and directories in `exclude` are
+/// skipped.
+pub fn find_orphans(
+    dirs: &[PathBuf],
+    active_ids: &HashSet<String>,
+    min_age: Duration,
+    exclude: &[PathBuf],
+) -> Vec<Orphan> {
+    // Paths that don't exist yet have nothing to protect
+    let exclude: HashSet<PathBuf> = exclude
+        .iter()
+        .filter_map(|path| path.canonicalize().ok())
+        .collect();
+    let is_excluded = |path: &Path| {
+        path.canonicalize()
+            .is_ok_and(|path| exclude.contains(&path))
+    };
+    let now = SystemTime::now();
+    let mut orphans = vec![];
+    let mut pending: Vec<PathBuf> = dirs.to_vec();
+    while let Some(dir) = pending.pop() {
+        if is_excluded(&dir) {
+            continue;
+        }
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                debug!("Failed to read {}: {:?}", dir.display(), e);
+                continue;
+            }
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            // Symlinks are not followed
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if metadata.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            if !metadata.is_file() || is_excluded(&path) {
+                continue;
+            }
+            let is_leftover = path
+                .file_name()
+                .is_some_and(|name| is_leftover(&name.to_string_lossy()));
+            if !is_leftover {
+                continue;
+            }
+
+            let name = path.to_string_lossy();
+            if active_ids.iter().any(|id| name.contains(id.as_str())) {
+                continue;
+            }
+            let Ok(modified) = metadata.modified() else {
+                continue;
+            };
+            if now.duration_since(modified).unwrap_or_default() < min_age {
+                continue;
+            }
+
+            orphans.push(Orphan {
+                path: name.into_owned(),
+                size_bytes: metadata.len(),
+                modified: modified.into(),
+            });
+        }
+    }
+    orphans.sort_by(|a, b| a.path.cmp(&b.path));
+    orphans
+}
+
+/// Deletes the orphaned files in the working directories, or moves them to
+/// the quarantine directory if one is set. Returns the files found. The
+/// paths in `exclude` are never touched.
+pub fn clean(
+    cfg: &JanitorConfig,
+    dirs: &[PathBuf],
+    exclude: &[PathBuf],
+    active_ids: &HashSet<String>,
+) -> Result<Vec<Orphan>> {
+    let quarantine = cfg.quarantine_directory.as_ref().map(PathBuf::from);
+    let orphans = find_orphans(dirs, active_ids, cfg.min_age, exclude);
+    if orphans.is_empty() {
+        return Ok(orphans);
+    }
+
+    let size: u64 = orphans.iter().map(|o| o.size_bytes).sum();
+    if cfg.dry_run {
+        for orphan in &orphans {
+            info!("[janitor] Would remove {}", orphan.path);
+        }
+        info!(
+            "[janitor] Found {} orphaned files ({} bytes), not removing them in dry-run mode",
+            orphans.len(),
+            size
+        );
+        return Ok(orphans);
+    }
+
+    if let Some(quarantine) = &quarantine {
+        fs::create_dir_all(quarantine).with_context(|| {
+            format!(
+                "Failed to create quarantine directory: {}",
+                quarantine.display()
+            )
+        })?;
+    }
+    for orphan in &orphans {
+        let path = Path::new(&orphan.path);
+        let result = match &quarantine {
+            Some(quarantine) => {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                let dest = outpath::unique_path(&quarantine.join(name.as_ref()));
+                YTArchive::move_file("[janitor]", path, &dest)
+                    .map(|()| info!("[janitor] Moved {} to {}", orphan.path, dest.display()))
+            }
+            None => fs::remove_file(path)
+                .with_context(|| format!("Failed to remove {}", orphan.path))
+                .map(|()| info!("[janitor] Removed {}", orphan.path)),
+        };
+        if let Err(e) = result {
+            warn!("[janitor] {:?}", e);
+        }
+    }
+    info!(
+        "[janitor] Cleaned up {} orphaned files ({} bytes)",
+        orphans.len(),
+        size
+    );
+    Ok(orphans)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write_old(path: &Path) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let file = fs::File::create(path).unwrap();
+        let modified = SystemTime::now() - Duration::from_secs(2 * 60 * 60);
+        file.set_modified(modified).unwrap();
+    }
+
+    #[test]
+    fn test_clean() {
+        let dir = TempDir::new().unwrap();
+        let working = dir.path().join("temp");
+        let quarantine = working.join("quarantine");
+        write_old(&working.join("old (IKKar5SS29E).f299.ts"));
+        write_old(&working.join("sub/old (AAAAAAAAAAA).mp4.part"));
+        write_old(&working.join("running (BBBBBBBBBBB).f140.ts"));
+        fs::write(working.join("new (CCCCCCCCCCC).f299.ts"), "").unwrap();
+        write_old(&working.join("done (DDDDDDDDDDD).mp4"));
+
+        let dirs = vec![working.clone()];
+        let exclude = vec![quarantine.clone()];
+        let active_ids = HashSet::from(["BBBBBBBBBBB".to_string()]);
+        let mut cfg = JanitorConfig {
+            min_age: Duration::from_secs(60 * 60),
+            quarantine_directory: Some(quarantine.to_string_lossy().into_owned()),
+            dry_run: true,
+            ..Default::default()
+        };
+
+        // Nothing is touched in dry-run mode
+        let orphans = clean(&cfg, &dirs, &exclude, &active_ids).unwrap();
+        let names: Vec<_> = orphans
+            .iter()
+            .map(|o| Path::new(&o.path).file_name().unwrap().to_owned())
+            .collect();
+        assert_eq!(
+            names,
+            vec!["old (IKKar5SS29E).f299.ts", "old (AAAAAAAAAAA).mp4.part"]
+        );
+        assert!(working.join("old (IKKar5SS29E).f299.ts").exists());
+
+        cfg.dry_run = false;
+        assert_eq!(clean(&cfg, &dirs, &exclude, &active_ids).unwrap().len(), 2);
+        assert!(!working.join("old (IKKar5SS29E).f299.ts").exists());
+        assert!(quarantine.join("old (IKKar5SS29E).f299.ts").exists());
+        assert!(quarantine.join("old (AAAAAAAAAAA).mp4.part").exists());
+        assert!(working.join("running (BBBBBBBBBBB).f140.ts").exists());
+        assert!(working.join("new (CCCCCCCCCCC).f299.ts").exists());
+        assert!(working.join("done (DDDDDDDDDDD).mp4").exists());
+
+        // Quarantined files are not picked up again, and are deleted without
+        // a quarantine directory
+        assert!(clean(&cfg, &dirs, &exclude, &active_ids)
+            .unwrap()
+            .is_empty());
+        cfg.quarantine_directory = None;
+        let dirs = vec![quarantine.clone()];
+        assert_eq!(clean(&cfg, &dirs, &[], &active_ids).unwrap().len(), 2);
+        assert!(!quarantine.join("old (AAAAAAAAAAA).mp4.part").exists());
+    }
+
+    #[test]
+    fn test_is_leftover() {
+        for name in [
+            "IKKar5SS29E.f299.ts",
+            "live [Channel] (IKKar5SS29E).ts",
+            "live (IKKar5SS29E).frag12",
+            "live (IKKar5SS29E).tmp",
+            "live (IKKar5SS29E).mp4.part",
+            "live (IKKar5SS29E).mp4.part-Frag12.part",
+            "live (IKKar5SS29E).mp4.ytdl",
+            "live (IKKar5SS29E).temp.mp4",
+            "live (IKKar5SS29E).f251-drc.webm",
+        ] {
+            assert!(is_leftover(name), "{}", name);
+        }
+        for name in [
+            "live (IKKar5SS29E).mp4",
+            "live (IKKar5SS29E).info.json",
+            "notes.txt.part",
+            "live (IKKar5SS29E_toolong).f299.ts",
+        ] {
+            assert!(!is_leftover(name), "{}", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_protected_paths() {
+        let dir = TempDir::new().unwrap();
+        let path = |p: &str| dir.path().join(p).to_string_lossy().into_owned();
+
+        // The config file may be in the working directory too
+        let source = toml::to_string(&Config::default()).unwrap();
+        fs::write(dir.path().join("config_2024.tmp"), source).unwrap();
+        fs::File::options()
+            .write(true)
+            .open(dir.path().join("config_2024.tmp"))
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(2 * 60 * 60))
+            .unwrap();
+        let mut config = crate::config::load_config(&path("config_2024.tmp"))
+            .await
+            .unwrap();
+        config.ytarchive.working_directory = path("");
+        config.ytarchive.args = vec!["--cookies".into(), "cookies-bak.tmp".into()];
+        config.ytarchive.task_log.directory = path("logs");
+        config.store = Some(crate::config::StoreConfig {
+            path: path("store.json"),
+        });
+        config.scraper.rss.scraped_path = Some(path("scraped.json"));
+        config.channel.push(
+            toml::from_str(&format!(
+                "id = \"UC\"\nname = \"Channel\"\nfilters = []\noutpath = \"{}\"",
+                path("videos/{channel_name}")
+            ))
+            .unwrap(),
+        );
+        let protected = protected_paths(&config);
+        assert!(protected.contains(&dir.path().join("videos")));
+
+        write_old(&dir.path().join("videos/Channel/done (IKKar5SS29E).f299.ts"));
+        write_old(&dir.path().join("logs/IKKar5SS29E.tmp"));
+        write_old(&dir.path().join("cookies-bak.tmp"));
+        write_old(&dir.path().join("old (AAAAAAAAAAA).mp4.part"));
+
+        // Only the leftover recording is an orphan
+        let orphans = find_orphans(
+            &working_directories(&config),
+            &HashSet::new(),
+            Duration::from_secs(60 * 60),
+            &protected,
+        );
+        let names: Vec<_> = orphans
+            .iter()
+            .map(|o| Path::new(&o.path).file_name().unwrap().to_owned())
+            .collect();
+        assert_eq!(names, vec!["old (AAAAAAAAAAA).mp4.part"]);
+    }
+}
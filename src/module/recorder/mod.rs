@@ -0,0 +1,1870 @@
+use super::{Message, Module, Notification, QueueStatus, Task, TaskOutput, TaskStatus};
+use crate::{config::Config, module::RecordingStatus};
+use crate::{metrics, msgbus::BusTx};
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc,
+    },
+};
+use tokio::{
+    io::{AsyncReadExt, BufReader},
+    select,
+    sync::{broadcast, mpsc, watch, Notify, RwLock},
+    time::Instant,
+};
+use ts_rs::TS;
+
+mod backend;
+pub mod disk;
+pub mod janitor;
+pub mod outpath;
+mod queue;
+pub mod tasklog;
+
+pub use backend::BackendKind;
+use queue::PriorityQueue;
+use tasklog::TaskLog;
+
+/// The endings of the files that are moved along with a recording.
+const SIDECAR_SUFFIXES: &[&str] = &[
+    ".jpg",
+    ".webp",
+    ".png",
+    ".description",
+    ".info.json",
+    ".live_chat.json",
+];
+
+pub struct YTArchive {
+    config: Arc<RwLock<Config>>,
+    active_ids: Arc<RwLock<HashMap<String, ActiveTask>>>,
+    output: broadcast::Sender<TaskOutput>,
+}
+
+/// A task that is currently being recorded.
+struct ActiveTask {
+    task: Task,
+    /// Set to true to stop the recording.
+    cancel: watch::Sender<bool>,
+    run: Arc<RunState>,
+}
+
+/// What a running task shares with the queue.
+#[derive(Default)]
+struct RunState {
+    /// The current attempt number.
+    attempt: AtomicU32,
+    /// The pid of the running process, which leads its own process group,
+    /// or 0 if there is none.
+    pid: AtomicU32,
+    /// The date the output directory is rendered with, once it's known.
+    date: std::sync::Mutex<Option<DateTime<Utc>>>,
+}
+
+/// What a task being recorded needs from the module.
+#[derive(Clone)]
+struct RecordContext {
+    run: Arc<RunState>,
+    /// The tasks being recorded, to tell their files apart.
+    active_ids: Arc<RwLock<HashMap<String, ActiveTask>>>,
+    output: broadcast::Sender<TaskOutput>,
+}
+
+/// Asks the process to exit gracefully. On unix this sends a SIGINT, which
+/// ytarchive handles by muxing what has been downloaded so far.
+fn interrupt(process: &mut tokio::process::Child) -> Result<()> {
+    #[cfg(unix)]
+    {
+        let pid = process.id().ok_or(anyhow!("Process has already exited"))?;
+        // SAFETY: kill only sends a signal to the given pid
+        if unsafe { libc::kill(pid as libc::pid_t, libc::SIGINT) } != 0 {
+            return Err(std::io::Error::last_os_error()).context("Failed to send SIGINT");
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    process.start_kill().context("Failed to kill process")
+}
+
+/// Kills the process along with the ones it started, such as ffmpeg, for when
+/// it doesn't respond anymore.
+fn kill(process: &mut tokio::process::Child) -> Result<()> {
+    #[cfg(unix)]
+    {
+        let pid = process.id().ok_or(anyhow!("Process has already exited"))?;
+        kill_group(pid)
+    }
+
+    #[cfg(not(unix))]
+    process.start_kill().context("Failed to kill process")
+}
+
+/// Kills the process group led by the pid.
+#[cfg(unix)]
+fn kill_group(pid: u32) -> Result<()> {
+    // SAFETY: kill only sends a signal to the process group, which was
+    // created for the process when it was started
+    if unsafe { libc::kill(-(pid as libc::pid_t), libc::SIGKILL) } != 0 {
+        return Err(std::io::Error::last_os_error()).context("Failed to send SIGKILL");
+    }
+    Ok(())
+}
+
+impl YTArchive {
+    /// Streams the output of the tasks to the given channel.
+    pub fn with_output(mut self, output: broadcast::Sender<TaskOutput>) -> Self {
+        self.output = output;
+        self
+    }
+
+    async fn record(
+        cfg: Config,
+        task: Task,
+        backend: BackendKind,
+        bus: &mut BusTx<Message>,
+        mut cancel: watch::Receiver<bool>,
+        ctx: &RecordContext,
+    ) -> Result<YTAStatus> {
+        let attempt = ctx.run.attempt.load(Ordering::Relaxed);
+        let task_name = format!("[{}][{}][{}]", task.video_id, task.channel_name, task.title);
+
+        // Ensure the working directory exists
+        let working_directory = task
+            .overrides
+            .working_directory
+            .as_ref()
+            .unwrap_or(&cfg.ytarchive.working_directory);
+        tokio::fs::create_dir_all(working_directory)
+            .await
+            .context("Failed to create working directory")?;
+
+        // Construct the command line
+        let (executable, args) = backend.backend().command(&cfg, &task);
+
+        // Start the process
+        debug!(
+            "{} Starting {} with args {:?}",
+            task_name,
+            backend.as_str(),
+            args
+        );
+        let mut command = tokio::process::Command::new(&executable);
+        command
+            .args(&args)
+            .current_dir(working_directory)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        // Start the backend in its own process group, so that it doesn't
+        // receive the signals meant for hoshinova. Shutdown is handled
+        // separately.
+        #[cfg(unix)]
+        command.process_group(0);
+
+        // Keep the output of the process. Recording goes on without a log
+        // file if it can't be opened.
+        let mut log = TaskLog::open(&cfg.ytarchive.task_log, &task.video_id)
+            .map_err(|e| warn!("{} {:#}", task_name, e))
+            .ok();
+        Self::log_output(
+            &ctx.output,
+            &mut log,
+            &task,
+            &format!(
+                "[hoshinova] Starting {} (attempt {}): {} {:?}",
+                backend.as_str(),
+                attempt,
+                executable,
+                args
+            ),
+        );
+
+        let mut process = command
+            .spawn()
+            .with_context(|| format!("Failed to start {}", backend.as_str()))?;
+        ctx.run
+            .pid
+            .store(process.id().unwrap_or_default(), Ordering::Relaxed);
+
+        // Grab stdout/stderr byte iterators
+        let mut stdout = BufReader::new(
+            process
+                .stdout
+                .take()
+                .ok_or(anyhow!("Failed to take stdout"))?,
+        );
+        let mut stderr = BufReader::new(
+            process
+                .stderr
+                .take()
+                .ok_or(anyhow!("Failed to take stderr"))?,
+        );
+
+        // Create a channel to consolidate stdout and stderr
+        let (tx, mut rx) = mpsc::channel(1);
+
+        // Flag to mark when the process has exited
+        let done = Arc::from(AtomicBool::new(false));
+
+        // Set to true to kill the process when it stalls
+        let (kill_tx, mut kill_rx) = watch::channel(false);
+
+        macro_rules! read_line {
+            ($reader:expr, $tx:expr) => {{
+                // Read bytes until a \r or \n is returned
+                let mut bytes = Vec::new();
+                loop {
+                    match $reader.read_u8().await {
+                        Ok(byte) => {
+                            if byte == b'\r' || byte == b'\n' {
+                                break;
+                            }
+                            bytes.push(byte);
+                        }
+                        _ => break,
+                    }
+                }
+
+                // Skip if there are no bytes
+                if bytes.is_empty() {
+                    continue;
+                }
+
+                // Convert to a string
+                let line = match std::str::from_utf8(&bytes) {
+                    Ok(line) => line.to_owned(),
+                    Err(e) => {
+                        trace!("Failed to read utf8: {:?}", e);
+                        break;
+                    }
+                };
+
+                // Send the line to the channel
+                if let Err(e) = $tx.send(line).await {
+                    trace!("Failed to send line: {:?}", e);
+                    break;
+                }
+            }};
+        }
+
+        // Read stdout
+        let h_stdout = tokio::spawn({
+            let done = done.clone();
+            let task_name = task_name.clone();
+            let tx = tx.clone();
+            async move {
+                while !done.load(Ordering::Relaxed) {
+                    read_line!(&mut stdout, tx);
+                }
+                trace!("{} stdout reader exited", task_name);
+            }
+        });
+
+        // Read stderr
+        let h_stderr = tokio::spawn({
+            let done = done.clone();
+            let task_name = task_name.clone();
+            let tx = tx.clone();
+            async move {
+                while !done.load(Ordering::Relaxed) {
+                    read_line!(&mut stderr, tx);
+                }
+                trace!("{} stderr reader exited", task_name);
+            }
+        });
+
+        // Wait for the process to exit
+        let h_wait = tokio::spawn({
+            let done = done.clone();
+            let task_name = task_name.clone();
+            let run = ctx.run.clone();
+            async move {
+                let result = loop {
+                    select! {
+                        result = process.wait() => break result,
+                        Ok(_) = cancel.changed() => {
+                            if !*cancel.borrow_and_update() {
+                                continue;
+                            }
+                            info!("{} Cancelling recording", task_name);
+                            if let Err(e) = interrupt(&mut process) {
+                                warn!("{} Failed to interrupt ytarchive: {:?}", task_name, e);
+                            }
+                        }
+                        Ok(_) = kill_rx.changed() => {
+                            if let Err(e) = kill(&mut process) {
+                                warn!("{} Failed to kill ytarchive: {:?}", task_name, e);
+                            }
+                        }
+                    }
+                };
+                run.pid.store(0, Ordering::Relaxed);
+
+                if let Ok(exit) = &result {
+                    let code = exit
+                        .code()
+                        .map(|code| code.to_string())
+                        .unwrap_or_else(|| "signal".to_string());
+                    metrics::YTARCHIVE_EXITS.inc(&[("code", &code)]);
+                }
+
+                // Wait a bit for the stdout to be completely read
+                tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+                // Stop threads
+                done.store(true, Ordering::Relaxed);
+                debug!("{} Process exited with {:?}", task_name, result);
+
+                // Send a blank message to unblock the status monitor thread
+                let _ = tx.send("".into());
+
+                result
+            }
+        });
+
+        // Parse each line
+        let mut status = YTAStatus::new();
+        status.attempt = attempt;
+        status.backend = backend;
+        let mut last_progress = Instant::now();
+        let mut stalled = None;
+        loop {
+            // Give up on the process if it takes too long to make progress
+            let timeout = cfg.ytarchive.stall_timeout.for_state(&status.state);
+            let deadline = timeout.map(|timeout| last_progress + timeout);
+            let line = select! {
+                line = rx.recv() => match line {
+                    Some(line) => line,
+                    None => break,
+                },
+                _ = tokio::time::sleep_until(deadline.unwrap_or(last_progress)), if deadline.is_some() => {
+                    let reason = format!(
+                        "{} stalled: no progress for {} while {}",
+                        backend.as_str(),
+                        humantime::format_duration(timeout.unwrap_or_default()),
+                        status.state.as_str(),
+                    );
+                    warn!("{} {}, killing it", task_name, reason);
+                    let _ = kill_tx.send(true);
+                    stalled = Some(reason);
+                    break;
+                }
+            };
+
+            // Stop when done
+            if done.load(Ordering::Relaxed) {
+                break;
+            }
+
+            trace!("{}[yta:out] {}", task_name, line);
+            Self::log_output(&ctx.output, &mut log, &task, &line);
+
+            let old = status.clone();
+            backend.backend().parse_line(&mut status, &line);
+            if status.state == YTAState::Recording && status.started_at.is_none() {
+                status.started_at = Some(Utc::now());
+            }
+            if let YTAState::Waiting(Some(start)) = status.state {
+                status.scheduled_start = Some(start);
+            }
+            if status.recording_date() != old.recording_date() {
+                *ctx.run.date.lock().unwrap() = status.recording_date();
+            }
+            if !status.state.is_active() && status.finished_at.is_none() {
+                status.finished_at = Some(Utc::now());
+            }
+
+            if status.made_progress(&old) {
+                last_progress = Instant::now();
+            }
+
+            // Push the current status to the bus
+            if let Err(_) = bus
+                .send(Message::RecordingStatus(RecordingStatus {
+                    task: task.clone(),
+                    status: status.clone(),
+                }))
+                .await
+            {
+                break;
+            }
+
+            // Check if status changed
+            if old.state == status.state {
+                continue;
+            }
+
+            let notify = match status.state {
+                YTAState::Waiting(_) => {
+                    info!("{} Waiting for stream to go live", task_name);
+                    Some(TaskStatus::Waiting)
+                }
+                YTAState::Recording => {
+                    info!("{} Recording started", task_name);
+                    Some(TaskStatus::Recording)
+                }
+                YTAState::Finished => {
+                    // Notified once the file is in its final location
+                    info!("{} Recording finished", task_name);
+                    None
+                }
+                YTAState::AlreadyProcessed => {
+                    info!("{} Video already processed, skipping", task_name);
+                    None
+                }
+                YTAState::Interrupted => {
+                    info!("{} Recording failed: interrupted", task_name);
+                    Some(TaskStatus::Failed)
+                }
+                YTAState::Errored => {
+                    info!("{} Recording failed: errored", task_name);
+                    Some(TaskStatus::Failed)
+                }
+                _ => None,
+            };
+            let message = notify.map(|notify| {
+                Message::ToNotify(Notification {
+                    task: task.clone(),
+                    status: notify,
+                    attempt,
+                    yta_status: Some(status.clone()),
+                    message: None,
+                })
+            });
+
+            if let Some(message) = message {
+                // Exit the loop if message failed to send
+                if let Err(_) = bus.send(message).await {
+                    break;
+                }
+            }
+        }
+
+        trace!("{} Status loop exited: {:?}", task_name, status);
+
+        // Wait for threads to finish
+        drop(rx);
+        let (r_wait, r_stdout, r_stderr) = futures::join!(h_wait, h_stdout, h_stderr);
+        trace!("{} Process monitor exited: {:?}", task_name, r_wait);
+        trace!("{} Stdout monitor quit: {:?}", task_name, r_stdout);
+        trace!("{} Stderr monitor quit: {:?}", task_name, r_stderr);
+
+        let exit = match (&stalled, r_wait) {
+            (Some(reason), _) => format!("[hoshinova] {}, killed it", reason),
+            (None, Ok(Ok(exit))) => {
+                format!("[hoshinova] {} exited with {}", backend.as_str(), exit)
+            }
+            (None, result) => format!("[hoshinova] {} exited: {:?}", backend.as_str(), result),
+        };
+        Self::log_output(&ctx.output, &mut log, &task, &exit);
+
+        if let Some(reason) = stalled {
+            status.state = YTAState::Stalled;
+            status.finished_at = Some(Utc::now());
+            bus.send(Message::RecordingStatus(RecordingStatus {
+                task: task.clone(),
+                status: status.clone(),
+            }))
+            .await
+            .context("Failed to send recording status")?;
+            bus.send(Message::ToNotify(Notification {
+                task,
+                status: TaskStatus::Failed,
+                attempt,
+                yta_status: Some(status.clone()),
+                message: Some(reason),
+            }))
+            .await
+            .context("Failed to send notification")?;
+            return Ok(status);
+        }
+
+        // Skip moving files if it didn't finish
+        if status.state != YTAState::Finished {
+            return Ok(status);
+        }
+
+        // The output file may be relative to the working directory
+        if let Some(output_file) = &status.output_file {
+            let output_file = Path::new(working_directory).join(output_file);
+            status.output_file = Some(output_file.to_string_lossy().into_owned());
+        }
+
+        // Move the video to the output directory, leaving the files of the
+        // other recordings alone
+        let others: HashSet<String> = ctx
+            .active_ids
+            .read()
+            .await
+            .keys()
+            .filter(|video_id| **video_id != task.video_id)
+            .cloned()
+            .collect();
+        let destpath = match Self::move_output(&task_name, &task, &status, &others) {
+            Ok(destpath) => destpath,
+            Err(e) => {
+                bus.send(Message::ToNotify(Notification {
+                    task,
+                    status: TaskStatus::Failed,
+                    attempt,
+                    yta_status: Some(status),
+                    message: Some(format!("{:#}", e)),
+                }))
+                .await
+                .context("Failed to send notification")?;
+                return Err(e);
+            }
+        };
+
+        // Report the final location of the file
+        status.output_file = Some(destpath.to_string_lossy().into_owned());
+        if let Err(e) = Self::write_info_json(&destpath, &task, &status) {
+            error!("{} {:#}", task_name, e);
+            bus.send(Message::ToNotify(Notification {
+                task: task.clone(),
+                status: TaskStatus::Warning,
+                attempt,
+                yta_status: Some(status.clone()),
+                message: Some(format!("{:#}", e)),
+            }))
+            .await
+            .context("Failed to send notification")?;
+        }
+        bus.send(Message::RecordingStatus(RecordingStatus {
+            task: task.clone(),
+            status: status.clone(),
+        }))
+        .await
+        .context("Failed to send final recording status")?;
+        bus.send(Message::ToNotify(Notification {
+            task,
+            status: TaskStatus::Done,
+            attempt,
+            yta_status: Some(status.clone()),
+            message: None,
+        }))
+        .await
+        .context("Failed to send notification")?;
+
+        Ok(status)
+    }
+
+    /// Writes a line to the log file of the task and streams it to the
+    /// output channel.
+    fn log_output(
+        output: &broadcast::Sender<TaskOutput>,
+        log: &mut Option<TaskLog>,
+        task: &Task,
+        line: &str,
+    ) {
+        let line = tasklog::timestamped(line);
+        if let Some(file) = log {
+            if let Err(e) = file.write(&line) {
+                // Don't try again for every line
+                warn!("[{}] {:#}, no longer writing its log", task.video_id, e);
+                *log = None;
+            }
+        }
+        // Sending only fails if nobody is listening
+        let _ = output.send(TaskOutput {
+            video_id: task.video_id.clone(),
+            line,
+        });
+    }
+
+    /// Moves the finished recording into the task's output directory and
+    /// returns its new path. The output directory is rendered from the task's
+    /// template, and the file gets a suffix instead of replacing an existing
+    /// one. Sidecar files, such as the thumbnail or description, are moved
+    /// along with it and renamed to match.
+    fn move_output(
+        task_name: &str,
+        task: &Task,
+        status: &YTAStatus,
+        others: &HashSet<String>,
+    ) -> Result<PathBuf> {
+        let frompath = status
+            .output_file
+            .clone()
+            .ok_or(anyhow!("ytarchive did not emit an output file"))?;
+        let frompath = Path::new(&frompath);
+        let filename = frompath
+            .file_name()
+            .ok_or(anyhow!("Failed to get filename"))?;
+
+        let date = status.recording_date().unwrap_or_else(Utc::now);
+        let output_directory = outpath::render(
+            &task.output_directory,
+            task,
+            &date.with_timezone(&chrono::Local),
+        )
+        .context("Failed to render output directory")?;
+        fs::create_dir_all(&output_directory).with_context(|| {
+            format!(
+                "Failed to create output directory: {}",
+                output_directory.display()
+            )
+        })?;
+        let destpath = outpath::unique_path(
+            &output_directory.join(outpath::sanitize(&filename.to_string_lossy())),
+        );
+
+        // Look for the sidecars before the video is gone
+        let sidecars = Self::find_sidecars(frompath, others).unwrap_or_else(|e| {
+            warn!("{} Failed to look for sidecar files: {:?}", task_name, e);
+            vec![]
+        });
+
+        Self::move_file(task_name, frompath, &destpath)?;
+        info!("{} Moved output file to {}", task_name, destpath.display());
+
+        // Sidecars keep whatever follows the stem of the video, so that they
+        // still match it if it got a suffix
+        let stem = Self::file_stem(frompath);
+        let dest_stem = Self::file_stem(&destpath);
+        for sidecar in sidecars {
+            let name = sidecar.file_name().unwrap_or_default().to_string_lossy();
+            let sidecar_dest = outpath::unique_path(&output_directory.join(outpath::sanitize(
+                &format!("{}{}", dest_stem, &name[stem.len()..]),
+            )));
+            match Self::move_file(task_name, &sidecar, &sidecar_dest) {
+                Ok(()) => info!("{} Moved sidecar to {}", task_name, sidecar_dest.display()),
+                Err(e) => warn!("{} Failed to move sidecar: {:?}", task_name, e),
+            }
+        }
+
+        Ok(destpath)
+    }
+
+    fn file_stem(path: &Path) -> String {
+        path.file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    /// Finds the other files ytarchive wrote for the video, which share its
+    /// file name up to the extension, e.g. `title.jpg` and
+    /// `title.description` for `title.mp4`. Only known kinds of sidecars are
+    /// picked up, and never ones that mention the video ID of another
+    /// recording in `others`, whose file name may start the same way.
+    fn find_sidecars(video: &Path, others: &HashSet<String>) -> Result<Vec<PathBuf>> {
+        let stem = format!("{}.", Self::file_stem(video));
+        let dir = match video.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+
+        let mut sidecars = vec![];
+        for entry in fs::read_dir(dir).context("Failed to read working directory")? {
+            let entry = entry?;
+            let path = entry.path();
+            if path == video || !entry.file_type()?.is_file() {
+                continue;
+            }
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with(&stem)
+                && SIDECAR_SUFFIXES.iter().any(|suffix| name.ends_with(suffix))
+                && !others
+                    .iter()
+                    .any(|video_id| name.contains(video_id.as_str()))
+            {
+                sidecars.push(path);
+            }
+        }
+        sidecars.sort();
+        Ok(sidecars)
+    }
+
+    /// Moves a file, falling back to copying it if it can't be renamed, e.g.
+    /// because it's on another file system.
+    fn move_file(task_name: &str, frompath: &Path, destpath: &Path) -> Result<()> {
+        if fs::rename(frompath, destpath).is_ok() {
+            return Ok(());
+        }
+        debug!(
+            "{} Failed to rename {} to output, trying to copy",
+            task_name,
+            frompath.display(),
+        );
+
+        fs::copy(frompath, destpath)
+            .with_context(|| format!("Failed to copy file to output: {:?}", destpath))?;
+        info!(
+            "{} Copied {} to {}, removing original",
+            task_name,
+            frompath.display(),
+            destpath.display(),
+        );
+        fs::remove_file(frompath)
+            .with_context(|| format!("Failed to remove original file: {:?}", frompath))
+    }
+
+    /// Writes the task and its final status next to the recording, as
+    /// `<name>.hoshinova.json`, so that it doesn't replace an `.info.json`
+    /// written by the backend.
+    fn write_info_json(video: &Path, task: &Task, status: &YTAStatus) -> Result<PathBuf> {
+        #[derive(Serialize)]
+        struct Info<'a> {
+            task: &'a Task,
+            status: &'a YTAStatus,
+        }
+
+        let path = video.with_file_name(format!("{}.hoshinova.json", Self::file_stem(video)));
+        let contents = serde_json::to_vec_pretty(&Info { task, status })
+            .context("Failed to serialize info")?;
+        fs::write(&path, contents)
+            .with_context(|| format!("Failed to write info file: {}", path.display()))?;
+        Ok(path)
+    }
+}
+
+impl YTArchive {
+    /// Records the task with each of its backends in turn, moving on to the
+    /// next one when the previous one ends in a state listed in
+    /// `fallback_on`, e.g. when a stream can only be downloaded as a VOD.
+    /// Starts from where `retry` left off if set. Returns where to pick up
+    /// again if the task should be retried after a backoff, and otherwise
+    /// sends the last status again, marked as final.
+    async fn record_with_fallback(
+        cfg: Config,
+        task: Task,
+        bus: &mut BusTx<Message>,
+        cancel: watch::Receiver<bool>,
+        ctx: RecordContext,
+        retry: Option<Retry>,
+    ) -> Result<Option<Retry>> {
+        let backends = task
+            .overrides
+            .backends
+            .clone()
+            .unwrap_or_else(|| cfg.ytarchive.backends.clone());
+        let (first, mut attempt) = retry.map_or((0, 1), |retry| (retry.backend, retry.attempt));
+        let mut last = None;
+        for (i, backend) in backends.iter().enumerate().skip(first) {
+            let result = YTArchive::record_attempt(
+                cfg.clone(),
+                task.clone(),
+                *backend,
+                bus,
+                cancel.clone(),
+                &ctx,
+                attempt,
+            )
+            .await;
+            let status = match result {
+                Ok(Attempt::Done(status)) => *status,
+                Ok(Attempt::Retry(delay)) => {
+                    return Ok(Some(Retry {
+                        backend: i,
+                        attempt: attempt + 1,
+                        not_before: Instant::now() + delay,
+                    }))
+                }
+                Err(e) => {
+                    let mut status = YTAStatus::new();
+                    status.state = YTAState::Errored;
+                    status.attempt = attempt;
+                    status.backend = *backend;
+                    Self::send_final_status(bus, task, status).await;
+                    return Err(e);
+                }
+            };
+            attempt = 1;
+
+            let next = backends
+                .get(i + 1)
+                .filter(|_| !*cancel.borrow() && cfg.ytarchive.should_fall_back(&status.state));
+            if let Some(next) = next {
+                info!(
+                    "[{}][{}][{}] {} ended as {}, falling back to {}",
+                    task.video_id,
+                    task.channel_name,
+                    task.title,
+                    backend.as_str(),
+                    status.state.as_str(),
+                    next.as_str(),
+                );
+            }
+            last = Some(status);
+            if next.is_none() {
+                break;
+            }
+        }
+
+        let status = last.unwrap_or_else(YTAStatus::new);
+        Self::send_final_status(bus, task, status).await;
+        Ok(None)
+    }
+
+    /// Lets the other modules know that the recorder is done with the task,
+    /// which won't be retried or recorded with another backend anymore.
+    async fn send_final_status(bus: &mut BusTx<Message>, task: Task, mut status: YTAStatus) {
+        status.is_final = true;
+        let _ = bus
+            .send(Message::RecordingStatus(RecordingStatus { task, status }))
+            .await;
+    }
+
+    /// Records the task once, and tells whether it should be started again
+    /// according to the retry policy. Never retries once cancelled.
+    async fn record_attempt(
+        cfg: Config,
+        task: Task,
+        backend: BackendKind,
+        bus: &mut BusTx<Message>,
+        cancel: watch::Receiver<bool>,
+        ctx: &RecordContext,
+        attempt: u32,
+    ) -> Result<Attempt> {
+        let retry = cfg.ytarchive.retry.clone();
+        ctx.run.attempt.store(attempt, Ordering::Relaxed);
+        let status =
+            YTArchive::record(cfg, task.clone(), backend, bus, cancel.clone(), ctx).await?;
+
+        if *cancel.borrow() || !retry.should_retry(&status.state, attempt) {
+            return Ok(Attempt::Done(Box::new(status)));
+        }
+
+        let delay = retry.backoff(attempt);
+        info!(
+            "[{}][{}][{}] Retrying in {} (attempt {}/{})",
+            task.video_id,
+            task.channel_name,
+            task.title,
+            humantime::format_duration(delay),
+            attempt + 1,
+            retry.max_attempts,
+        );
+        Ok(Attempt::Retry(delay))
+    }
+}
+
+/// How an attempt to record a task ended.
+enum Attempt {
+    /// The backend is done with the task, and this is its last status.
+    Done(Box<YTAStatus>),
+    /// The task should be started again after the given delay.
+    Retry(std::time::Duration),
+}
+
+/// Where to pick up a task that's waiting in the queue to be retried. The
+/// task doesn't take up a slot while it waits.
+#[derive(Debug, Clone, Copy)]
+struct Retry {
+    /// The index of the backend to record with.
+    backend: usize,
+    /// The attempt number with that backend.
+    attempt: u32,
+    /// The attempt isn't started before this time.
+    not_before: Instant,
+}
+
+struct SpawnTask {
+    task: Task,
+    cfg: Config,
+    tx: BusTx<Message>,
+    retry: Option<Retry>,
+}
+
+impl SpawnTask {
+    /// Returns the priority of the task's channel, if it's configured.
+    fn priority(&self) -> i32 {
+        self.cfg
+            .channel
+            .iter()
+            .find(|channel| channel.id == self.task.channel_id)
+            .map(|channel| channel.priority)
+            .unwrap_or_default()
+    }
+}
+
+enum QueueCommand {
+    Add(Box<SpawnTask>),
+    Cancel(String),
+    Shutdown,
+}
+
+impl YTArchive {
+    /// Cleans up the orphaned files in the working directories in the
+    /// background, so that the queue isn't blocked while it runs.
+    async fn run_janitor(
+        config: &RwLock<Config>,
+        active_ids: &RwLock<HashMap<String, ActiveTask>>,
+    ) {
+        let config = config.read().await;
+        let Some(cfg) = config.ytarchive.janitor.clone() else {
+            return;
+        };
+        let dirs = janitor::working_directories(&config);
+        let exclude = janitor::protected_paths(&config);
+        let active_ids: HashSet<String> = active_ids.read().await.keys().cloned().collect();
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = janitor::clean(&cfg, &dirs, &exclude, &active_ids) {
+                error!("[janitor] Failed to clean up orphaned files: {:?}", e);
+            }
+        });
+    }
+
+    /// Warns once about each running task whose directories are low on disk
+    /// space, and again if the space drops after having recovered.
+    async fn check_active_space(
+        active_ids: &RwLock<HashMap<String, ActiveTask>>,
+        config: &Config,
+        low_space: &mut HashSet<String>,
+        tx: &BusTx<Message>,
+    ) {
+        let active_ids = active_ids.read().await;
+        low_space.retain(|video_id| active_ids.contains_key(video_id));
+        for (video_id, active) in active_ids.iter() {
+            let date = active.run.date.lock().unwrap().unwrap_or_else(Utc::now);
+            let Some(reason) = disk::check(config, &active.task, &date) else {
+                low_space.remove(video_id);
+                continue;
+            };
+            if !low_space.insert(video_id.clone()) {
+                continue;
+            }
+
+            warn!(
+                "[{}][{}][{}] {}",
+                active.task.video_id, active.task.channel_name, active.task.title, reason
+            );
+            let _ = tx
+                .send(Message::ToNotify(Notification {
+                    task: active.task.clone(),
+                    status: TaskStatus::Warning,
+                    attempt: active.run.attempt.load(Ordering::Relaxed),
+                    yta_status: None,
+                    message: Some(reason),
+                }))
+                .await;
+        }
+    }
+
+    /// Interrupts every running task and waits for them to exit, up to the
+    /// configured grace period. Tasks that are still running after that are
+    /// killed along with the processes they started, and reported as failed.
+    async fn shutdown(
+        active_ids: &RwLock<HashMap<String, ActiveTask>>,
+        slot_freed: &Notify,
+        grace_period: std::time::Duration,
+        tx: &BusTx<Message>,
+    ) {
+        let count = {
+            let active_ids = active_ids.read().await;
+            for active in active_ids.values() {
+                let _ = active.cancel.send(true);
+            }
+            active_ids.len()
+        };
+        info!(
+            "Waiting up to {} for {} recordings to finish",
+            humantime::format_duration(grace_period),
+            count
+        );
+
+        let deadline = Instant::now() + grace_period;
+        while !active_ids.read().await.is_empty() {
+            select! {
+                _ = slot_freed.notified() => (),
+                _ = tokio::time::sleep_until(deadline) => break,
+            }
+        }
+
+        for active in active_ids.read().await.values() {
+            warn!(
+                "[{}][{}][{}] Recording did not finish before shutdown",
+                active.task.video_id, active.task.channel_name, active.task.title
+            );
+            // Dropping the process would only kill the backend, and leave
+            // ffmpeg running
+            #[cfg(unix)]
+            match active.run.pid.load(Ordering::Relaxed) {
+                0 => (),
+                pid => {
+                    if let Err(e) = kill_group(pid) {
+                        warn!(
+                            "[{}] Failed to kill recording: {:?}",
+                            active.task.video_id, e
+                        );
+                    }
+                }
+            }
+            let _ = tx
+                .send(Message::ToNotify(Notification {
+                    task: active.task.clone(),
+                    status: TaskStatus::Failed,
+                    attempt: active.run.attempt.load(Ordering::Relaxed),
+                    yta_status: None,
+                    message: Some("Recording did not finish before shutdown".to_string()),
+                }))
+                .await;
+        }
+    }
+}
+
+#[async_trait]
+impl Module for YTArchive {
+    fn new(config: Arc<RwLock<Config>>) -> Self {
+        let active_ids = Arc::new(RwLock::new(HashMap::new()));
+        let (output, _) = broadcast::channel(1);
+        Self {
+            config,
+            active_ids,
+            output,
+        }
+    }
+
+    async fn run(&self, tx: &BusTx<Message>, rx: &mut mpsc::Receiver<Message>) -> Result<()> {
+        // Create a spawn queue
+        let (spawn_tx, mut spawn_rx) = mpsc::unbounded_channel::<QueueCommand>();
+
+        // Notified whenever a running task finishes
+        let slot_freed = Arc::new(Notify::new());
+
+        // Tasks waiting to be retried go back into the queue
+        let (retry_tx, mut retry_rx) = mpsc::unbounded_channel::<SpawnTask>();
+
+        // Future to handle spawning new tasks
+        let active_ids = self.active_ids.clone();
+        let config = self.config.clone();
+        let bus = tx.clone();
+        let output = self.output.clone();
+        let f_spawner = async move {
+            let mut queue = PriorityQueue::<SpawnTask>::new();
+            let mut closed = false;
+
+            // Free space is checked again periodically, to start held tasks
+            // and to warn about running ones
+            let check_interval = config.read().await.ytarchive.disk_space.check_interval;
+            let mut disk_check = tokio::time::interval(check_interval);
+            disk_check.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            let mut held = HashMap::<String, String>::new();
+            let mut low_space = HashSet::<String>::new();
+
+            // Orphaned files are cleaned up periodically if enabled
+            let janitor_interval = config
+                .read()
+                .await
+                .ytarchive
+                .janitor
+                .as_ref()
+                .map(|janitor| janitor.interval);
+            let mut janitor_tick =
+                tokio::time::interval(janitor_interval.unwrap_or(check_interval));
+
+            while !closed || !queue.is_empty() {
+                // Wake up when the next retry is due
+                let now = Instant::now();
+                let next_retry = queue
+                    .ordered()
+                    .iter()
+                    .filter_map(|t| t.retry.map(|retry| retry.not_before))
+                    .filter(|not_before| *not_before > now)
+                    .min();
+
+                // Wait for a new task or for a running one to finish
+                select! {
+                    command = spawn_rx.recv(), if !closed => match command {
+                        Some(QueueCommand::Add(task)) => {
+                            if queue.any(|t| t.task.video_id == task.task.video_id) {
+                                warn!("Task {} is already queued, skipping", task.task.video_id);
+                                continue;
+                            }
+                            let priority = task.priority();
+                            queue.push(*task, priority);
+                        }
+                        Some(QueueCommand::Cancel(video_id)) => {
+                            if let Some(active) = active_ids.read().await.get(&video_id) {
+                                let _ = active.cancel.send(true);
+                            } else if let Some(task) =
+                                queue.remove(|t| t.task.video_id == video_id)
+                            {
+                                info!("Removed task {} from the queue", video_id);
+                                let mut status = YTAStatus::new();
+                                status.state = YTAState::Interrupted;
+                                status.is_final = true;
+                                let _ = task
+                                    .tx
+                                    .send(Message::QueueStatus(QueueStatus {
+                                        task: task.task.clone(),
+                                        position: None,
+                                        held_reason: None,
+                                    }))
+                                    .await;
+                                let _ = task
+                                    .tx
+                                    .send(Message::RecordingStatus(RecordingStatus {
+                                        task: task.task,
+                                        status,
+                                    }))
+                                    .await;
+                            } else {
+                                warn!("Task {} is not queued or running", video_id);
+                                continue;
+                            }
+                        }
+                        Some(QueueCommand::Shutdown) => {
+                            // Queued tasks are dropped, the task store (if
+                            // enabled) will queue them again on restart
+                            let grace_period = config.read().await.ytarchive.shutdown_grace_period;
+                            YTArchive::shutdown(&active_ids, &slot_freed, grace_period, &bus).await;
+                            bus.close().await?;
+                            break;
+                        }
+                        None => {
+                            debug!("Spawn queue closed, exiting");
+                            closed = true;
+                            continue;
+                        }
+                    },
+                    Some(task) = retry_rx.recv() => {
+                        // Keep the retry state if the task got queued again
+                        // in the meantime
+                        queue.remove(|t| t.task.video_id == task.task.video_id);
+                        let priority = task.priority();
+                        queue.push(task, priority);
+                    }
+                    _ = slot_freed.notified() => (),
+                    _ = tokio::time::sleep_until(next_retry.unwrap_or(now)), if next_retry.is_some() => (),
+                    _ = disk_check.tick() => {
+                        let config = config.read().await;
+                        YTArchive::check_active_space(&active_ids, &config, &mut low_space, &bus)
+                            .await;
+                    }
+                    _ = janitor_tick.tick(), if janitor_interval.is_some() => {
+                        YTArchive::run_janitor(&config, &active_ids).await;
+                    }
+                }
+
+                // Start as many tasks as there are free slots, skipping the
+                // ones that don't have enough disk space
+                held.clear();
+                loop {
+                    let cfg = config.read().await;
+                    let active = active_ids.read().await.len();
+                    if cfg
+                        .ytarchive
+                        .max_concurrent
+                        .is_some_and(|max| active >= max)
+                    {
+                        break;
+                    }
+                    let next = queue.pop_first(|t| {
+                        if held.contains_key(&t.task.video_id) {
+                            return false;
+                        }
+                        if let Some(retry) = t.retry.filter(|r| r.not_before > Instant::now()) {
+                            let reason = format!("Waiting to retry (attempt {})", retry.attempt);
+                            held.insert(t.task.video_id.clone(), reason);
+                            return false;
+                        }
+                        match disk::check(&cfg, &t.task, &Utc::now()) {
+                            Some(reason) => {
+                                held.insert(t.task.video_id.clone(), reason);
+                                false
+                            }
+                            None => true,
+                        }
+                    });
+                    drop(cfg);
+                    let Some(mut task) = next else {
+                        break;
+                    };
+
+                    let _ = task
+                        .tx
+                        .send(Message::QueueStatus(QueueStatus {
+                            task: task.task.clone(),
+                            position: None,
+                            held_reason: None,
+                        }))
+                        .await;
+
+                    let video_id = task.task.video_id.clone();
+                    let (cancel_tx, cancel_rx) = watch::channel(false);
+                    let run = Arc::new(RunState {
+                        attempt: AtomicU32::new(task.retry.map_or(1, |retry| retry.attempt)),
+                        ..Default::default()
+                    });
+                    active_ids.write().await.insert(
+                        video_id.clone(),
+                        ActiveTask {
+                            task: task.task.clone(),
+                            cancel: cancel_tx,
+                            run: run.clone(),
+                        },
+                    );
+                    let delay = task.cfg.ytarchive.delay_start;
+
+                    debug!("Spawning thread for task: {:?}", task.task);
+                    tokio::spawn({
+                        let active_ids = active_ids.clone();
+                        let output = output.clone();
+                        let slot_freed = slot_freed.clone();
+                        let retry_tx = retry_tx.clone();
+                        async move {
+                            let retry = YTArchive::record_with_fallback(
+                                task.cfg.clone(),
+                                task.task.clone(),
+                                &mut task.tx,
+                                cancel_rx,
+                                RecordContext {
+                                    run,
+                                    active_ids: active_ids.clone(),
+                                    output,
+                                },
+                                task.retry,
+                            )
+                            .await
+                            .unwrap_or_else(|e| {
+                                error!("Failed to record task: {:?}", e);
+                                None
+                            });
+
+                            // Free the slot while waiting for the retry
+                            active_ids.write().await.remove(&video_id);
+                            if let Some(retry) = retry {
+                                task.retry = Some(retry);
+                                let _ = retry_tx.send(task);
+                            }
+                            slot_freed.notify_one();
+                        }
+                    });
+
+                    // Wait a bit before starting the next task
+                    tokio::time::sleep(delay).await;
+                }
+
+                // Let everyone know where the remaining tasks are in the queue
+                for (position, task) in queue.ordered().into_iter().enumerate() {
+                    let held_reason = held.get(&task.task.video_id).cloned();
+                    if let Some(reason) = &held_reason {
+                        debug!("Holding task {}: {}", task.task.video_id, reason);
+                    }
+                    let _ = task
+                        .tx
+                        .send(Message::QueueStatus(QueueStatus {
+                            task: task.task.clone(),
+                            position: Some(position),
+                            held_reason,
+                        }))
+                        .await;
+                }
+            }
+
+            Ok::<(), anyhow::Error>(())
+        };
+
+        // Future to handle incoming messages
+        let f_message = async move {
+            let mut shutting_down = false;
+            while let Some(message) = rx.recv().await {
+                match message {
+                    Message::ToRecord(_) if shutting_down => {
+                        debug!("Shutting down, not accepting new tasks");
+                    }
+                    Message::ToRecord(task) => {
+                        // Check if the task is already active
+                        if self.active_ids.read().await.contains_key(&task.video_id) {
+                            warn!("Task {} is already active, skipping", task.video_id);
+                            continue;
+                        }
+
+                        debug!("Adding task to spawn queue: {:?}", task);
+                        let tx = tx.clone();
+                        let cfg = self.config.read().await;
+                        let cfg = cfg.clone();
+
+                        let task = Box::new(SpawnTask {
+                            task,
+                            cfg,
+                            tx,
+                            retry: None,
+                        });
+                        if spawn_tx.send(QueueCommand::Add(task)).is_err() {
+                            debug!("Spawn queue closed, exiting");
+                            break;
+                        }
+                    }
+                    Message::CancelTask(video_id) => {
+                        debug!("Cancelling task {}", video_id);
+                        if spawn_tx.send(QueueCommand::Cancel(video_id)).is_err() {
+                            debug!("Spawn queue closed, exiting");
+                            break;
+                        }
+                    }
+                    Message::Shutdown => {
+                        // Keep reading the bus until it's closed by the spawner
+                        shutting_down = true;
+                        if spawn_tx.send(QueueCommand::Shutdown).is_err() {
+                            debug!("Spawn queue closed, exiting");
+                            break;
+                        }
+                    }
+                    _ => (),
+                }
+            }
+
+            Ok::<(), anyhow::Error>(())
+        };
+
+        // Run the futures
+        tokio::try_join!(f_spawner, f_message)?;
+
+        debug!("YTArchive module finished");
+        Ok(())
+    }
+}
+
+/// The current state of ytarchive.
+#[derive(Debug, Clone, TS, Serialize, Deserialize)]
+#[ts(export)]
+pub struct YTAStatus {
+    version: Option<String>,
+    state: YTAState,
+    last_output: Option<String>,
+    last_update: chrono::DateTime<chrono::Utc>,
+    video_fragments: Option<u32>,
+    audio_fragments: Option<u32>,
+    total_size: Option<String>,
+    video_quality: Option<String>,
+    output_file: Option<String>,
+    /// The attempt number, starting from 1.
+    #[serde(default = "default_attempt")]
+    attempt: u32,
+    /// The backend that produced this status.
+    #[serde(default)]
+    backend: BackendKind,
+    /// The size downloaded so far in bytes.
+    #[serde(default)]
+    #[ts(type = "number | null")]
+    downloaded_bytes: Option<u64>,
+    /// The download rate in bytes per second.
+    #[serde(default)]
+    download_rate: Option<f64>,
+    /// The length of the recording in seconds, once ffmpeg reports it.
+    /// Fragments are shorter on streams with lower latency, so their number
+    /// doesn't tell the length.
+    #[serde(default)]
+    recorded_seconds: Option<f64>,
+    /// How far muxing has progressed, from 0 to 100. Only known if ffmpeg
+    /// reports the length of the recording before muxing it.
+    #[serde(default)]
+    muxing_percent: Option<f64>,
+    /// How many times faster than real time ffmpeg is muxing.
+    #[serde(default)]
+    muxing_speed: Option<f64>,
+    /// The estimated number of seconds until the current step is done.
+    #[serde(default)]
+    eta_seconds: Option<f64>,
+    /// When the stream started being recorded, after waiting for it.
+    #[serde(default)]
+    started_at: Option<DateTime<Utc>>,
+    /// When the process ended.
+    #[serde(default)]
+    finished_at: Option<DateTime<Utc>>,
+    /// When the stream was scheduled to start, if it had to be waited for.
+    #[serde(default)]
+    scheduled_start: Option<DateTime<Utc>>,
+    /// When the download rate was last measured, and the size at the time.
+    #[serde(skip)]
+    #[ts(skip)]
+    rate_sample: Option<(DateTime<Utc>, u64)>,
+    /// The length of the recording being muxed, as reported by ffmpeg.
+    #[serde(skip)]
+    #[ts(skip)]
+    muxing_duration: Option<f64>,
+    /// How much of the recording has been muxed in seconds.
+    #[serde(skip)]
+    #[ts(skip)]
+    muxed_seconds: Option<f64>,
+    /// Set on the last status of a task, once the recorder is done with it
+    /// and won't retry it or fall back to another backend.
+    #[serde(default)]
+    is_final: bool,
+}
+
+/// The minimum time the download rate is measured over.
+const RATE_WINDOW: chrono::Duration = chrono::Duration::seconds(5);
+
+fn default_attempt() -> u32 {
+    1
+}
+
+#[derive(Debug, Clone, PartialEq, TS, Serialize, Deserialize)]
+#[ts(export)]
+pub enum YTAState {
+    Idle,
+    Waiting(Option<DateTime<Utc>>),
+    Recording,
+    Muxing,
+    Finished,
+    AlreadyProcessed,
+    Ended,
+    Interrupted,
+    Errored,
+    /// ytarchive stopped making progress and was killed
+    Stalled,
+}
+
+impl YTAState {
+    /// Returns the name of the state, without any data attached to it.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            YTAState::Idle => "Idle",
+            YTAState::Waiting(_) => "Waiting",
+            YTAState::Recording => "Recording",
+            YTAState::Muxing => "Muxing",
+            YTAState::Finished => "Finished",
+            YTAState::AlreadyProcessed => "AlreadyProcessed",
+            YTAState::Ended => "Ended",
+            YTAState::Interrupted => "Interrupted",
+            YTAState::Errored => "Errored",
+            YTAState::Stalled => "Stalled",
+        }
+    }
+
+    /// Returns true if ytarchive is still running or about to be started.
+    pub fn is_active(&self) -> bool {
+        matches!(
+            self,
+            YTAState::Idle | YTAState::Waiting(_) | YTAState::Recording | YTAState::Muxing
+        )
+    }
+}
+
+impl YTAStatus {
+    pub fn new() -> Self {
+        Self {
+            version: None,
+            state: YTAState::Idle,
+            last_output: None,
+            last_update: chrono::Utc::now(),
+            video_fragments: None,
+            audio_fragments: None,
+            total_size: None,
+            video_quality: None,
+            output_file: None,
+            attempt: 1,
+            backend: BackendKind::default(),
+            downloaded_bytes: None,
+            download_rate: None,
+            recorded_seconds: None,
+            muxing_percent: None,
+            muxing_speed: None,
+            eta_seconds: None,
+            started_at: None,
+            finished_at: None,
+            scheduled_start: None,
+            rate_sample: None,
+            muxing_duration: None,
+            muxed_seconds: None,
+            is_final: false,
+        }
+    }
+
+    pub fn state(&self) -> &YTAState {
+        &self.state
+    }
+
+    pub fn output_file(&self) -> Option<&String> {
+        self.output_file.as_ref()
+    }
+
+    pub fn started_at(&self) -> Option<DateTime<Utc>> {
+        self.started_at
+    }
+
+    pub fn finished_at(&self) -> Option<DateTime<Utc>> {
+        self.finished_at
+    }
+
+    /// Tells whether going from the `old` status to this one counts as
+    /// progress for the stall timeout. Any output counts, except while
+    /// recording where the number of fragments or the size has to change, so
+    /// that repeating the same progress line doesn't keep a stuck download
+    /// alive.
+    fn made_progress(&self, old: &YTAStatus) -> bool {
+        self.state != YTAState::Recording
+            || old.state != self.state
+            || old.video_fragments != self.video_fragments
+            || old.audio_fragments != self.audio_fragments
+            || old.total_size != self.total_size
+    }
+
+    pub fn is_final(&self) -> bool {
+        self.is_final
+    }
+
+    /// Marks the status as the last one of its task.
+    pub fn set_final(&mut self) {
+        self.is_final = true;
+    }
+
+    /// Returns the date the output directory is rendered with: when the
+    /// recording started, or else when the stream was scheduled to start.
+    pub fn recording_date(&self) -> Option<DateTime<Utc>> {
+        self.started_at.or(self.scheduled_start)
+    }
+
+    pub fn video_fragments(&self) -> Option<u32> {
+        self.video_fragments
+    }
+
+    pub fn audio_fragments(&self) -> Option<u32> {
+        self.audio_fragments
+    }
+
+    /// Returns the total size downloaded so far in bytes.
+    pub fn downloaded_bytes(&self) -> Option<u64> {
+        self.downloaded_bytes
+    }
+
+    /// Sets the size downloaded so far, e.g. `133.12MiB`, and updates the
+    /// download rate.
+    fn set_total_size(&mut self, size: String) {
+        if let Ok(bytes) = crate::config::parse_size(&size) {
+            self.set_downloaded_bytes(bytes);
+        }
+        self.total_size = Some(size);
+    }
+
+    /// Sets the size downloaded so far in bytes and updates the download
+    /// rate, measured between updates at least `RATE_WINDOW` apart.
+    fn set_downloaded_bytes(&mut self, bytes: u64) {
+        self.downloaded_bytes = Some(bytes);
+        let now = self.last_update;
+        match self.rate_sample {
+            Some((since, _)) if now - since < RATE_WINDOW => (),
+            Some((since, before)) => {
+                let elapsed = (now - since).num_milliseconds() as f64 / 1000.0;
+                self.download_rate = Some(bytes.saturating_sub(before) as f64 / elapsed);
+                self.rate_sample = Some((now, bytes));
+            }
+            None => self.rate_sample = Some((now, bytes)),
+        }
+    }
+
+    /// Sets the number of fragments downloaded so far, leaving out the ones
+    /// that aren't known.
+    fn set_fragments(&mut self, video: Option<u32>, audio: Option<u32>) {
+        if video.is_some() {
+            self.video_fragments = video;
+        }
+        if audio.is_some() {
+            self.audio_fragments = audio;
+        }
+    }
+
+    /// Sets the length of the recording, as reported by ffmpeg before it
+    /// starts muxing.
+    fn set_muxing_duration(&mut self, seconds: f64) {
+        self.muxing_duration = Some(seconds);
+        self.recorded_seconds = Some(seconds);
+    }
+
+    /// Updates the muxing progress from how much of the recording has been
+    /// muxed in seconds, and how fast that happens. The percentage is only
+    /// known along with the length of the recording.
+    fn set_muxing_progress(&mut self, time: Option<f64>, speed: Option<f64>) {
+        if speed.is_some() {
+            self.muxing_speed = speed;
+        }
+        let Some(time) = time else {
+            return;
+        };
+        self.muxed_seconds = Some(time);
+        let Some(total) = self.muxing_duration.filter(|total| *total > 0.0) else {
+            return;
+        };
+        let time = time.min(total);
+        self.muxing_percent = Some(time / total * 100.0);
+        self.eta_seconds = self
+            .muxing_speed
+            .filter(|speed| *speed > 0.0)
+            .map(|speed| (total - time) / speed);
+    }
+
+    /// Marks muxing as done, at which point all of the recording has been
+    /// muxed and its length is known.
+    fn finish_muxing(&mut self) {
+        if self.state != YTAState::Muxing {
+            return;
+        }
+        self.muxing_percent = Some(100.0);
+        self.eta_seconds = None;
+        if let Some(time) = self.muxed_seconds {
+            self.recorded_seconds = Some(time);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use backend::{Backend, Ytarchive};
+
+    #[test]
+    fn test_move_output() {
+        let work = tempfile::tempdir().unwrap();
+        let out = tempfile::tempdir().unwrap();
+        for name in [
+            "video.mp4",
+            "video.jpg",
+            "video.description",
+            "video.info.json",
+            "video.mp4.part",
+            "video.live [abcdefghijk].jpg",
+            "other.mp4",
+        ] {
+            std::fs::write(work.path().join(name), name).unwrap();
+        }
+        // Already recorded before
+        std::fs::write(out.path().join("video.mp4"), "old").unwrap();
+
+        let task = Task {
+            output_directory: out.path().to_string_lossy().into_owned(),
+            ..Task::test("IKKar5SS29E")
+        };
+        let mut status = YTAStatus::new();
+        Ytarchive.parse_line(
+            &mut status,
+            &format!("Final file: {}", work.path().join("video.mp4").display()),
+        );
+
+        let others = HashSet::from(["abcdefghijk".to_string()]);
+        let dest = YTArchive::move_output("test", &task, &status, &others).unwrap();
+        assert_eq!(dest, out.path().join("video (1).mp4"));
+        assert_eq!(std::fs::read_to_string(&dest).unwrap(), "video.mp4");
+        assert_eq!(
+            std::fs::read_to_string(out.path().join("video (1).jpg")).unwrap(),
+            "video.jpg"
+        );
+        assert!(out.path().join("video (1).description").exists());
+        assert_eq!(
+            std::fs::read_to_string(out.path().join("video.mp4")).unwrap(),
+            "old"
+        );
+        assert!(work.path().join("other.mp4").exists());
+        assert!(!work.path().join("video.jpg").exists());
+        // Unknown files and the ones of other recordings stay behind
+        assert!(work.path().join("video.mp4.part").exists());
+        assert!(work.path().join("video.live [abcdefghijk].jpg").exists());
+
+        // The info file written by the backend is kept
+        let info = YTArchive::write_info_json(&dest, &task, &status).unwrap();
+        assert_eq!(info, out.path().join("video (1).hoshinova.json"));
+        assert_eq!(
+            std::fs::read_to_string(out.path().join("video (1).info.json")).unwrap(),
+            "video.info.json"
+        );
+        let info: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(info).unwrap()).unwrap();
+        assert_eq!(info["task"]["video_id"], "IKKar5SS29E");
+        assert_eq!(info["status"]["state"], "Finished");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_shutdown_kills_process_group() {
+        // The shell starts a child that also holds the pipe open
+        let mut process = tokio::process::Command::new("sh")
+            .args(["-c", "sleep 30 & wait"])
+            .stdout(Stdio::piped())
+            .process_group(0)
+            .kill_on_drop(true)
+            .spawn()
+            .unwrap();
+        let run = Arc::new(RunState::default());
+        run.pid.store(process.id().unwrap(), Ordering::Relaxed);
+        let active_ids = RwLock::new(HashMap::from([(
+            "IKKar5SS29E".to_string(),
+            ActiveTask {
+                task: Task::test("IKKar5SS29E"),
+                cancel: watch::channel(false).0,
+                run,
+            },
+        )]));
+        let mut bus = crate::msgbus::MessageBus::<Message>::new(16);
+        let tx = bus.add_tx();
+
+        YTArchive::shutdown(&active_ids, &Notify::new(), std::time::Duration::ZERO, &tx).await;
+
+        // The pipe is closed once every process of the group is gone
+        let mut stdout = process.stdout.take().unwrap();
+        let mut buf = vec![];
+        tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            stdout.read_to_end(&mut buf),
+        )
+        .await
+        .expect("The process group is still running")
+        .unwrap();
+        process.wait().await.unwrap();
+    }
+
+    #[test]
+    fn test_stall_timeout() {
+        let mut status = YTAStatus::new();
+        let line = "Video Fragments: 1; Audio Fragments: 1; Total Downloaded: 1.00MiB";
+        Ytarchive.parse_line(&mut status, line);
+        assert_eq!(status.state, YTAState::Recording);
+
+        // The same progress line again isn't progress, new fragments are
+        let old = status.clone();
+        Ytarchive.parse_line(&mut status, line);
+        assert!(!status.made_progress(&old));
+        Ytarchive.parse_line(
+            &mut status,
+            "Video Fragments: 2; Audio Fragments: 2; Total Downloaded: 2.00MiB",
+        );
+        assert!(status.made_progress(&old));
+
+        // Any output counts while muxing
+        Ytarchive.parse_line(&mut status, "Muxing final file...");
+        let old = status.clone();
+        Ytarchive.parse_line(&mut status, "Muxing final file...");
+        assert_eq!(status.state, YTAState::Muxing);
+        assert!(status.made_progress(&old));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_stall_watchdog() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cfg = Config::default();
+        cfg.ytarchive.working_directory = dir.path().to_string_lossy().into_owned();
+        cfg.ytarchive.task_log.directory = dir.path().join("logs").to_string_lossy().into_owned();
+        cfg.ytarchive.stall_timeout.recording = Some(std::time::Duration::from_millis(300));
+        // Starts recording, then repeats itself without new fragments
+        cfg.ytarchive.executable_path = "sh".into();
+        cfg.ytarchive.args = vec![
+            "-c".into(),
+            "while true; do echo 'Video Fragments: 1; Audio Fragments: 1; Total Downloaded: 1.00MiB'; sleep 0.1; done".into(),
+        ];
+
+        let mut bus = crate::msgbus::MessageBus::<Message>::new(64);
+        let mut tx = bus.add_tx();
+        let mut rx = bus.add_rx("test");
+        tokio::spawn(async move { bus.start().await });
+        let (_cancel_tx, cancel_rx) = watch::channel(false);
+        let ctx = RecordContext {
+            run: Arc::new(RunState::default()),
+            active_ids: Arc::new(RwLock::new(HashMap::new())),
+            output: broadcast::channel(16).0,
+        };
+
+        let status = tokio::time::timeout(
+            std::time::Duration::from_secs(10),
+            YTArchive::record(
+                cfg,
+                Task::test("IKKar5SS29E"),
+                BackendKind::Ytarchive,
+                &mut tx,
+                cancel_rx,
+                &ctx,
+            ),
+        )
+        .await
+        .expect("The stalled process wasn't killed")
+        .unwrap();
+        assert_eq!(status.state, YTAState::Stalled);
+
+        drop(tx);
+        let mut failed = None;
+        while let Ok(Some(msg)) =
+            tokio::time::timeout(std::time::Duration::from_secs(1), rx.recv()).await
+        {
+            if let Message::ToNotify(notification) = msg {
+                if notification.status == TaskStatus::Failed {
+                    failed = Some(notification);
+                }
+            }
+        }
+        let failed = failed.expect("No failed notification");
+        assert!(failed.message.unwrap().contains("no progress"));
+        assert_eq!(failed.yta_status.unwrap().state, YTAState::Stalled);
+    }
+
+    #[test]
+    fn test_recording_date() {
+        let mut status = YTAStatus::new();
+        assert_eq!(status.recording_date(), None);
+
+        let scheduled = "2020-06-15T12:00:00Z".parse().unwrap();
+        status.scheduled_start = Some(scheduled);
+        assert_eq!(status.recording_date(), Some(scheduled));
+
+        // The actual start wins over the schedule
+        let started = "2020-06-16T00:30:00Z".parse().unwrap();
+        status.started_at = Some(started);
+        assert_eq!(status.recording_date(), Some(started));
+    }
+
+    #[test]
+    fn test_downloaded_bytes() {
+        let mut status = YTAStatus::new();
+        assert_eq!(status.downloaded_bytes(), None);
+
+        Ytarchive.parse_line(
+            &mut status,
+            "Video Fragments: 1215; Audio Fragments: 1215; Total Downloaded: 133.12MiB",
+        );
+        assert_eq!(status.video_fragments(), Some(1215));
+        assert_eq!(status.audio_fragments(), Some(1215));
+        assert_eq!(status.downloaded_bytes(), Some(139_586_437));
+
+        Ytarchive.parse_line(&mut status, "Audio Fragments: 20; Total Downloaded: 512B");
+        assert_eq!(status.downloaded_bytes(), Some(512));
+
+        Ytarchive.parse_line(&mut status, "Audio Fragments: 20; Total Downloaded: 1.5GiB");
+        assert_eq!(status.downloaded_bytes(), Some(1_610_612_736));
+    }
+
+    #[test]
+    fn test_progress() {
+        let mut status = YTAStatus::new();
+        let start = status.last_update;
+        status.set_downloaded_bytes(0);
+        status.last_update = start + chrono::Duration::seconds(2);
+        status.set_downloaded_bytes(1 << 20);
+        assert_eq!(status.download_rate, None);
+        status.last_update = start + chrono::Duration::seconds(10);
+        status.set_downloaded_bytes(10 << 20);
+        assert_eq!(status.download_rate, Some((1 << 20) as f64));
+
+        status.set_fragments(Some(120), None);
+        status.set_fragments(None, Some(110));
+        assert_eq!(status.video_fragments, Some(120));
+        assert_eq!(status.audio_fragments, Some(110));
+        // The number of fragments doesn't tell the length
+        assert_eq!(status.recorded_seconds, None);
+
+        // Without the length, there's no percentage
+        status.set_muxing_progress(Some(300.0), Some(10.0));
+        assert_eq!(status.muxing_percent, None);
+        assert_eq!(status.muxing_speed, Some(10.0));
+
+        status.set_muxing_duration(600.0);
+        assert_eq!(status.recorded_seconds, Some(600.0));
+        status.set_muxing_progress(Some(300.0), Some(10.0));
+        assert_eq!(status.muxing_percent, Some(50.0));
+        assert_eq!(status.eta_seconds, Some(30.0));
+        status.set_muxing_progress(Some(900.0), None);
+        assert_eq!(status.muxing_percent, Some(100.0));
+        assert_eq!(status.eta_seconds, Some(0.0));
+    }
+}
@@ -0,0 +1,218 @@
+use crate::module::Task;
+use anyhow::{anyhow, Result};
+use chrono::{format::StrftimeItems, DateTime, TimeZone};
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+/// Format used by `{date}` when no format is given
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Maximum length in bytes of a single substituted value, which keeps long
+/// titles from exceeding the file name limit of most file systems.
+const MAX_VALUE_LEN: usize = 200;
+
+/// Renders an output directory template. The placeholders `{channel_name}`,
+/// `{channel_id}`, `{video_id}`, `{title}` and `{date:FORMAT}` are replaced
+/// with the values of the task, sanitised so that they can't add or escape
+/// directories. `{{` and `}}` insert literal braces.
+pub fn render<Tz>(template: &str, task: &Task, date: &DateTime<Tz>) -> Result<PathBuf>
+where
+    Tz: TimeZone,
+    Tz::Offset: Display,
+{
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find(['{', '}']) {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+
+        // Escaped braces
+        if rest[start..].starts_with("{{") || rest[start..].starts_with("}}") {
+            output.push_str(&rest[start..start + 1]);
+            rest = &after[1..];
+            continue;
+        }
+        if rest[start..].starts_with('}') {
+            return Err(anyhow!("Unmatched '}}' in outpath template"));
+        }
+
+        let end = after
+            .find('}')
+            .ok_or_else(|| anyhow!("Unclosed placeholder in outpath template"))?;
+        let (name, format) = match after[..end].split_once(':') {
+            Some((name, format)) => (name, Some(format)),
+            None => (&after[..end], None),
+        };
+        let value = match (name, format) {
+            ("channel_name", None) => task.channel_name.clone(),
+            ("channel_id", None) => task.channel_id.clone(),
+            ("video_id", None) => task.video_id.clone(),
+            ("title", None) => task.title.clone(),
+            ("date", format) => {
+                let items = StrftimeItems::new(format.unwrap_or(DEFAULT_DATE_FORMAT));
+                // Formatting with an invalid format would panic
+                items.clone().parse().map_err(|_| {
+                    anyhow!("Invalid date format in outpath template: {}", &after[..end])
+                })?;
+                date.format_with_items(items).to_string()
+            }
+            _ => {
+                return Err(anyhow!(
+                    "Unknown placeholder in outpath template: {{{}}}",
+                    &after[..end]
+                ))
+            }
+        };
+        output.push_str(&sanitize(&value));
+
+        rest = &after[end + 1..];
+    }
+    output.push_str(rest);
+    Ok(PathBuf::from(output))
+}
+
+/// Checks that a template can be rendered.
+pub fn validate(template: &str) -> Result<(), String> {
+    let task = Task {
+        title: String::new(),
+        video_id: String::new(),
+        video_picture: String::new(),
+        channel_name: String::new(),
+        channel_id: String::new(),
+        channel_picture: None,
+        output_directory: String::new(),
+        overrides: Default::default(),
+    };
+    render(template, &task, &chrono::Utc::now())
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// Makes a value safe to use as a single path component, by replacing path
+/// separators and characters that are invalid on common file systems.
+pub fn sanitize(value: &str) -> String {
+    let mut sanitized: String = value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+
+    if sanitized.len() > MAX_VALUE_LEN {
+        let mut end = MAX_VALUE_LEN;
+        while !sanitized.is_char_boundary(end) {
+            end -= 1;
+        }
+        sanitized.truncate(end);
+    }
+
+    // Windows doesn't allow trailing dots or spaces, and a value made of
+    // dots only would refer to a directory
+    let sanitized = sanitized.trim_end_matches(['.', ' ']);
+    if sanitized.is_empty() {
+        return "_".into();
+    }
+    sanitized.to_string()
+}
+
+/// Returns the path itself if nothing exists there yet, or otherwise the
+/// first free path with a ` (n)` suffix added before the extension.
+pub fn unique_path(path: &Path) -> PathBuf {
+    if !path.exists() {
+        return path.to_path_buf();
+    }
+
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    (1..)
+        .map(|n| path.with_file_name(format!("{} ({}){}", stem, n, extension)))
+        .find(|candidate| !candidate.exists())
+        .expect("Ran out of suffixes")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use tempfile::TempDir;
+
+    fn task() -> Task {
+        Task {
+            title: "【歌枠】Karaoke / Singing: part 1?".into(),
+            channel_name: "Moona Hoshinova".into(),
+            channel_id: "UCP0BspO_AMEe3aQqqpo89Dg".into(),
+            output_directory: "".into(),
+            ..Task::test("IKKar5SS29E")
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let date = Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+
+        let path = render(
+            "./videos/{channel_name}/{date:%Y}/{date:%m}",
+            &task(),
+            &date,
+        )
+        .unwrap();
+        assert_eq!(path, PathBuf::from("./videos/Moona Hoshinova/2024/06"));
+
+        let path = render(
+            "/v/{channel_id}/{date} {title} [{video_id}]",
+            &task(),
+            &date,
+        )
+        .unwrap();
+        assert_eq!(
+            path,
+            PathBuf::from(
+                "/v/UCP0BspO_AMEe3aQqqpo89Dg/2024-06-01 【歌枠】Karaoke _ Singing_ part 1_ [IKKar5SS29E]"
+            )
+        );
+
+        // Plain paths are left alone
+        let path = render("./videos/moona", &task(), &date).unwrap();
+        assert_eq!(path, PathBuf::from("./videos/moona"));
+        let path = render("./{{literal}}", &task(), &date).unwrap();
+        assert_eq!(path, PathBuf::from("./{literal}"));
+
+        assert!(render("./{nope}", &task(), &date).is_err());
+        assert!(render("./{title", &task(), &date).is_err());
+        assert!(render("./title}", &task(), &date).is_err());
+        assert!(render("./{title:%Y}", &task(), &date).is_err());
+        assert!(validate("./{date:%Q}").is_err());
+        assert!(validate("./{channel_name}/{date:%Y-%m}").is_ok());
+    }
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(sanitize("a/b\\c"), "a_b_c");
+        assert_eq!(sanitize(".."), "_");
+        assert_eq!(sanitize("title. "), "title");
+        assert_eq!(sanitize("line\nbreak"), "line_break");
+        assert_eq!(sanitize(&"あ".repeat(100)).len(), 198);
+    }
+
+    #[test]
+    fn test_unique_path() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("video.mp4");
+        assert_eq!(unique_path(&path), path);
+
+        std::fs::write(&path, "").unwrap();
+        assert_eq!(unique_path(&path), dir.path().join("video (1).mp4"));
+        std::fs::write(dir.path().join("video (1).mp4"), "").unwrap();
+        assert_eq!(unique_path(&path), dir.path().join("video (2).mp4"));
+    }
+}
@@ -0,0 +1,126 @@
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+/// A priority queue that returns items with the highest priority first, and
+/// items with the same priority in the order they were added.
+pub struct PriorityQueue<T> {
+    heap: BinaryHeap<Entry<T>>,
+    seq: u64,
+}
+
+struct Entry<T> {
+    priority: i32,
+    seq: u64,
+    item: T,
+}
+
+impl<T> PartialEq for Entry<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T> Eq for Entry<T> {}
+
+impl<T> PartialOrd for Entry<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Entry<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Higher priority first, then lower sequence number first
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl<T> PriorityQueue<T> {
+    pub fn new() -> Self {
+        Self {
+            heap: BinaryHeap::new(),
+            seq: 0,
+        }
+    }
+
+    pub fn push(&mut self, item: T, priority: i32) {
+        self.heap.push(Entry {
+            priority,
+            seq: self.seq,
+            item,
+        });
+        self.seq += 1;
+    }
+
+    /// Removes and returns the item that would be popped first among the
+    /// ones that match the predicate, which is called in that order.
+    pub fn pop_first(&mut self, mut f: impl FnMut(&T) -> bool) -> Option<T> {
+        let mut entries = std::mem::take(&mut self.heap).into_sorted_vec();
+        let popped = entries
+            .iter()
+            .rposition(|entry| f(&entry.item))
+            .map(|i| entries.remove(i).item);
+        self.heap = BinaryHeap::from(entries);
+        popped
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    /// Returns true if any item in the queue matches the predicate.
+    pub fn any(&self, f: impl Fn(&T) -> bool) -> bool {
+        self.heap.iter().any(|entry| f(&entry.item))
+    }
+
+    /// Removes and returns the first item that matches the predicate.
+    pub fn remove(&mut self, f: impl Fn(&T) -> bool) -> Option<T> {
+        let mut entries = std::mem::take(&mut self.heap).into_vec();
+        let removed = entries
+            .iter()
+            .position(|entry| f(&entry.item))
+            .map(|i| entries.swap_remove(i).item);
+        self.heap = BinaryHeap::from(entries);
+        removed
+    }
+
+    /// Returns the items in the order they would be popped.
+    pub fn ordered(&self) -> Vec<&T> {
+        let mut entries: Vec<&Entry<T>> = self.heap.iter().collect();
+        entries.sort_by(|a, b| b.cmp(a));
+        entries.into_iter().map(|entry| &entry.item).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_priority_queue_order() {
+        let mut queue = PriorityQueue::new();
+        queue.push("a", 0);
+        queue.push("b", 10);
+        queue.push("c", 0);
+        queue.push("d", -5);
+        queue.push("e", 10);
+
+        assert_eq!(queue.ordered(), vec![&"b", &"e", &"a", &"c", &"d"]);
+        assert!(queue.any(|x| *x == "c"));
+        assert!(!queue.any(|x| *x == "z"));
+
+        assert_eq!(queue.remove(|x| *x == "a"), Some("a"));
+        assert_eq!(queue.remove(|x| *x == "a"), None);
+
+        // Items that don't match are skipped but kept
+        assert_eq!(queue.pop_first(|x| *x != "b"), Some("e"));
+
+        let mut popped = vec![];
+        while let Some(item) = queue.pop_first(|_| true) {
+            popped.push(item);
+        }
+        assert_eq!(popped, vec!["b", "c", "d"]);
+        assert!(queue.is_empty());
+    }
+}
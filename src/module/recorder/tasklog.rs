@@ -0,0 +1,170 @@
+use crate::config::TaskLogConfig;
+use anyhow::{anyhow, Context, Result};
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+/// Returns the path of the current log file of a task.
+pub fn path(cfg: &TaskLogConfig, video_id: &str) -> PathBuf {
+    Path::new(&cfg.directory).join(format!("{}.log", video_id))
+}
+
+/// Returns the path of the nth most recently rotated log file, e.g.
+/// `IKKar5SS29E.log.1`.
+fn rotated_path(path: &Path, n: usize) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(format!(".{}", n));
+    PathBuf::from(path)
+}
+
+/// Prefixes a line with the current time, the way it's written to the log.
+pub fn timestamped(line: &str) -> String {
+    format!(
+        "{} {}",
+        chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ"),
+        line
+    )
+}
+
+/// Appends the output of a task's process to its log file, and rotates the
+/// file once it reaches the maximum size.
+pub struct TaskLog {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    max_files: usize,
+}
+
+impl TaskLog {
+    pub fn open(cfg: &TaskLogConfig, video_id: &str) -> Result<Self> {
+        fs::create_dir_all(&cfg.directory)
+            .with_context(|| format!("Failed to create log directory: {}", cfg.directory))?;
+        let path = path(cfg, video_id);
+        let file = Self::open_file(&path)?;
+        let size = file.metadata().map(|m| m.len()).unwrap_or_default();
+        Ok(Self {
+            path,
+            file,
+            size,
+            max_size: cfg.max_size().map_err(|e| anyhow!(e))?,
+            max_files: cfg.max_files,
+        })
+    }
+
+    fn open_file(path: &Path) -> Result<File> {
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open log file: {}", path.display()))
+    }
+
+    pub fn write(&mut self, line: &str) -> Result<()> {
+        let len = line.len() as u64 + 1;
+        if self.size > 0 && self.size + len > self.max_size {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{}", line)
+            .with_context(|| format!("Failed to write to {}", self.path.display()))?;
+        self.size += len;
+        Ok(())
+    }
+
+    /// Moves the current file to `.1`, `.1` to `.2` and so on, dropping the
+    /// oldest one, and starts a new file.
+    fn rotate(&mut self) -> Result<()> {
+        for n in (1..=self.max_files).rev() {
+            let from = match n {
+                1 => self.path.clone(),
+                n => rotated_path(&self.path, n - 1),
+            };
+            if from.exists() {
+                fs::rename(&from, rotated_path(&self.path, n))
+                    .with_context(|| format!("Failed to rotate {}", from.display()))?;
+            }
+        }
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)
+                .with_context(|| format!("Failed to remove {}", self.path.display()))?;
+        }
+        self.file = Self::open_file(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+/// Reads the log of a task from its rotated files and the current one, oldest
+/// line first. Only the last `tail` lines are returned if set. Returns None if
+/// the task has no log.
+pub fn read(
+    cfg: &TaskLogConfig,
+    video_id: &str,
+    tail: Option<usize>,
+) -> Result<Option<Vec<String>>> {
+    let path = path(cfg, video_id);
+    let files: Vec<PathBuf> = (1..=cfg.max_files)
+        .rev()
+        .map(|n| rotated_path(&path, n))
+        .chain(std::iter::once(path.clone()))
+        .filter(|path| path.exists())
+        .collect();
+    if files.is_empty() {
+        return Ok(None);
+    }
+
+    let mut lines = VecDeque::new();
+    for path in files {
+        let file =
+            File::open(&path).with_context(|| format!("Failed to open {}", path.display()))?;
+        for line in BufReader::new(file).split(b'\n') {
+            let line = line.with_context(|| format!("Failed to read {}", path.display()))?;
+            lines.push_back(String::from_utf8_lossy(&line).into_owned());
+            if tail.is_some_and(|tail| lines.len() > tail) {
+                lines.pop_front();
+            }
+        }
+    }
+    Ok(Some(lines.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_rotate() {
+        let dir = TempDir::new().unwrap();
+        let cfg = TaskLogConfig {
+            directory: dir.path().join("logs").to_string_lossy().into_owned(),
+            max_size: "20B".into(),
+            max_files: 1,
+            ..Default::default()
+        };
+        assert_eq!(read(&cfg, "IKKar5SS29E", None).unwrap(), None);
+
+        let mut log = TaskLog::open(&cfg, "IKKar5SS29E").unwrap();
+        for line in ["line 1", "line 2", "line 3", "line 4", "line 5"] {
+            log.write(line).unwrap();
+        }
+        // Two lines fit in a file, and the oldest file was dropped
+        assert!(rotated_path(&path(&cfg, "IKKar5SS29E"), 1).exists());
+        assert!(!rotated_path(&path(&cfg, "IKKar5SS29E"), 2).exists());
+        assert_eq!(
+            read(&cfg, "IKKar5SS29E", None).unwrap().unwrap(),
+            vec!["line 3", "line 4", "line 5"]
+        );
+
+        // Reopening appends to the current file
+        let mut log = TaskLog::open(&cfg, "IKKar5SS29E").unwrap();
+        log.write("line 6").unwrap();
+        assert_eq!(
+            read(&cfg, "IKKar5SS29E", Some(2)).unwrap().unwrap(),
+            vec!["line 5", "line 6"]
+        );
+    }
+}
@@ -0,0 +1,172 @@
+use super::{filter, load_scraped, persist_scraped, wait_until, ScrapedSet};
+use crate::{
+    config, metrics,
+    module::{Message, Module, Task},
+    msgbus::BusTx,
+    youtube, APP_USER_AGENT,
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use futures::stream::{self, StreamExt};
+use reqwest::Client;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::{mpsc, RwLock};
+
+/// ChannelPage scrapes the streams tab of each channel. It picks up streams
+/// that are live or scheduled, which may not show up in the RSS feed until
+/// much later.
+pub struct ChannelPage {
+    config: Arc<RwLock<config::Config>>,
+    client: Client,
+}
+
+impl ChannelPage {
+    async fn run_one(
+        &self,
+        scraped: &Mutex<ScrapedSet>,
+        channel: &config::ResolvedChannelConfig,
+    ) -> Result<Vec<Task>> {
+        debug!("Fetching streams page for {}", channel.name);
+
+        let streams = youtube::channel::fetch_streams(self.client.clone(), &channel.id)
+            .await
+            .context("Failed to fetch streams page")?;
+        let channel_name = streams.channel_name.unwrap_or(channel.name.clone());
+
+        let mut tasks = vec![];
+        for video in streams.videos {
+            if scraped.lock().unwrap().contains(&video.video_id) {
+                debug!("Skipping {}: already scraped", video.video_id);
+                continue;
+            }
+
+            let matches = filter::matches(
+                &self.client,
+                channel,
+                &video.video_id,
+                &video.title,
+                &video.description,
+            )
+            .await;
+            match matches {
+                Ok(true) => (),
+                Ok(false) => {
+                    debug!("Skipping {}: doesn't match filters", video.video_id);
+                    continue;
+                }
+                Err(e) => {
+                    // Try again on the next poll
+                    warn!("Failed to filter {}: {:?}", video.video_id, e);
+                    continue;
+                }
+            }
+
+            scraped
+                .lock()
+                .unwrap()
+                .insert(video.video_id.clone(), chrono::Utc::now());
+
+            tasks.push(Task {
+                title: video.title,
+                video_id: video.video_id,
+                video_picture: video.thumbnail,
+                channel_name: channel_name.clone(),
+                channel_id: channel.id.clone(),
+                channel_picture: channel.picture_url.clone(),
+                output_directory: channel.outpath.clone(),
+                overrides: channel.overrides.clone(),
+            });
+        }
+
+        Ok(tasks)
+    }
+
+    async fn run_loop(&self, scraped: &Mutex<ScrapedSet>) -> Vec<Task> {
+        let channels = self.config.read().await.channels();
+        stream::iter(channels)
+            .map(|channel| async move {
+                let result = self.run_one(scraped, &channel).await;
+
+                let labels = [("channel_id", channel.id.as_str())];
+                metrics::CHANNEL_PAGE_POLLS.inc(&labels);
+                if result.is_err() {
+                    metrics::CHANNEL_PAGE_POLL_FAILURES.inc(&labels);
+                }
+                result.unwrap_or_else(|e| {
+                    error!("Failed to scrape streams page of {}: {:?}", channel.name, e);
+                    vec![]
+                })
+            })
+            .buffer_unordered(4)
+            .concat()
+            .await
+    }
+}
+
+#[async_trait]
+impl Module for ChannelPage {
+    fn new(config: Arc<RwLock<config::Config>>) -> Self {
+        let client = Client::builder()
+            .user_agent(APP_USER_AGENT)
+            .timeout(Duration::from_secs(30))
+            .build()
+            .expect("Failed to create client");
+        Self { config, client }
+    }
+
+    async fn run(&self, tx: &BusTx<Message>, rx: &mut mpsc::Receiver<Message>) -> Result<()> {
+        let mut scraped: Option<Mutex<ScrapedSet>> = None;
+        loop {
+            let page = match self.config.read().await.scraper.channel_page.clone() {
+                Some(page) => page,
+                None => {
+                    // Check again later, in case it gets enabled by a config
+                    // reload
+                    let wakeup = std::time::Instant::now() + Duration::from_secs(60);
+                    if !wait_until(wakeup, rx, &Mutex::default()).await {
+                        debug!("Stopped scraping channel pages");
+                        return Ok(());
+                    }
+                    continue;
+                }
+            };
+
+            if scraped.is_none() {
+                let channels = self.config.read().await.channels();
+                let set = load_scraped(
+                    page.scraped_path.as_deref(),
+                    page.seed_from_outpath,
+                    &channels,
+                )
+                .await;
+                scraped = Some(Mutex::new(set));
+            }
+            let scraped = scraped.as_ref().expect("Scraped set was just loaded");
+
+            // Scrape the streams pages
+            for task in self.run_loop(scraped).await {
+                if tx.send(Message::ToRecord(task)).await.is_err() {
+                    debug!("Failed to send message to bus");
+                    return Ok(());
+                }
+            }
+
+            // Remember what has been scraped across restarts
+            if let Err(e) =
+                persist_scraped(scraped, page.scraped_path, page.scraped_retention).await
+            {
+                warn!("Failed to save scraped videos: {:?}", e);
+            }
+
+            // Sleep
+            let wakeup = std::time::Instant::now() + page.poll_interval;
+            if !wait_until(wakeup, rx, scraped).await {
+                debug!("Stopped scraping channel pages");
+                return Ok(());
+            }
+        }
+    }
+}
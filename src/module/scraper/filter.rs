@@ -0,0 +1,283 @@
+use crate::{
+    config::{ChannelFilterConfig, FilterField, FilterRule, ResolvedChannelConfig, ScheduleConfig},
+    youtube,
+};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Datelike, Utc};
+use lazy_static::lazy_static;
+use reqwest::Client;
+use std::{collections::HashMap, sync::Mutex};
+
+/// How long fetched video details are reused. Videos that don't match are
+/// checked again on every poll, and fetching their page each time would get
+/// us rate limited.
+const DETAILS_TTL: chrono::Duration = chrono::Duration::hours(1);
+
+#[derive(Clone)]
+struct Details {
+    fetched: DateTime<Utc>,
+    tags: Vec<String>,
+    start: Option<DateTime<Utc>>,
+    description: String,
+}
+
+lazy_static! {
+    static ref DETAILS: Mutex<HashMap<String, Details>> = Mutex::new(HashMap::new());
+}
+
+async fn fetch_details(client: &Client, video_id: &str) -> Result<Details> {
+    let now = Utc::now();
+    if let Some(details) = DETAILS.lock().unwrap().get(video_id) {
+        if now - details.fetched < DETAILS_TTL {
+            return Ok(details.clone());
+        }
+    }
+
+    let url = format!("https://www.youtube.com/watch?v={}", video_id);
+    let ipr = youtube::video::fetch_initial_player_response(client.clone(), &url)
+        .await
+        .context("Failed to fetch video details")?;
+    let details = Details {
+        fetched: now,
+        start: ipr.start_timestamp(),
+        tags: ipr.video_details.keywords,
+        description: ipr.video_details.short_description,
+    };
+
+    let mut cache = DETAILS.lock().unwrap();
+    cache.retain(|_, d| now - d.fetched < DETAILS_TTL);
+    cache.insert(video_id.to_owned(), details.clone());
+    Ok(details)
+}
+
+/// What a video is filtered on
+#[derive(Debug, Default)]
+pub struct Video {
+    pub title: String,
+    pub description: String,
+    pub tags: Vec<String>,
+    /// When the stream is scheduled to start, or started at
+    pub start: Option<DateTime<Utc>>,
+}
+
+/// Returns true if the video matches the filters of the channel. The video
+/// page is only fetched if the filter needs the tags or the start time.
+pub async fn matches(
+    client: &Client,
+    channel: &ResolvedChannelConfig,
+    video_id: &str,
+    title: &str,
+    description: &str,
+) -> Result<bool> {
+    let mut video = Video {
+        title: title.to_owned(),
+        description: description.to_owned(),
+        ..Video::default()
+    };
+
+    if !matches_legacy(channel, &video) {
+        return Ok(false);
+    }
+    let filter = match &channel.filter {
+        Some(filter) => filter,
+        None => return Ok(true),
+    };
+
+    if needs_details(filter) {
+        let details = fetch_details(client, video_id).await?;
+        video.start = details.start;
+        video.tags = details.tags;
+        // The channel page only has a snippet of the description
+        if details.description.len() > video.description.len() {
+            video.description = details.description;
+        }
+    }
+
+    Ok(matches_filter(filter, &video))
+}
+
+/// Checks the plain list of regexes. An empty list matches nothing, unless
+/// the structured filter is used instead.
+fn matches_legacy(channel: &ResolvedChannelConfig, video: &Video) -> bool {
+    if channel.filters.is_empty() && channel.filter.is_some() {
+        return true;
+    }
+    channel.filters.iter().any(|filter| {
+        filter.is_match(&video.title)
+            || (channel.match_description && filter.is_match(&video.description))
+    })
+}
+
+fn needs_details(filter: &ChannelFilterConfig) -> bool {
+    fn rule_needs_details(rule: &FilterRule) -> bool {
+        match rule {
+            FilterRule::All { all: rules } | FilterRule::Any { any: rules } => {
+                rules.iter().any(rule_needs_details)
+            }
+            FilterRule::Match { field, .. } => *field == FilterField::Tag,
+        }
+    }
+
+    filter.schedule.is_some()
+        || filter
+            .include
+            .iter()
+            .chain(&filter.exclude)
+            .any(rule_needs_details)
+}
+
+fn matches_filter(filter: &ChannelFilterConfig, video: &Video) -> bool {
+    (filter.include.is_empty() || filter.include.iter().any(|r| matches_rule(r, video)))
+        && !filter.exclude.iter().any(|r| matches_rule(r, video))
+        && filter
+            .schedule
+            .as_ref()
+            .is_none_or(|s| matches_schedule(s, video))
+}
+
+fn matches_rule(rule: &FilterRule, video: &Video) -> bool {
+    match rule {
+        FilterRule::All { all } => all.iter().all(|r| matches_rule(r, video)),
+        FilterRule::Any { any } => any.iter().any(|r| matches_rule(r, video)),
+        FilterRule::Match { field, pattern } => match field {
+            FilterField::Title => pattern.is_match(&video.title),
+            FilterField::Description => pattern.is_match(&video.description),
+            FilterField::Tag => video.tags.iter().any(|tag| pattern.is_match(tag)),
+        },
+    }
+}
+
+/// Videos without a known start time never match a schedule.
+fn matches_schedule(schedule: &ScheduleConfig, video: &Video) -> bool {
+    let (offset, after, before) = match (schedule.utc_offset(), schedule.after(), schedule.before())
+    {
+        (Ok(offset), Ok(after), Ok(before)) => (offset, after, before),
+        _ => return false, // Rejected when loading the config
+    };
+    let start = match video.start {
+        Some(start) => start.with_timezone(&offset),
+        None => return false,
+    };
+
+    let time = start.time();
+    let mut day = start.weekday();
+    let in_window = match (after, before) {
+        (Some(after), Some(before)) if after <= before => after <= time && time < before,
+        // The window wraps around midnight, and belongs to the day it starts
+        (Some(after), Some(before)) => {
+            if time < before {
+                day = day.pred();
+            }
+            after <= time || time < before
+        }
+        (Some(after), None) => after <= time,
+        (None, Some(before)) => time < before,
+        (None, None) => true,
+    };
+
+    in_window && (schedule.days.is_empty() || schedule.days.contains(&day))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    fn rule(field: FilterField, pattern: &str) -> FilterRule {
+        FilterRule::Match {
+            field,
+            pattern: Regex::new(pattern).unwrap(),
+        }
+    }
+
+    fn video(title: &str, description: &str, tags: &[&str]) -> Video {
+        Video {
+            title: title.into(),
+            description: description.into(),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            start: None,
+        }
+    }
+
+    #[test]
+    fn test_matches_filter() {
+        // Match karaoke, but not shorts
+        let filter = ChannelFilterConfig {
+            include: vec![rule(FilterField::Title, "(?i)karaoke")],
+            exclude: vec![FilterRule::Any {
+                any: vec![
+                    rule(FilterField::Title, "(?i)#shorts"),
+                    rule(FilterField::Tag, "(?i)^shorts$"),
+                ],
+            }],
+            schedule: None,
+        };
+        assert!(matches_filter(&filter, &video("Karaoke!", "", &[])));
+        assert!(!matches_filter(&filter, &video("Karaoke #shorts", "", &[])));
+        assert!(!matches_filter(&filter, &video("Karaoke", "", &["Shorts"])));
+        assert!(!matches_filter(&filter, &video("Minecraft", "", &[])));
+        assert!(needs_details(&filter));
+
+        // Title and description both need to match
+        let filter = ChannelFilterConfig {
+            include: vec![FilterRule::All {
+                all: vec![
+                    rule(FilterField::Title, "Karaoke"),
+                    rule(FilterField::Description, "unarchived"),
+                ],
+            }],
+            ..ChannelFilterConfig::default()
+        };
+        assert!(matches_filter(
+            &filter,
+            &video("Karaoke", "unarchived!", &[])
+        ));
+        assert!(!matches_filter(&filter, &video("Karaoke", "", &[])));
+        assert!(!needs_details(&filter));
+
+        // No rules match everything
+        assert!(matches_filter(
+            &ChannelFilterConfig::default(),
+            &video("Anything", "", &[])
+        ));
+    }
+
+    #[test]
+    fn test_matches_schedule() {
+        let schedule = ScheduleConfig {
+            utc_offset: Some("+09:00".into()),
+            after: Some("18:00".into()),
+            before: Some("02:00".into()),
+            days: vec![chrono::Weekday::Sat],
+        };
+        let at = |start: &str| Video {
+            start: Some(start.parse().unwrap()),
+            ..Video::default()
+        };
+
+        // 2024-06-01 is a Saturday
+        assert!(matches_schedule(&schedule, &at("2024-06-01T10:00:00Z"))); // 19:00 JST
+        assert!(matches_schedule(&schedule, &at("2024-06-01T16:30:00Z"))); // 01:30 JST on Sunday, Saturday night
+        assert!(!matches_schedule(&schedule, &at("2024-06-01T03:00:00Z"))); // 12:00 JST
+        assert!(!matches_schedule(&schedule, &at("2024-06-02T10:00:00Z"))); // Sunday
+        assert!(!matches_schedule(&schedule, &Video::default()));
+    }
+
+    #[test]
+    fn test_matches_legacy() {
+        let mut channel = ResolvedChannelConfig {
+            filters: vec![Regex::new("Karaoke").unwrap()],
+            ..ResolvedChannelConfig::default()
+        };
+        assert!(matches_legacy(&channel, &video("Karaoke", "", &[])));
+        assert!(!matches_legacy(&channel, &video("Chat", "Karaoke", &[])));
+        channel.match_description = true;
+        assert!(matches_legacy(&channel, &video("Chat", "Karaoke", &[])));
+
+        // Without regexes, only the structured filter applies
+        channel.filters.clear();
+        assert!(!matches_legacy(&channel, &video("Karaoke", "", &[])));
+        channel.filter = Some(ChannelFilterConfig::default());
+        assert!(matches_legacy(&channel, &video("Karaoke", "", &[])));
+    }
+}
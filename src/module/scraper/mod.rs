@@ -0,0 +1,293 @@
+use super::Message;
+use crate::config::ResolvedChannelConfig;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Instant,
+};
+use tokio::{select, sync::mpsc};
+
+mod channel_page;
+mod filter;
+mod rss;
+
+pub use channel_page::ChannelPage;
+pub use rss::RSS;
+
+/// The set of videos that have already been scraped, along with the time they
+/// were first seen.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+struct ScrapedSet {
+    videos: HashMap<String, DateTime<Utc>>,
+    #[serde(skip)]
+    dirty: bool,
+}
+
+impl ScrapedSet {
+    fn contains(&self, video_id: &str) -> bool {
+        self.videos.contains_key(video_id)
+    }
+
+    fn insert(&mut self, video_id: String, seen: DateTime<Utc>) {
+        if self.videos.insert(video_id, seen).is_none() {
+            self.dirty = true;
+        }
+    }
+
+    /// Forgets videos that were seen before the given time.
+    fn expire(&mut self, before: DateTime<Utc>) {
+        let len = self.videos.len();
+        self.videos.retain(|_, seen| *seen >= before);
+        if self.videos.len() != len {
+            debug!("Expired {} scraped videos", len - self.videos.len());
+            self.dirty = true;
+        }
+    }
+
+    /// Loads the set from a file. Returns an empty set if the file doesn't
+    /// exist yet.
+    async fn load(path: &str) -> Result<Self> {
+        if !Path::new(path).exists() {
+            return Ok(Self::default());
+        }
+        let contents = tokio::fs::read_to_string(path)
+            .await
+            .with_context(|| format!("Failed to read scraped videos from {}", path))?;
+        serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to parse scraped videos from {}", path))
+    }
+
+    /// Writes the set to a file if it changed since the last save.
+    async fn save(&mut self, path: &str) -> Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+
+        // Write to a temporary file first so that a crash doesn't leave a
+        // truncated file behind
+        let contents = serde_json::to_string(&self).context("Failed to serialize scraped set")?;
+        let tmp_path = format!("{}.tmp", path);
+        tokio::fs::write(&tmp_path, contents)
+            .await
+            .with_context(|| format!("Failed to write scraped videos to {}", tmp_path))?;
+        tokio::fs::rename(&tmp_path, path)
+            .await
+            .with_context(|| format!("Failed to move scraped videos to {}", path))?;
+
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Adds the IDs of videos found in a directory, and in its subdirectories
+    /// up to the given depth. The IDs are taken from the file names, which
+    /// need to contain them in brackets or parentheses, as in the default
+    /// ytarchive output format.
+    async fn seed_from_dir(&mut self, dir: &str, depth: usize) -> Result<()> {
+        lazy_static! {
+            static ref ID_RE: Regex = Regex::new(r"[\[(]([0-9A-Za-z_-]{11})[\])]")
+                .expect("Failed to compile video ID regex");
+        }
+
+        if !Path::new(dir).exists() {
+            return Ok(());
+        }
+
+        let mut dirs = vec![(PathBuf::from(dir), depth)];
+        while let Some((dir, depth)) = dirs.pop() {
+            let mut entries = tokio::fs::read_dir(&dir)
+                .await
+                .with_context(|| format!("Failed to read directory {}", dir.display()))?;
+            while let Some(entry) = entries.next_entry().await? {
+                let metadata = entry.metadata().await;
+                if depth > 0 && metadata.as_ref().is_ok_and(|m| m.is_dir()) {
+                    dirs.push((entry.path(), depth - 1));
+                    continue;
+                }
+
+                let name = entry.file_name();
+                let name = name.to_string_lossy();
+                let seen = metadata
+                    .and_then(|m| m.modified())
+                    .map(DateTime::<Utc>::from)
+                    .unwrap_or_else(|_| Utc::now());
+                for cap in ID_RE.captures_iter(&name) {
+                    self.insert(cap[1].to_string(), seen);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Splits a templated outpath into the directory before its first
+/// placeholder, and the number of directory levels below it that the
+/// placeholders can create.
+fn outpath_root(outpath: &str) -> (String, usize) {
+    let components: Vec<&str> = outpath.split(['/', '\\']).collect();
+    match components.iter().position(|c| c.contains('{')) {
+        Some(i) => (components[..i].join("/"), components.len() - i),
+        None => (outpath.to_string(), 0),
+    }
+}
+
+/// Loads the scraped set from disk if a path is given, and optionally seeds
+/// it with the videos already present in each channel's outpath.
+async fn load_scraped(
+    path: Option<&str>,
+    seed_from_outpath: bool,
+    channels: &[ResolvedChannelConfig],
+) -> ScrapedSet {
+    let mut scraped = match path {
+        Some(path) => ScrapedSet::load(path).await.unwrap_or_else(|e| {
+            warn!("Failed to load scraped videos: {:?}", e);
+            ScrapedSet::default()
+        }),
+        None => ScrapedSet::default(),
+    };
+
+    if seed_from_outpath {
+        for channel in channels {
+            let (dir, depth) = outpath_root(&channel.outpath);
+            if let Err(e) = scraped.seed_from_dir(&dir, depth).await {
+                warn!(
+                    "Failed to seed scraped videos for {}: {:?}",
+                    channel.name, e
+                );
+            }
+        }
+    }
+
+    debug!("Loaded {} scraped videos", scraped.videos.len());
+    scraped
+}
+
+/// Expires old entries from the scraped set and writes it to disk if a path
+/// is given.
+async fn persist_scraped(
+    scraped: &Mutex<ScrapedSet>,
+    path: Option<String>,
+    retention: std::time::Duration,
+) -> Result<()> {
+    let mut set = std::mem::take(&mut *scraped.lock().unwrap());
+    if let Ok(retention) = chrono::Duration::from_std(retention) {
+        set.expire(Utc::now() - retention);
+    }
+    let result = match path {
+        Some(path) => set.save(&path).await,
+        None => Ok(()),
+    };
+    *scraped.lock().unwrap() = set;
+    result
+}
+
+/// Waits until the given time while reading the bus. Videos that are sent to
+/// be recorded, by any module, are marked as scraped so that they don't get
+/// picked up again. Returns false if the bus was closed.
+async fn wait_until(
+    wakeup: Instant,
+    rx: &mut mpsc::Receiver<Message>,
+    scraped: &Mutex<ScrapedSet>,
+) -> bool {
+    let sleep = tokio::time::sleep_until(wakeup.into());
+    tokio::pin!(sleep);
+    loop {
+        select! {
+            _ = &mut sleep => return true,
+            msg = rx.recv() => match msg {
+                Some(Message::ToRecord(task)) => {
+                    scraped.lock().unwrap().insert(task.video_id, Utc::now());
+                }
+                Some(_) => (),
+                None => return false,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scraped_set_expire() {
+        let now = Utc::now();
+        let mut set = ScrapedSet::default();
+        set.insert("old".into(), now - chrono::Duration::days(10));
+        set.insert("new".into(), now);
+
+        set.expire(now - chrono::Duration::days(7));
+        assert!(!set.contains("old"));
+        assert!(set.contains("new"));
+    }
+
+    #[tokio::test]
+    async fn test_scraped_set_save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("scraped.json");
+        let path = path.to_str().unwrap();
+
+        let mut set = ScrapedSet::load(path).await.unwrap();
+        assert!(set.videos.is_empty());
+
+        set.insert("IKKar5SS29E".into(), Utc::now());
+        set.save(path).await.unwrap();
+        assert!(!set.dirty);
+
+        let set = ScrapedSet::load(path).await.unwrap();
+        assert!(set.contains("IKKar5SS29E"));
+    }
+
+    #[tokio::test]
+    async fn test_scraped_set_seed_from_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in [
+            "20220314 Karaoke [Moona] (IKKar5SS29E).mp4",
+            "20220315 [stmZAThUl64].jpg",
+            "unrelated.txt",
+        ] {
+            std::fs::write(dir.path().join(name), "").unwrap();
+        }
+
+        let mut set = ScrapedSet::default();
+        set.seed_from_dir(dir.path().to_str().unwrap(), 0)
+            .await
+            .unwrap();
+        assert_eq!(set.videos.len(), 2);
+        assert!(set.contains("IKKar5SS29E"));
+        assert!(set.contains("stmZAThUl64"));
+    }
+
+    #[tokio::test]
+    async fn test_scraped_set_seed_from_templated_outpath() {
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("Moona/2024/06");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::write(nested.join("Karaoke [IKKar5SS29E].mp4"), "").unwrap();
+
+        let template = format!(
+            "{}/{{channel_name}}/{{date:%Y}}/{{date:%m}}",
+            dir.path().display()
+        );
+        let (root, depth) = outpath_root(&template);
+        assert_eq!(root, dir.path().display().to_string());
+        assert_eq!(depth, 3);
+        assert_eq!(outpath_root("./videos"), ("./videos".to_string(), 0));
+
+        let mut set = ScrapedSet::default();
+        set.seed_from_dir(&root, depth).await.unwrap();
+        assert!(set.contains("IKKar5SS29E"));
+
+        // Files deeper than the template are not found
+        let mut set = ScrapedSet::default();
+        set.seed_from_dir(&root, 2).await.unwrap();
+        assert!(!set.contains("IKKar5SS29E"));
+    }
+}
@@ -1,5 +1,10 @@
-use super::{Message, Module, Task};
-use crate::{config, msgbus::BusTx, youtube, APP_USER_AGENT};
+use super::{filter, load_scraped, persist_scraped, wait_until, ScrapedSet};
+use crate::{
+    config, metrics,
+    module::{Message, Module, Task},
+    msgbus::BusTx,
+    youtube, APP_USER_AGENT,
+};
 use anyhow::{Context, Result};
 use async_trait::async_trait;
 use futures::stream::{self, Stream, StreamExt};
@@ -7,7 +12,6 @@ use quick_xml::de::from_reader;
 use reqwest::Client;
 use serde::Deserialize;
 use std::{
-    collections::HashSet,
     sync::{Arc, Mutex},
     time::Duration,
 };
@@ -56,8 +60,8 @@ struct Thumbnail {
 impl RSS {
     async fn run_one(
         &self,
-        scraped: Arc<Mutex<HashSet<String>>>,
-        channel: config::ChannelConfig,
+        scraped: Arc<Mutex<ScrapedSet>>,
+        channel: config::ResolvedChannelConfig,
     ) -> Result<impl Stream<Item = Task>> {
         debug!("Fetching RSS for {}", channel.name);
 
@@ -88,61 +92,94 @@ impl RSS {
         let feed: RSSFeed =
             from_reader(res.bytes().await?.as_ref()).context("Failed to parse RSS feed")?;
 
+        // Find new videos
+        let entries: Vec<FeedEntry> = {
+            let scraped = scraped.lock().unwrap();
+            feed.entries
+                .into_iter()
+                .filter(|entry| {
+                    if scraped.contains(&entry.video_id) {
+                        // Skip if video has already been scraped
+                        debug!("Skipping {}: already scraped", entry.video_id);
+                        false
+                    } else if entry.updated < chrono::Utc::now() - max_age {
+                        // Or if the video is too old
+                        debug!(
+                            "Skipping {}: too old ({} < {})",
+                            entry.video_id,
+                            entry.updated,
+                            chrono::Utc::now() - max_age
+                        );
+                        false
+                    } else {
+                        true
+                    }
+                })
+                .collect()
+        };
+
         // Find matching videos
-        let tasks: Vec<Task> = feed
-            .entries
-            .iter()
-            .filter_map(move |entry| {
-                let mut scraped = scraped.lock().unwrap();
-
-                if scraped.contains(&entry.video_id) {
-                    // Skip if video has already been scraped
-                    debug!("Skipping {}: already scraped", entry.video_id);
-                    return None;
-                } else if entry.updated < chrono::Utc::now() - max_age {
-                    // Or if the video is too old
-                    debug!(
-                        "Skipping {}: too old ({} < {})",
-                        entry.video_id,
-                        entry.updated,
-                        chrono::Utc::now() - max_age
-                    );
-                    return None;
-                } else if !channel.filters.iter().any(|filter| {
-                    filter.is_match(&entry.title)
-                        || (channel.match_description && filter.is_match(&entry.group.description))
-                }) {
-                    // Or if the video doesn't match the filters
+        let mut tasks = vec![];
+        for entry in entries {
+            let matches = filter::matches(
+                &self.client,
+                &channel,
+                &entry.video_id,
+                &entry.title,
+                &entry.group.description,
+            )
+            .await;
+            match matches {
+                Ok(true) => (),
+                Ok(false) => {
                     debug!("Skipping {}: doesn't match filters", entry.video_id);
-                    return None;
+                    continue;
+                }
+                Err(e) => {
+                    // Try again on the next poll
+                    warn!("Failed to filter {}: {:?}", entry.video_id, e);
+                    continue;
                 }
+            }
 
-                // Add to scraped set
-                scraped.insert(entry.video_id.clone());
-
-                // Return the task
-                Some(Task {
-                    title: entry.title.to_owned(),
-                    video_id: entry.video_id.to_owned(),
-                    video_picture: entry.group.thumbnail.url.to_owned(),
-                    channel_name: entry.author.name.to_owned(),
-                    channel_id: entry.channel_id.to_owned(),
-                    channel_picture: channel.picture_url.clone(),
-                    output_directory: channel.outpath.clone(),
-                })
-            })
-            .collect();
+            // Add to scraped set
+            scraped
+                .lock()
+                .unwrap()
+                .insert(entry.video_id.clone(), chrono::Utc::now());
+
+            tasks.push(Task {
+                title: entry.title,
+                video_id: entry.video_id,
+                video_picture: entry.group.thumbnail.url,
+                channel_name: entry.author.name,
+                channel_id: entry.channel_id,
+                channel_picture: channel.picture_url.clone(),
+                output_directory: channel.outpath.clone(),
+                overrides: channel.overrides.clone(),
+            });
+        }
 
         Ok(stream::iter(tasks))
     }
 
-    async fn run_loop(
-        &self,
-        scraped: Arc<Mutex<HashSet<String>>>,
-    ) -> impl Stream<Item = Task> + '_ {
+    async fn run_loop(&self, scraped: Arc<Mutex<ScrapedSet>>) -> impl Stream<Item = Task> + '_ {
         let config = self.config.read().await;
-        stream::iter(config.channel.clone())
-            .map(move |channel| self.run_one(scraped.clone(), channel))
+        stream::iter(config.channels())
+            .map(move |channel| {
+                let scraped = scraped.clone();
+                async move {
+                    let channel_id = channel.id.clone();
+                    let result = self.run_one(scraped, channel).await;
+
+                    let labels = [("channel_id", channel_id.as_str())];
+                    metrics::RSS_POLLS.inc(&labels);
+                    if result.is_err() {
+                        metrics::RSS_POLL_FAILURES.inc(&labels);
+                    }
+                    result
+                }
+            })
             .buffer_unordered(4)
             .filter_map(|one| async { one.map_err(|e| error!("Failed to run RSS: {:?}", e)).ok() })
             .flatten()
@@ -178,7 +215,16 @@ impl Module for RSS {
     }
 
     async fn run(&self, tx: &BusTx<Message>, rx: &mut mpsc::Receiver<Message>) -> Result<()> {
-        let scraped = Arc::new(Mutex::new(HashSet::<String>::new()));
+        let scraped = {
+            let cfg = self.config.read().await;
+            load_scraped(
+                cfg.scraper.rss.scraped_path.as_deref(),
+                cfg.scraper.rss.seed_from_outpath,
+                &cfg.channels(),
+            )
+            .await
+        };
+        let scraped = Arc::new(Mutex::new(scraped));
         loop {
             // Cache channel image URLs
             if let Err(e) = self.cache_picture_url().await {
@@ -201,6 +247,18 @@ impl Module for RSS {
                 return Ok(());
             }
 
+            // Remember what has been scraped across restarts
+            let (path, retention) = {
+                let cfg = self.config.read().await;
+                (
+                    cfg.scraper.rss.scraped_path.clone(),
+                    cfg.scraper.rss.scraped_retention,
+                )
+            };
+            if let Err(e) = persist_scraped(&scraped, path, retention).await {
+                warn!("Failed to save scraped videos: {:?}", e);
+            }
+
             // Determine when to wake up
             let wakeup = {
                 let cfg = self.config.read().await;
@@ -208,17 +266,9 @@ impl Module for RSS {
             };
 
             // Sleep
-            while std::time::Instant::now() < wakeup {
-                match rx.try_recv() {
-                    Ok(_) => continue,
-                    Err(mpsc::error::TryRecvError::Disconnected) => {
-                        debug!("Stopped scraping RSS");
-                        return Ok(());
-                    }
-                    Err(mpsc::error::TryRecvError::Empty) => {
-                        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-                    }
-                }
+            if !wait_until(wakeup, rx, &scraped).await {
+                debug!("Stopped scraping RSS");
+                return Ok(());
             }
         }
     }
@@ -0,0 +1,395 @@
+use super::{
+    recorder::{YTAState, YTAStatus},
+    Message, Module, RecordingStatus, Task,
+};
+use crate::{config::Config, msgbus::BusTx};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, path::Path, sync::Arc};
+use tokio::{
+    io::AsyncWriteExt,
+    sync::{mpsc, RwLock},
+};
+
+/// TaskStore keeps an append-only JSON log of every task and its status
+/// transitions, so that recordings survive a restart. The log is compacted to
+/// the latest entry of each task on startup.
+pub struct TaskStore {
+    config: Arc<RwLock<Config>>,
+    tasks: StoredTasks,
+}
+
+/// The latest entry of each task in the store, keyed by video ID.
+pub type StoredTasks = Arc<RwLock<HashMap<String, StoredTask>>>;
+
+/// A single line in the store file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredTask {
+    pub task: Task,
+    pub status: YTAStatus,
+}
+
+impl StoredTask {
+    /// Returns true if the entry holds the same information as the other one,
+    /// ignoring progress updates.
+    fn same_as(&self, other: &StoredTask) -> bool {
+        self.status.state() == other.status.state()
+            && self.status.output_file() == other.status.output_file()
+            && self.status.is_final() == other.status.is_final()
+    }
+
+    /// Returns true if the task was still in progress when it was stored.
+    fn is_active(&self) -> bool {
+        matches!(
+            self.status.state(),
+            YTAState::Idle | YTAState::Waiting(_) | YTAState::Recording | YTAState::Muxing
+        )
+    }
+}
+
+impl TaskStore {
+    /// Keeps the latest entry of each task in the given map, e.g. so that the
+    /// webserver can list the tasks without reading the file.
+    pub fn with_tasks(mut self, tasks: StoredTasks) -> Self {
+        self.tasks = tasks;
+        self
+    }
+
+    /// Reads the store file and returns the latest entry of each task, in the
+    /// order they were first added.
+    async fn load(path: &str) -> Result<Vec<StoredTask>> {
+        if !Path::new(path).exists() {
+            return Ok(vec![]);
+        }
+
+        let contents = tokio::fs::read_to_string(path)
+            .await
+            .with_context(|| format!("Failed to read task store: {}", path))?;
+
+        let mut order: Vec<String> = vec![];
+        let mut latest: HashMap<String, StoredTask> = HashMap::new();
+        for (n, line) in contents.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            // Skip corrupted lines, e.g. if the process died mid-write
+            let entry: StoredTask = match serde_json::from_str(line) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    warn!("Skipping invalid task store entry on line {}: {}", n + 1, e);
+                    continue;
+                }
+            };
+
+            let id = entry.task.video_id.clone();
+            if latest.insert(id.clone(), entry).is_none() {
+                order.push(id);
+            }
+        }
+
+        Ok(order
+            .into_iter()
+            .filter_map(|id| latest.remove(&id))
+            .collect())
+    }
+
+    /// Marks the tasks that aren't queued again as final, even if the
+    /// recorder stopped before it could say so, and returns the ones that
+    /// were interrupted by the restart.
+    fn restore(entries: &mut [StoredTask]) -> Vec<Task> {
+        let mut requeue = vec![];
+        for entry in entries {
+            if entry.is_active() {
+                requeue.push(entry.task.clone());
+            } else {
+                entry.status.set_final();
+            }
+        }
+        requeue
+    }
+
+    /// Rewrites the store file with the given entries only, so that it
+    /// doesn't keep growing with every restart.
+    async fn compact(path: &str, entries: &[StoredTask]) -> Result<()> {
+        let mut contents = String::new();
+        for entry in entries {
+            contents += &serde_json::to_string(entry).context("Failed to serialize task")?;
+            contents.push('\n');
+        }
+
+        // Write to a temporary file first so that a crash doesn't leave a
+        // truncated file behind
+        let tmp_path = format!("{}.tmp", path);
+        tokio::fs::write(&tmp_path, contents)
+            .await
+            .with_context(|| format!("Failed to write task store to {}", tmp_path))?;
+        tokio::fs::rename(&tmp_path, path)
+            .await
+            .with_context(|| format!("Failed to move task store to {}", path))?;
+        Ok(())
+    }
+
+    /// Appends an entry to the store file.
+    async fn append(path: &str, entry: &StoredTask) -> Result<()> {
+        let mut line = serde_json::to_string(entry).context("Failed to serialize task")?;
+        line.push('\n');
+
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await
+            .with_context(|| format!("Failed to open task store: {}", path))?;
+        file.write_all(line.as_bytes())
+            .await
+            .context("Failed to write to task store")?;
+        file.flush().await.context("Failed to flush task store")?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Module for TaskStore {
+    fn new(config: Arc<RwLock<Config>>) -> Self {
+        Self {
+            config,
+            tasks: Default::default(),
+        }
+    }
+
+    async fn run(&self, tx: &BusTx<Message>, rx: &mut mpsc::Receiver<Message>) -> Result<()> {
+        let path = match &self.config.read().await.store {
+            Some(store) => store.path.clone(),
+            None => {
+                debug!("No task store configured");
+
+                // Noop read the bus
+                while rx.recv().await.is_some() {}
+                return Ok(());
+            }
+        };
+
+        // Load the history and replay it onto the bus
+        let mut history = Self::load(&path).await?;
+        info!("Loaded {} tasks from {}", history.len(), path);
+        let requeue = Self::restore(&mut history);
+        if let Err(e) = Self::compact(&path, &history).await {
+            error!("Failed to compact task store: {:?}", e);
+        }
+
+        for entry in history {
+            tx.send(Message::RecordingStatus(RecordingStatus {
+                task: entry.task.clone(),
+                status: entry.status.clone(),
+            }))
+            .await?;
+            self.tasks
+                .write()
+                .await
+                .insert(entry.task.video_id.clone(), entry);
+        }
+
+        // Re-queue tasks that were interrupted by the restart
+        for task in requeue {
+            info!(
+                "Re-queueing task [{}][{}][{}]",
+                task.video_id, task.channel_name, task.title
+            );
+            tx.send(Message::ToRecord(task)).await?;
+        }
+
+        // Record every change from now on
+        let mut shutting_down = false;
+        while let Some(msg) = rx.recv().await {
+            let entry = match msg {
+                Message::Shutdown => {
+                    shutting_down = true;
+                    continue;
+                }
+                // Keep tasks interrupted by the shutdown active, so they are
+                // queued again on restart
+                Message::RecordingStatus(recstat)
+                    if shutting_down && recstat.status.state() == &YTAState::Interrupted =>
+                {
+                    continue;
+                }
+                Message::ToRecord(task) => {
+                    // Don't overwrite the status of a task that's in progress
+                    if let Some(existing) = self.tasks.read().await.get(&task.video_id) {
+                        if existing.is_active() {
+                            continue;
+                        }
+                    }
+                    StoredTask {
+                        task,
+                        status: YTAStatus::new(),
+                    }
+                }
+                Message::RecordingStatus(recstat) => StoredTask {
+                    task: recstat.task,
+                    status: recstat.status,
+                },
+                _ => continue,
+            };
+
+            // Skip progress-only updates
+            if let Some(existing) = self.tasks.read().await.get(&entry.task.video_id) {
+                if existing.same_as(&entry) {
+                    continue;
+                }
+            }
+
+            if let Err(e) = Self::append(&path, &entry).await {
+                error!("Failed to store task {}: {:?}", entry.task.video_id, e);
+            }
+            self.tasks
+                .write()
+                .await
+                .insert(entry.task.video_id.clone(), entry);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::module::recorder::BackendKind;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[tokio::test]
+    async fn test_load_missing_file() {
+        let entries = TaskStore::load("/nonexistent/hoshinova.jsonl")
+            .await
+            .unwrap();
+        assert!(entries.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_append_and_load() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_str().unwrap();
+
+        let mut status = YTAStatus::new();
+        TaskStore::append(
+            path,
+            &StoredTask {
+                task: Task::test("a"),
+                status: status.clone(),
+            },
+        )
+        .await
+        .unwrap();
+        TaskStore::append(
+            path,
+            &StoredTask {
+                task: Task::test("b"),
+                status: status.clone(),
+            },
+        )
+        .await
+        .unwrap();
+        BackendKind::Ytarchive
+            .backend()
+            .parse_line(&mut status, "Final file: /tmp/a.mp4");
+        TaskStore::append(
+            path,
+            &StoredTask {
+                task: Task::test("a"),
+                status,
+            },
+        )
+        .await
+        .unwrap();
+
+        let entries = TaskStore::load(path).await.unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].task.video_id, "a");
+        assert_eq!(entries[0].status.state(), &YTAState::Finished);
+        assert_eq!(
+            entries[0].status.output_file(),
+            Some(&"/tmp/a.mp4".to_string())
+        );
+        assert!(!entries[0].is_active());
+        assert_eq!(entries[1].task.video_id, "b");
+        assert!(entries[1].is_active());
+
+        // The final status is stored even though nothing else changed
+        let mut last = entries[0].clone();
+        last.status.set_final();
+        assert!(!entries[0].same_as(&last));
+    }
+
+    #[tokio::test]
+    async fn test_load_skips_corrupted_lines() {
+        let mut file = NamedTempFile::new().unwrap();
+        let entry = StoredTask {
+            task: Task::test("a"),
+            status: YTAStatus::new(),
+        };
+        writeln!(file, "{}", serde_json::to_string(&entry).unwrap()).unwrap();
+        write!(file, "{{\"task\":{{\"title\"").unwrap();
+
+        let entries = TaskStore::load(file.path().to_str().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].task.video_id, "a");
+    }
+
+    #[test]
+    fn test_restore() {
+        let entry = |video_id: &str, line: Option<&str>| {
+            let mut status = YTAStatus::new();
+            if let Some(line) = line {
+                BackendKind::Ytarchive
+                    .backend()
+                    .parse_line(&mut status, line);
+            }
+            StoredTask {
+                task: Task::test(video_id),
+                status,
+            }
+        };
+        let mut entries = vec![
+            entry("AAAAAAAAAAA", Some("Final file: /tmp/a.mp4")),
+            entry("BBBBBBBBBBB", Some("Muxing final file...")),
+            entry("CCCCCCCCCCC", None),
+        ];
+        assert_eq!(entries[1].status.state(), &YTAState::Muxing);
+
+        // Muxing was interrupted, so the recording is done again
+        let requeue = TaskStore::restore(&mut entries);
+        let ids: Vec<_> = requeue.iter().map(|t| t.video_id.as_str()).collect();
+        assert_eq!(ids, vec!["BBBBBBBBBBB", "CCCCCCCCCCC"]);
+        assert!(entries[0].status.is_final());
+        assert!(!entries[1].status.is_final());
+        assert!(!entries[2].status.is_final());
+    }
+
+    #[tokio::test]
+    async fn test_compact() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("tasks.jsonl");
+        let path = path.to_str().unwrap();
+        for video_id in ["a", "b", "a"] {
+            let entry = StoredTask {
+                task: Task::test(video_id),
+                status: YTAStatus::new(),
+            };
+            TaskStore::append(path, &entry).await.unwrap();
+        }
+
+        let entries = TaskStore::load(path).await.unwrap();
+        TaskStore::compact(path, &entries).await.unwrap();
+        assert_eq!(std::fs::read_to_string(path).unwrap().lines().count(), 2);
+        let compacted = TaskStore::load(path).await.unwrap();
+        let ids: Vec<_> = compacted.iter().map(|e| e.task.video_id.as_str()).collect();
+        assert_eq!(ids, vec!["a", "b"]);
+        assert!(!Path::new(&format!("{}.tmp", path)).exists());
+    }
+}
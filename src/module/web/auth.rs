@@ -0,0 +1,309 @@
+use crate::config::{AuthConfig, AuthScope, Config};
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::{header, Method},
+    middleware::Next,
+    web::Data,
+    HttpMessage, HttpResponse,
+};
+use base64::Engine;
+use lazy_static::lazy_static;
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, sync::Arc, sync::Mutex};
+use tokio::sync::RwLock;
+
+lazy_static! {
+    /// Basic auth credentials that were already checked, keyed by the digest
+    /// of the Authorization header, with the password hash they matched.
+    /// bcrypt is deliberately slow, so we don't want to run it on every
+    /// request the web UI makes.
+    static ref VERIFIED: Mutex<HashMap<[u8; 32], String>> = Mutex::new(HashMap::new());
+}
+
+/// Upper bound on the number of cached credentials
+const VERIFIED_MAX: usize = 256;
+
+fn digest(value: &[u8]) -> [u8; 32] {
+    Sha256::digest(value).into()
+}
+
+/// Returns the scope needed to serve the request. Routes that expose secrets
+/// even when read, such as the config, are wrapped in [`require_admin`].
+fn required_scope(req: &ServiceRequest) -> AuthScope {
+    if matches!(*req.method(), Method::GET | Method::HEAD) {
+        AuthScope::Read
+    } else {
+        AuthScope::Admin
+    }
+}
+
+/// Returns the auth config of the webserver, if auth is enabled.
+async fn auth_config(req: &ServiceRequest) -> Option<AuthConfig> {
+    let config = req.app_data::<Data<Arc<RwLock<Config>>>>()?;
+    let config = config.read().await;
+    config.webserver.as_ref().and_then(|ws| ws.auth.clone())
+}
+
+/// Checks a bearer token against the configured tokens and returns the scope
+/// it grants.
+async fn check_token(auth: &AuthConfig, token: &str) -> Option<AuthScope> {
+    // Compare digests so that the comparison time doesn't depend on how much
+    // of the token matches
+    let given = digest(token.as_bytes());
+    let mut scope = None;
+    for cfg in &auth.token {
+        let expected = match cfg.get_token().await {
+            Ok(expected) => expected,
+            Err(e) => {
+                error!("Failed to get webserver auth token: {:?}", e);
+                continue;
+            }
+        };
+        if !expected.is_empty() && digest(expected.as_bytes()) == given {
+            scope = scope.max(Some(cfg.scope));
+        }
+    }
+    scope
+}
+
+/// Checks basic auth credentials against the configured users and returns
+/// the scope they grant.
+async fn check_basic(auth: &AuthConfig, credentials: &str) -> Option<AuthScope> {
+    let decoded = base64::engine::general_purpose::STANDARD
+        .decode(credentials)
+        .ok()?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    let (username, password) = decoded.split_once(':')?;
+    let user = auth.user.iter().find(|u| u.username == username)?;
+
+    // The cache entry is only valid if the password hash hasn't changed
+    let key = digest(credentials.as_bytes());
+    if let Some(hash) = VERIFIED.lock().unwrap().get(&key) {
+        if *hash == user.password_hash {
+            return Some(user.scope);
+        }
+    }
+
+    let password = password.to_string();
+    let hash = user.password_hash.clone();
+    let valid = tokio::task::spawn_blocking(move || bcrypt::verify(password, &hash))
+        .await
+        .ok()?;
+    match valid {
+        Ok(true) => {
+            let mut verified = VERIFIED.lock().unwrap();
+            if verified.len() >= VERIFIED_MAX {
+                verified.clear();
+            }
+            verified.insert(key, user.password_hash.clone());
+            Some(user.scope)
+        }
+        Ok(false) => None,
+        Err(e) => {
+            error!("Invalid password hash for user {}: {}", user.username, e);
+            None
+        }
+    }
+}
+
+/// Middleware that enforces the credentials configured in
+/// `webserver.auth`. Requests are let through if no auth is configured.
+pub async fn authenticate<B: MessageBody>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> actix_web::Result<ServiceResponse<EitherBody<B>>> {
+    let auth = match auth_config(&req).await {
+        Some(auth) => auth,
+        None => return Ok(next.call(req).await?.map_into_left_body()),
+    };
+
+    let header = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or_default();
+    let scope = if let Some(token) = header.strip_prefix("Bearer ") {
+        check_token(&auth, token.trim()).await
+    } else if let Some(credentials) = header.strip_prefix("Basic ") {
+        check_basic(&auth, credentials.trim()).await
+    } else {
+        None
+    };
+
+    let response = match scope {
+        Some(scope) if scope >= required_scope(&req) => {
+            // Keep the scope for the routes that need more than the method
+            req.extensions_mut().insert(scope);
+            return Ok(next.call(req).await?.map_into_left_body());
+        }
+        Some(_) => HttpResponse::Forbidden().body("Insufficient scope"),
+        None => HttpResponse::Unauthorized()
+            .insert_header((header::WWW_AUTHENTICATE, "Basic realm=\"hoshinova\""))
+            .body("Unauthorized"),
+    };
+    Ok(req.into_response(response).map_into_right_body())
+}
+
+/// Middleware for the routes that need the admin scope whatever the method.
+/// It runs after routing, so it applies however the path was written, and
+/// relies on the scope granted by [`authenticate`].
+pub async fn require_admin<B: MessageBody>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> actix_web::Result<ServiceResponse<EitherBody<B>>> {
+    let scope = req.extensions().get::<AuthScope>().copied();
+    if scope == Some(AuthScope::Admin) || auth_config(&req).await.is_none() {
+        return Ok(next.call(req).await?.map_into_left_body());
+    }
+    let response = HttpResponse::Forbidden().body("Insufficient scope");
+    Ok(req.into_response(response).map_into_right_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{TokenConfig, UserConfig, WebserverConfig};
+    use actix_web::{get, middleware::from_fn, put, test, App, HttpResponse, Responder};
+
+    #[get("/api/tasks")]
+    async fn get_tasks() -> impl Responder {
+        HttpResponse::Ok()
+    }
+
+    #[get("/api/config", wrap = "from_fn(require_admin)")]
+    async fn get_config() -> impl Responder {
+        HttpResponse::Ok()
+    }
+
+    #[put("/api/config/toml", wrap = "from_fn(require_admin)")]
+    async fn put_config_toml() -> impl Responder {
+        HttpResponse::Ok()
+    }
+
+    fn basic(username: &str, password: &str) -> String {
+        let credentials = format!("{}:{}", username, password);
+        format!(
+            "Basic {}",
+            base64::engine::general_purpose::STANDARD.encode(credentials)
+        )
+    }
+
+    #[actix_web::test]
+    async fn test_authenticate() {
+        let mut config = Config::default();
+        config.webserver = Some(WebserverConfig {
+            auth: Some(AuthConfig {
+                token: vec![TokenConfig {
+                    token: Some("readtoken".into()),
+                    token_file: None,
+                    scope: AuthScope::Read,
+                }],
+                user: vec![UserConfig {
+                    username: "admin".into(),
+                    password_hash: bcrypt::hash("hunter2", 4).unwrap(),
+                    scope: AuthScope::Admin,
+                }],
+            }),
+            ..WebserverConfig::default()
+        });
+
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(Arc::new(RwLock::new(config))))
+                .wrap(from_fn(authenticate))
+                .service(get_tasks)
+                .service(get_config)
+                .service(put_config_toml),
+        )
+        .await;
+
+        let cases = [
+            (Method::GET, "/api/tasks", None, 401),
+            (Method::GET, "/api/tasks", Some("Bearer wrong".into()), 401),
+            (
+                Method::GET,
+                "/api/tasks",
+                Some("Bearer readtoken".into()),
+                200,
+            ),
+            (
+                Method::GET,
+                "/api/config",
+                Some("Bearer readtoken".into()),
+                403,
+            ),
+            // Routing decodes the path, so the scope can't depend on it
+            (
+                Method::GET,
+                "/api/%63onfig",
+                Some("Bearer readtoken".into()),
+                403,
+            ),
+            (
+                Method::PUT,
+                "/api/config/toml",
+                Some("Bearer readtoken".into()),
+                403,
+            ),
+            (
+                Method::GET,
+                "/api/tasks",
+                Some(basic("admin", "wrong")),
+                401,
+            ),
+            (
+                Method::GET,
+                "/api/tasks",
+                Some(basic("nobody", "hunter2")),
+                401,
+            ),
+            (
+                Method::GET,
+                "/api/config",
+                Some(basic("admin", "hunter2")),
+                200,
+            ),
+            (
+                Method::GET,
+                "/api/%63onfig",
+                Some(basic("admin", "hunter2")),
+                200,
+            ),
+            (
+                Method::PUT,
+                "/api/config/toml",
+                Some(basic("admin", "hunter2")),
+                200,
+            ),
+        ];
+        for (method, uri, authorization, status) in cases {
+            let mut req = test::TestRequest::default().method(method).uri(uri);
+            if let Some(authorization) = &authorization {
+                req = req.insert_header((header::AUTHORIZATION, authorization.as_str()));
+            }
+            let resp = test::call_service(&app, req.to_request()).await;
+            assert_eq!(resp.status(), status, "{} {:?}", uri, authorization);
+            if status == 401 {
+                assert!(resp.headers().contains_key(header::WWW_AUTHENTICATE));
+            }
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_authenticate_disabled() {
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(Arc::new(RwLock::new(Config::default()))))
+                .wrap(from_fn(authenticate))
+                .service(put_config_toml),
+        )
+        .await;
+
+        let req = test::TestRequest::put()
+            .uri("/api/config/toml")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+    }
+}
@@ -1,21 +1,37 @@
-use super::TaskMap;
+use super::{auth, EventSender, LogMap, LogSender, TaskEvent, TaskMap, TaskWithStatus};
 use crate::{
     config::Config,
-    module::{Message, Task},
+    metrics,
+    module::{
+        recorder::{disk, janitor, tasklog, YTAState},
+        store::StoredTasks,
+        Message, Task, TaskOverrides,
+    },
     msgbus::BusTx,
     youtube,
 };
 use actix_web::{
-    error::{ErrorBadRequest, ErrorForbidden, ErrorInternalServerError},
-    get, post, put,
+    delete,
+    error::{ErrorBadRequest, ErrorForbidden, ErrorInternalServerError, ErrorNotFound},
+    get,
+    middleware::from_fn,
+    post, put,
     web::{self, Data},
     HttpResponse, Responder,
 };
 use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use lazy_static::lazy_static;
+use regex::Regex;
 use rust_embed::RustEmbed;
-use serde::Deserialize;
-use std::sync::Arc;
-use tokio::sync::RwLock;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::sync::{broadcast, RwLock};
 use ts_rs::TS;
 
 #[derive(RustEmbed)]
@@ -26,12 +42,19 @@ struct StaticFiles;
 /// Configure routes for the webserver
 pub fn configure(cfg: &mut actix_web::web::ServiceConfig) {
     cfg.service(get_tasks)
+        .service(get_disk)
+        .service(get_orphans)
         .service(post_task)
+        .service(delete_task)
+        .service(get_task_log)
+        .service(get_history)
+        .service(get_events)
         .service(get_version)
         .service(get_config)
         .service(get_config_toml)
         .service(put_config_toml)
         .service(reload_config)
+        .service(get_metrics)
         .service(serve_static);
 }
 
@@ -46,16 +69,56 @@ async fn get_tasks(data: TaskMap) -> actix_web::Result<impl Responder> {
     ))
 }
 
+/// Reports the free space of the working and output directories.
+#[get("/api/disk")]
+async fn get_disk(config: Data<Arc<RwLock<Config>>>) -> actix_web::Result<impl Responder> {
+    let config = config.read().await.clone();
+    let usage = web::block(move || disk::usage(&config)).await?;
+    Ok(HttpResponse::Ok().json(usage))
+}
+
+/// Lists the files in the working directories that the janitor considers
+/// orphaned, whether or not it's enabled.
+#[get("/api/janitor/orphans")]
+async fn get_orphans(
+    config: Data<Arc<RwLock<Config>>>,
+    tasks: TaskMap,
+) -> actix_web::Result<impl Responder> {
+    let active_ids: HashSet<String> = tasks
+        .read()
+        .await
+        .values()
+        .filter(|t| !t.is_finished())
+        .map(|t| t.task.video_id.clone())
+        .collect();
+    let config = config.read().await.clone();
+    let orphans = web::block(move || {
+        let cfg = config.ytarchive.janitor.clone().unwrap_or_default();
+        janitor::find_orphans(
+            &janitor::working_directories(&config),
+            &active_ids,
+            cfg.min_age,
+            &janitor::protected_paths(&config),
+        )
+    })
+    .await?;
+    Ok(HttpResponse::Ok().json(orphans))
+}
+
 #[derive(Deserialize, TS)]
 #[ts(export)]
 struct CreateTaskRequest {
     video_url: String,
     output_directory: String,
+    /// Defaults to the overrides of the video's channel, if it's configured
+    #[ts(optional)]
+    overrides: Option<TaskOverrides>,
 }
 
 #[post("/api/task")]
 async fn post_task(
     tx: Data<BusTx<Message>>,
+    config: Data<Arc<RwLock<Config>>>,
     taskreq: web::Json<CreateTaskRequest>,
 ) -> actix_web::Result<impl Responder> {
     let taskreq = taskreq.into_inner();
@@ -87,6 +150,19 @@ async fn post_task(
                 ErrorInternalServerError(anyhow!("Failed to fetch channel picture: {:?}", e))
             })?;
 
+    // Use the overrides of the channel unless some are given
+    let overrides = match taskreq.overrides {
+        Some(overrides) => overrides,
+        None => config
+            .read()
+            .await
+            .channels()
+            .into_iter()
+            .find(|channel| channel.id == ipr.video_details.channel_id)
+            .map(|channel| channel.overrides)
+            .unwrap_or_default(),
+    };
+
     // Create the task
     let task = Task {
         title: ipr.video_details.title,
@@ -96,6 +172,7 @@ async fn post_task(
         channel_id: ipr.video_details.channel_id,
         channel_picture: Some(channel_picture),
         output_directory: taskreq.output_directory,
+        overrides,
     };
 
     // Broadcast it to the bus
@@ -106,17 +183,442 @@ async fn post_task(
     Ok(HttpResponse::Accepted().finish())
 }
 
+#[delete("/api/task/{video_id}")]
+async fn delete_task(
+    tx: Data<BusTx<Message>>,
+    tasks: TaskMap,
+    video_id: web::Path<String>,
+) -> actix_web::Result<impl Responder> {
+    let video_id = video_id.into_inner();
+    if !tasks.read().await.contains_key(&video_id) {
+        return Err(ErrorNotFound(format!("Task {} not found", video_id)));
+    }
+
+    // Ask the recorder to stop the task
+    tx.send(Message::CancelTask(video_id))
+        .await
+        .map_err(|e| ErrorInternalServerError(format!("{:?}", e)))?;
+
+    Ok(HttpResponse::Accepted().finish())
+}
+
+#[derive(Deserialize)]
+struct LogQuery {
+    /// Only return the last lines
+    tail: Option<usize>,
+    /// Keep the response open and stream new lines as they're written
+    #[serde(default)]
+    follow: bool,
+}
+
+/// Returns the log of a task as plain text. Recent lines come from memory, and
+/// the rest from the log files. When following, new lines are streamed until
+/// the recorder is done with the task. The log holds the command lines of the
+/// recordings, with their cookie files and extra args, so reading it needs the
+/// admin scope.
+#[get("/api/task/{video_id}/log", wrap = "from_fn(auth::require_admin)")]
+async fn get_task_log(
+    config: Data<Arc<RwLock<Config>>>,
+    tasks: TaskMap,
+    events: EventSender,
+    logs: LogMap,
+    output: LogSender,
+    video_id: web::Path<String>,
+    query: web::Query<LogQuery>,
+) -> actix_web::Result<impl Responder> {
+    lazy_static! {
+        static ref VIDEO_ID_RE: Regex = Regex::new(r"^[0-9A-Za-z_-]{11}$").unwrap();
+    }
+
+    let video_id = video_id.into_inner();
+    if !VIDEO_ID_RE.is_match(&video_id) {
+        return Err(ErrorBadRequest(format!("Invalid video ID: {}", video_id)));
+    }
+    let LogQuery { tail, follow } = query.into_inner();
+    let cfg = config.read().await.ytarchive.task_log.clone();
+
+    // Tasks are updated before their status is sent, so subscribing first
+    // makes sure the final status isn't missed
+    let status_rx = events.subscribe();
+    let finished = tasks
+        .read()
+        .await
+        .get(&video_id)
+        .is_none_or(|task| task.is_finished());
+
+    // Subscribe while the buffer can't change, so that no line is missed
+    let (buffered, rx) = {
+        let logs = logs.read().await;
+        (logs.get(&video_id).cloned(), output.subscribe())
+    };
+    let tail_of = |mut lines: Vec<String>| -> Vec<String> {
+        let skip = tail.map_or(0, |tail| lines.len().saturating_sub(tail));
+        lines.split_off(skip)
+    };
+    let (lines, streamed) = match buffered {
+        Some(buffer) if tail.is_some_and(|tail| tail <= buffer.len()) => {
+            (tail_of(buffer.into()), VecDeque::new())
+        }
+        buffered => {
+            let id = video_id.clone();
+            let buffer: Vec<String> = buffered.map(Vec::from).unwrap_or_default();
+            match web::block(move || tasklog::read(&cfg, &id, tail))
+                .await?
+                .map_err(|e| ErrorInternalServerError(format!("{:?}", e)))?
+            {
+                Some(mut lines) => {
+                    let (newer, streamed) = merge_log(&lines, buffer);
+                    lines.extend(newer);
+                    (tail_of(lines), streamed)
+                }
+                None if !buffer.is_empty() => (tail_of(buffer), VecDeque::new()),
+                None => return Err(ErrorNotFound(format!("No log for task {}", video_id))),
+            }
+        }
+    };
+
+    let body: String = lines.into_iter().map(|line| line + "\n").collect();
+    if !follow {
+        return Ok(HttpResponse::Ok()
+            .content_type("text/plain; charset=utf-8")
+            .body(body));
+    }
+
+    let state = (rx, status_rx, video_id, streamed, finished);
+    let new_lines = futures::stream::unfold(state, |state| async move {
+        let (mut rx, mut status_rx, video_id, mut streamed, mut finished) = state;
+        loop {
+            let output = if finished {
+                // The remaining lines were sent before the final status
+                rx.try_recv().map_err(|e| match e {
+                    broadcast::error::TryRecvError::Lagged(n) => {
+                        broadcast::error::RecvError::Lagged(n)
+                    }
+                    _ => broadcast::error::RecvError::Closed,
+                })
+            } else {
+                tokio::select! {
+                    biased;
+                    output = rx.recv() => output,
+                    event = status_rx.recv() => {
+                        match event {
+                            Ok(TaskEvent::RecordingStatus(recstat))
+                                if recstat.task.video_id == video_id
+                                    && recstat.status.is_final() =>
+                            {
+                                finished = true;
+                            }
+                            Ok(_) => (),
+                            Err(broadcast::error::RecvError::Lagged(n)) => {
+                                debug!("Log stream lagged behind, skipped {} events", n);
+                            }
+                            Err(broadcast::error::RecvError::Closed) => return None,
+                        }
+                        continue;
+                    }
+                }
+            };
+            match output {
+                Ok(output) if output.video_id == video_id => {
+                    // Skip the lines that were already read from the file
+                    if streamed.front() == Some(&output.line) {
+                        streamed.pop_front();
+                        continue;
+                    }
+                    streamed.clear();
+                    let bytes = web::Bytes::from(output.line + "\n");
+                    let state = (rx, status_rx, video_id, streamed, finished);
+                    return Some((Ok::<_, actix_web::Error>(bytes), state));
+                }
+                Ok(_) => continue,
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    debug!("Log stream lagged behind, skipped {} lines", n);
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+    let stream = futures::stream::once(async move { Ok(web::Bytes::from(body)) }).chain(new_lines);
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; charset=utf-8")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(stream))
+}
+
+/// Lines up the log read from a task's file with the lines buffered in memory
+/// when it was read. The file can be behind the buffer, or ahead of it by the
+/// lines that are yet to be streamed. Returns the buffered lines that are
+/// missing from the file, and the lines of the file that will be streamed.
+fn merge_log(file: &[String], buffer: Vec<String>) -> (Vec<String>, VecDeque<String>) {
+    if file.is_empty() || buffer.is_empty() {
+        return (buffer, VecDeque::new());
+    }
+
+    // Lines are only in flight briefly, so the ends are at most a buffer
+    // apart
+    let ahead = (file.len().saturating_sub(buffer.len()).max(1)..=file.len())
+        .rev()
+        .find(|&end| file[..end].ends_with(&buffer[buffer.len().saturating_sub(end)..]));
+    if let Some(end) = ahead {
+        return (vec![], file[end..].iter().cloned().collect());
+    }
+
+    let mut buffer = buffer;
+    let behind = (1..buffer.len())
+        .find(|&end| buffer[..end].ends_with(&file[file.len().saturating_sub(end)..]))
+        .unwrap_or(0);
+    (buffer.split_off(behind), VecDeque::new())
+}
+
+/// A task that is done recording, successfully or not.
+#[derive(Debug, Clone, TS, Serialize)]
+#[ts(export)]
+struct HistoryEntry {
+    task: Task,
+    state: YTAState,
+    started_at: Option<DateTime<Utc>>,
+    finished_at: Option<DateTime<Utc>>,
+    /// Seconds between the start and the end of the recording
+    #[ts(type = "number | null")]
+    duration_seconds: Option<i64>,
+    output_file: Option<String>,
+    /// Size of the output file in bytes, if it still exists
+    #[ts(type = "number | null")]
+    file_size: Option<u64>,
+}
+
+impl From<TaskWithStatus> for HistoryEntry {
+    fn from(TaskWithStatus { task, status, .. }: TaskWithStatus) -> Self {
+        let output_file = status.output_file().cloned();
+        HistoryEntry {
+            task,
+            state: status.state().clone(),
+            started_at: status.started_at(),
+            finished_at: status.finished_at(),
+            duration_seconds: status
+                .started_at()
+                .zip(status.finished_at())
+                .map(|(start, end)| (end - start).num_seconds()),
+            file_size: output_file
+                .as_ref()
+                .and_then(|path| std::fs::metadata(path).ok())
+                .map(|metadata| metadata.len()),
+            output_file,
+        }
+    }
+}
+
+#[derive(Debug, TS, Serialize)]
+#[ts(export)]
+struct HistoryPage {
+    /// Number of entries matching the filters, across all pages
+    total: usize,
+    entries: Vec<HistoryEntry>,
+}
+
+#[derive(Deserialize)]
+struct HistoryQuery {
+    /// Channel ID or name
+    channel: Option<String>,
+    state: Option<String>,
+    /// Only tasks that finished at or after this time
+    since: Option<DateTime<Utc>>,
+    /// Only tasks that finished before this time
+    until: Option<DateTime<Utc>>,
+    #[serde(default)]
+    offset: usize,
+    #[serde(default = "default_history_page_size")]
+    limit: usize,
+}
+
+fn default_history_page_size() -> usize {
+    50
+}
+
+impl HistoryQuery {
+    fn matches(&self, t: &TaskWithStatus) -> bool {
+        let finished_at = t.status.finished_at();
+        self.channel
+            .as_ref()
+            .is_none_or(|c| *c == t.task.channel_id || *c == t.task.channel_name)
+            && self
+                .state
+                .as_ref()
+                .is_none_or(|s| s.eq_ignore_ascii_case(t.status.state().as_str()))
+            && self
+                .since
+                .is_none_or(|since| finished_at.is_some_and(|f| f >= since))
+            && self
+                .until
+                .is_none_or(|until| finished_at.is_some_and(|f| f < until))
+    }
+}
+
+/// Lists the finished tasks, the most recently finished first. They're taken
+/// from the task store if there is one, so that tasks pruned from memory are
+/// still listed.
+#[get("/api/history")]
+async fn get_history(
+    stored: Data<StoredTasks>,
+    tasks: TaskMap,
+    query: web::Query<HistoryQuery>,
+) -> actix_web::Result<impl Responder> {
+    let query = query.into_inner();
+    let mut finished: HashMap<String, TaskWithStatus> = stored
+        .read()
+        .await
+        .iter()
+        .filter(|(_, entry)| entry.status.is_final())
+        .map(|(id, entry)| {
+            let task = TaskWithStatus {
+                task: entry.task.clone(),
+                status: entry.status.clone(),
+                queue_position: None,
+                held_reason: None,
+            };
+            (id.clone(), task)
+        })
+        .collect();
+    // Tasks in memory are more up to date, e.g. if they're recorded again
+    for (id, task) in tasks.read().await.iter() {
+        if task.is_finished() {
+            finished.insert(id.clone(), task.clone());
+        } else {
+            finished.remove(id);
+        }
+    }
+
+    let mut matching: Vec<TaskWithStatus> = finished
+        .into_values()
+        .filter(|t| query.matches(t))
+        .collect();
+    matching.sort_by_key(|t| std::cmp::Reverse(t.status.finished_at()));
+
+    let total = matching.len();
+    let page: Vec<_> = matching
+        .into_iter()
+        .skip(query.offset)
+        .take(query.limit)
+        .collect();
+    // Looking up the file sizes touches the disk
+    let entries = web::block(move || page.into_iter().map(HistoryEntry::from).collect()).await?;
+    Ok(HttpResponse::Ok().json(HistoryPage { total, entries }))
+}
+
+/// Formats an event as a server-sent event message.
+fn format_event(event: &TaskEvent) -> serde_json::Result<web::Bytes> {
+    Ok(web::Bytes::from(format!(
+        "data: {}\n\n",
+        serde_json::to_string(event)?
+    )))
+}
+
+/// Streams task status updates and notifications as server-sent events.
+#[get("/api/events")]
+async fn get_events(events: EventSender) -> actix_web::Result<impl Responder> {
+    let rx = events.subscribe();
+    let stream = futures::stream::unfold(rx, |mut rx| async move {
+        loop {
+            // Send a comment every now and then so that proxies don't close
+            // idle connections
+            let event = match tokio::time::timeout(Duration::from_secs(15), rx.recv()).await {
+                Ok(Ok(event)) => event,
+                Ok(Err(broadcast::error::RecvError::Lagged(n))) => {
+                    debug!("Event stream lagged behind, skipped {} events", n);
+                    continue;
+                }
+                Ok(Err(broadcast::error::RecvError::Closed)) => return None,
+                Err(_) => return Some((Ok(web::Bytes::from_static(b": keep-alive\n\n")), rx)),
+            };
+
+            match format_event(&event) {
+                Ok(bytes) => return Some((Ok::<_, actix_web::Error>(bytes), rx)),
+                Err(e) => error!("Failed to serialize event: {:?}", e),
+            }
+        }
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(stream))
+}
+
+/// Updates the metrics that are derived from the current tasks.
+fn update_task_metrics(tasks: &HashMap<String, TaskWithStatus>) {
+    let mut states: HashMap<&str, usize> = [
+        YTAState::Idle,
+        YTAState::Waiting(None),
+        YTAState::Recording,
+        YTAState::Muxing,
+    ]
+    .iter()
+    .map(|state| (state.as_str(), 0))
+    .collect();
+    let mut queued = 0;
+
+    metrics::TASK_VIDEO_FRAGMENTS.clear();
+    metrics::TASK_AUDIO_FRAGMENTS.clear();
+    metrics::TASK_DOWNLOADED_BYTES.clear();
+    for TaskWithStatus {
+        task,
+        status,
+        queue_position,
+        ..
+    } in tasks.values()
+    {
+        if queue_position.is_some() {
+            queued += 1;
+            continue;
+        }
+        *states.entry(status.state().as_str()).or_default() += 1;
+
+        if !status.state().is_active() {
+            continue;
+        }
+        let labels = [
+            ("video_id", task.video_id.as_str()),
+            ("channel_name", task.channel_name.as_str()),
+        ];
+        if let Some(n) = status.video_fragments() {
+            metrics::TASK_VIDEO_FRAGMENTS.set(&labels, n as f64);
+        }
+        if let Some(n) = status.audio_fragments() {
+            metrics::TASK_AUDIO_FRAGMENTS.set(&labels, n as f64);
+        }
+        if let Some(n) = status.downloaded_bytes() {
+            metrics::TASK_DOWNLOADED_BYTES.set(&labels, n as f64);
+        }
+    }
+
+    metrics::TASKS.clear();
+    for (state, n) in states {
+        metrics::TASKS.set(&[("state", state)], n as f64);
+    }
+    metrics::QUEUED_TASKS.set(&[], queued as f64);
+}
+
+#[get("/metrics")]
+async fn get_metrics(tasks: TaskMap) -> actix_web::Result<impl Responder> {
+    update_task_metrics(&*tasks.read().await);
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics::render()))
+}
+
 #[get("/api/version")]
 async fn get_version() -> actix_web::Result<impl Responder> {
     Ok(HttpResponse::Ok().body(crate::APP_NAME.to_owned()))
 }
 
-#[get("/api/config")]
+#[get("/api/config", wrap = "from_fn(auth::require_admin)")]
 async fn get_config(config: Data<Arc<RwLock<Config>>>) -> actix_web::Result<impl Responder> {
     Ok(HttpResponse::Ok().json(config.read().await.to_owned()))
 }
 
-#[post("/api/config/reload")]
+#[post("/api/config/reload", wrap = "from_fn(auth::require_admin)")]
 async fn reload_config(config: Data<Arc<RwLock<Config>>>) -> actix_web::Result<impl Responder> {
     config
         .write()
@@ -127,7 +629,7 @@ async fn reload_config(config: Data<Arc<RwLock<Config>>>) -> actix_web::Result<i
     Ok(HttpResponse::Ok().json("ok"))
 }
 
-#[get("/api/config/toml")]
+#[get("/api/config/toml", wrap = "from_fn(auth::require_admin)")]
 async fn get_config_toml(config: Data<Arc<RwLock<Config>>>) -> actix_web::Result<impl Responder> {
     Ok(HttpResponse::Ok().body(
         config
@@ -139,7 +641,7 @@ async fn get_config_toml(config: Data<Arc<RwLock<Config>>>) -> actix_web::Result
     ))
 }
 
-#[put("/api/config/toml")]
+#[put("/api/config/toml", wrap = "from_fn(auth::require_admin)")]
 async fn put_config_toml(
     config: Data<Arc<RwLock<Config>>>,
     body: web::Bytes,
@@ -194,15 +696,502 @@ async fn serve_static(path: web::Path<String>) -> impl Responder {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::WebserverConfig;
-    use actix_web::{test, App};
+    use crate::{
+        config::WebserverConfig,
+        module::{
+            recorder::{BackendKind, YTAStatus},
+            Notification, RecordingStatus, TaskOutput, TaskStatus,
+        },
+        msgbus::MessageBus,
+    };
+    use actix_web::{
+        test::{
+            call_and_read_body, call_and_read_body_json, call_service, init_service, read_body,
+            TestRequest,
+        },
+        App,
+    };
     use std::sync::Arc;
     use tokio::sync::RwLock;
 
+    #[actix_web::test]
+    async fn test_delete_task() {
+        let mut bus = MessageBus::<Message>::new(16);
+        let tx = bus.add_tx();
+        let mut rx = bus.add_rx("test");
+        tokio::spawn(async move { bus.start().await });
+
+        let tasks: TaskMap = Data::new(RwLock::new(HashMap::new()));
+        let task = Task::test("IKKar5SS29E");
+        tasks.write().await.insert(
+            task.video_id.clone(),
+            TaskWithStatus {
+                task,
+                status: YTAStatus::new(),
+                queue_position: Some(0),
+                held_reason: None,
+            },
+        );
+
+        let app = init_service(
+            App::new()
+                .app_data(Data::new(tx))
+                .app_data(tasks)
+                .service(delete_task),
+        )
+        .await;
+
+        let req = TestRequest::delete()
+            .uri("/api/task/doesnotexist")
+            .to_request();
+        let resp = call_service(&app, req).await;
+        assert_eq!(resp.status(), 404);
+
+        let req = TestRequest::delete()
+            .uri("/api/task/IKKar5SS29E")
+            .to_request();
+        let resp = call_service(&app, req).await;
+        assert_eq!(resp.status(), 202);
+
+        match rx.recv().await {
+            Some(Message::CancelTask(video_id)) => assert_eq!(video_id, "IKKar5SS29E"),
+            other => panic!("Unexpected message: {:?}", other),
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_get_events() {
+        let (events, _) = broadcast::channel::<TaskEvent>(16);
+        let app = init_service(App::new().app_data(Data::new(events)).service(get_events)).await;
+
+        let req = TestRequest::get().uri("/api/events").to_request();
+        let resp = call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(
+            resp.headers().get("Content-Type").unwrap(),
+            "text/event-stream"
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_get_metrics() {
+        let tasks: TaskMap = Data::new(RwLock::new(HashMap::new()));
+        let mut status = YTAStatus::new();
+        BackendKind::Ytarchive.backend().parse_line(
+            &mut status,
+            "Video Fragments: 10; Audio Fragments: 12; Total Downloaded: 1.00MiB",
+        );
+        tasks.write().await.insert(
+            "IKKar5SS29E".into(),
+            TaskWithStatus {
+                task: Task::test("IKKar5SS29E"),
+                status,
+                queue_position: None,
+                held_reason: None,
+            },
+        );
+
+        let app = init_service(App::new().app_data(tasks).service(get_metrics)).await;
+        let req = TestRequest::get().uri("/metrics").to_request();
+        let resp = call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        let body = read_body(resp).await;
+        let body = std::str::from_utf8(&body).unwrap();
+        assert!(body.contains("hoshinova_tasks{state=\"Recording\"} 1\n"));
+        assert!(body.contains("hoshinova_tasks{state=\"Waiting\"} 0\n"));
+        assert!(body.contains("hoshinova_queued_tasks 0\n"));
+        assert!(body.contains(
+            "hoshinova_task_downloaded_bytes{video_id=\"IKKar5SS29E\",channel_name=\"Channel\"} 1048576\n"
+        ));
+    }
+
+    #[actix_web::test]
+    async fn test_get_disk() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config::default();
+        config.ytarchive.working_directory = dir.path().to_string_lossy().into_owned();
+        config.ytarchive.disk_space.min_free_working = Some("1KiB".into());
+        let config = Data::new(Arc::new(RwLock::new(config)));
+
+        let app = init_service(App::new().app_data(config).service(get_disk)).await;
+        let req = TestRequest::get().uri("/api/disk").to_request();
+        let usage: serde_json::Value = call_and_read_body_json(&app, req).await;
+
+        let usage = usage.as_array().unwrap();
+        assert_eq!(usage.len(), 1);
+        assert_eq!(usage[0]["kind"], "working");
+        assert_eq!(usage[0]["min_free_bytes"], 1024);
+        #[cfg(unix)]
+        assert!(usage[0]["free_bytes"].is_u64());
+    }
+
+    #[actix_web::test]
+    async fn test_get_task_log() {
+        use actix_web::body::MessageBody;
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config::default();
+        config.ytarchive.task_log.directory = dir.path().to_string_lossy().into_owned();
+        let mut log = tasklog::TaskLog::open(&config.ytarchive.task_log, "IKKar5SS29E").unwrap();
+        for line in ["line 1", "line 2", "line 3"] {
+            log.write(line).unwrap();
+        }
+        let tasks: TaskMap = Data::new(RwLock::new(HashMap::from([(
+            "IKKar5SS29E".to_string(),
+            finished_task(
+                "IKKar5SS29E",
+                "Channel",
+                "Recording",
+                "2024-05-01T12:00:00Z",
+                None,
+            ),
+        )])));
+        let (events, _) = broadcast::channel::<TaskEvent>(16);
+        let events = Data::new(events);
+        let logs: LogMap = Data::new(RwLock::new(HashMap::from([(
+            "IKKar5SS29E".to_string(),
+            VecDeque::from(["line 2".to_string(), "line 3".to_string()]),
+        )])));
+        let (output, _) = broadcast::channel::<TaskOutput>(16);
+        let output = Data::new(output);
+        let app = init_service(
+            App::new()
+                .app_data(Data::new(Arc::new(RwLock::new(config))))
+                .app_data(tasks)
+                .app_data(events.clone())
+                .app_data(logs.clone())
+                .app_data(output.clone())
+                .service(get_task_log),
+        )
+        .await;
+
+        // The whole log comes from the file, the tail from memory if it can
+        for (uri, expected) in [
+            ("/api/task/IKKar5SS29E/log", "line 1\nline 2\nline 3\n"),
+            ("/api/task/IKKar5SS29E/log?tail=1", "line 3\n"),
+            (
+                "/api/task/IKKar5SS29E/log?tail=5",
+                "line 1\nline 2\nline 3\n",
+            ),
+        ] {
+            let req = TestRequest::get().uri(uri).to_request();
+            let body = call_and_read_body(&app, req).await;
+            assert_eq!(body, expected, "{}", uri);
+        }
+
+        let req = TestRequest::get()
+            .uri("/api/task/AAAAAAAAAAA/log")
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), 404);
+        for uri in [
+            "/api/task/IKKar5SS29/log",
+            "/api/task/..%2F..%2Fetc%2Fpasswd/log",
+        ] {
+            let req = TestRequest::get().uri(uri).to_request();
+            assert_eq!(call_service(&app, req).await.status(), 400, "{}", uri);
+        }
+
+        // Lines that aren't in the file yet come from memory
+        logs.write()
+            .await
+            .get_mut("IKKar5SS29E")
+            .unwrap()
+            .push_back("line 4".into());
+        let req = TestRequest::get()
+            .uri("/api/task/IKKar5SS29E/log")
+            .to_request();
+        let body = call_and_read_body(&app, req).await;
+        assert_eq!(body, "line 1\nline 2\nline 3\nline 4\n");
+
+        // Following sends the tail, then new lines of the task until its
+        // final status
+        let req = TestRequest::get()
+            .uri("/api/task/IKKar5SS29E/log?tail=1&follow=true")
+            .to_request();
+        let body = call_service(&app, req).await.into_body();
+        let mut body = std::pin::pin!(body);
+        let chunk = futures::future::poll_fn(|cx| body.as_mut().poll_next(cx)).await;
+        assert_eq!(chunk.unwrap().unwrap(), "line 4\n");
+        for video_id in ["AAAAAAAAAAA", "IKKar5SS29E"] {
+            output
+                .send(TaskOutput {
+                    video_id: video_id.into(),
+                    line: format!("line from {}", video_id),
+                })
+                .unwrap();
+        }
+        let chunk = futures::future::poll_fn(|cx| body.as_mut().poll_next(cx)).await;
+        assert_eq!(chunk.unwrap().unwrap(), "line from IKKar5SS29E\n");
+
+        let finished = finished_task(
+            "IKKar5SS29E",
+            "Channel",
+            "Finished",
+            "2024-05-01T12:00:00Z",
+            None,
+        );
+        output
+            .send(TaskOutput {
+                video_id: "IKKar5SS29E".into(),
+                line: "last line".into(),
+            })
+            .unwrap();
+        events
+            .send(TaskEvent::RecordingStatus(RecordingStatus {
+                task: finished.task,
+                status: finished.status,
+            }))
+            .unwrap();
+        let chunk = futures::future::poll_fn(|cx| body.as_mut().poll_next(cx)).await;
+        assert_eq!(chunk.unwrap().unwrap(), "last line\n");
+        let chunk = futures::future::poll_fn(|cx| body.as_mut().poll_next(cx)).await;
+        assert!(chunk.is_none());
+    }
+
+    #[test]
+    fn test_merge_log() {
+        let lines =
+            |lines: &[&str]| -> Vec<String> { lines.iter().map(|l| l.to_string()).collect() };
+
+        // The file is behind the buffer
+        let (newer, streamed) = merge_log(&lines(&["1", "2"]), lines(&["1", "2", "3", "4"]));
+        assert_eq!(newer, lines(&["3", "4"]));
+        assert!(streamed.is_empty());
+
+        // The file has lines that are yet to be streamed
+        let (newer, streamed) = merge_log(&lines(&["1", "2", "3", "4"]), lines(&["2", "3"]));
+        assert!(newer.is_empty());
+        assert_eq!(streamed, lines(&["4"]));
+
+        // Both are at the same line
+        let (newer, streamed) = merge_log(&lines(&["1", "2", "3"]), lines(&["2", "3"]));
+        assert!(newer.is_empty());
+        assert!(streamed.is_empty());
+
+        let (newer, streamed) = merge_log(&[], lines(&["1"]));
+        assert_eq!(newer, lines(&["1"]));
+        assert!(streamed.is_empty());
+    }
+
+    fn finished_task(
+        video_id: &str,
+        channel_name: &str,
+        state: &str,
+        finished_at: &str,
+        output_file: Option<&str>,
+    ) -> TaskWithStatus {
+        TaskWithStatus {
+            task: Task {
+                channel_name: channel_name.into(),
+                channel_id: format!("UC{}", channel_name),
+                ..Task::test(video_id)
+            },
+            status: serde_json::from_value(serde_json::json!({
+                "state": state,
+                "last_update": finished_at,
+                "started_at": "2024-05-01T10:00:00Z",
+                "finished_at": finished_at,
+                "output_file": output_file,
+                "is_final": state != "Recording",
+            }))
+            .unwrap(),
+            queue_position: None,
+            held_reason: None,
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_get_history() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let video = dir.path().join("video.mp4");
+        std::fs::write(&video, "12345").unwrap();
+
+        let tasks: TaskMap = Data::new(RwLock::new(HashMap::new()));
+        for task in [
+            finished_task(
+                "AAAAAAAAAAA",
+                "Moona",
+                "Finished",
+                "2024-05-01T12:00:00Z",
+                Some(&video.to_string_lossy()),
+            ),
+            finished_task(
+                "BBBBBBBBBBB",
+                "Moona",
+                "Errored",
+                "2024-05-02T12:00:00Z",
+                None,
+            ),
+            finished_task(
+                "CCCCCCCCCCC",
+                "Pekora",
+                "Finished",
+                "2024-05-03T12:00:00Z",
+                Some("/nonexistent.mp4"),
+            ),
+            finished_task(
+                "DDDDDDDDDDD",
+                "Pekora",
+                "Recording",
+                "2024-05-03T12:00:00Z",
+                None,
+            ),
+        ] {
+            tasks.write().await.insert(task.task.video_id.clone(), task);
+        }
+        let app = init_service(
+            App::new()
+                .app_data(Data::new(StoredTasks::default()))
+                .app_data(tasks)
+                .service(get_history),
+        )
+        .await;
+        let get = |uri: &str| TestRequest::get().uri(uri).to_request();
+
+        // Active tasks are left out, and the latest come first
+        let page: serde_json::Value = call_and_read_body_json(&app, get("/api/history")).await;
+        assert_eq!(page["total"], 3);
+        let ids: Vec<_> = page["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["task"]["video_id"].as_str().unwrap())
+            .collect();
+        assert_eq!(ids, vec!["CCCCCCCCCCC", "BBBBBBBBBBB", "AAAAAAAAAAA"]);
+        let entry = &page["entries"][2];
+        assert_eq!(entry["duration_seconds"], 2 * 60 * 60);
+        assert_eq!(entry["file_size"], 5);
+        assert_eq!(page["entries"][0]["file_size"], serde_json::Value::Null);
+
+        let page: serde_json::Value =
+            call_and_read_body_json(&app, get("/api/history?offset=1&limit=1")).await;
+        assert_eq!(page["total"], 3);
+        assert_eq!(page["entries"][0]["task"]["video_id"], "BBBBBBBBBBB");
+
+        let page: serde_json::Value =
+            call_and_read_body_json(&app, get("/api/history?channel=Moona&state=finished")).await;
+        assert_eq!(page["total"], 1);
+        assert_eq!(page["entries"][0]["task"]["video_id"], "AAAAAAAAAAA");
+
+        let page: serde_json::Value = call_and_read_body_json(
+            &app,
+            get("/api/history?channel=UCPekora&since=2024-05-02T00:00:00Z&until=2024-05-04T00:00:00Z"),
+        )
+        .await;
+        assert_eq!(page["total"], 1);
+        assert_eq!(page["entries"][0]["task"]["video_id"], "CCCCCCCCCCC");
+
+        let resp = call_service(&app, get("/api/history?since=yesterday")).await;
+        assert_eq!(resp.status(), 400);
+    }
+
+    #[actix_web::test]
+    async fn test_get_history_from_store() {
+        use crate::module::store::StoredTask;
+
+        let stored: HashMap<String, StoredTask> = [
+            // Pruned from memory
+            finished_task(
+                "AAAAAAAAAAA",
+                "Moona",
+                "Finished",
+                "2024-05-01T12:00:00Z",
+                None,
+            ),
+            // Finished since it was last stored
+            finished_task(
+                "BBBBBBBBBBB",
+                "Moona",
+                "Recording",
+                "2024-05-02T12:00:00Z",
+                None,
+            ),
+            // Queued again
+            finished_task(
+                "CCCCCCCCCCC",
+                "Moona",
+                "Finished",
+                "2024-05-03T12:00:00Z",
+                None,
+            ),
+            // Interrupted by a restart, waiting to be recorded again
+            finished_task(
+                "DDDDDDDDDDD",
+                "Moona",
+                "Recording",
+                "2024-05-04T12:00:00Z",
+                None,
+            ),
+        ]
+        .into_iter()
+        .map(|t| {
+            let entry = StoredTask {
+                task: t.task,
+                status: t.status,
+            };
+            (entry.task.video_id.clone(), entry)
+        })
+        .collect();
+
+        let tasks: TaskMap = Data::new(RwLock::new(HashMap::new()));
+        let mut requeued = finished_task(
+            "CCCCCCCCCCC",
+            "Moona",
+            "Finished",
+            "2024-05-03T12:00:00Z",
+            None,
+        );
+        requeued.queue_position = Some(0);
+        for task in [
+            finished_task(
+                "BBBBBBBBBBB",
+                "Moona",
+                "Errored",
+                "2024-05-02T12:00:00Z",
+                None,
+            ),
+            requeued,
+        ] {
+            tasks.write().await.insert(task.task.video_id.clone(), task);
+        }
+        let app = init_service(
+            App::new()
+                .app_data(Data::new(StoredTasks::new(RwLock::new(stored))))
+                .app_data(tasks)
+                .service(get_history),
+        )
+        .await;
+
+        let req = TestRequest::get().uri("/api/history").to_request();
+        let page: serde_json::Value = call_and_read_body_json(&app, req).await;
+        assert_eq!(page["total"], 2);
+        assert_eq!(page["entries"][0]["task"]["video_id"], "BBBBBBBBBBB");
+        assert_eq!(page["entries"][0]["state"], "Errored");
+        assert_eq!(page["entries"][1]["task"]["video_id"], "AAAAAAAAAAA");
+    }
+
+    #[actix_web::test]
+    async fn test_format_event() {
+        let event = TaskEvent::Notification(Notification {
+            task: Task::test("IKKar5SS29E"),
+            status: TaskStatus::Done,
+            attempt: 1,
+            yta_status: None,
+            message: None,
+        });
+
+        let bytes = format_event(&event).unwrap();
+        let text = std::str::from_utf8(&bytes).unwrap();
+        assert!(text.starts_with("data: {\"type\":\"Notification\",\"data\":{"));
+        assert!(text.contains("\"status\":\"done\""));
+        assert!(text.ends_with("}\n\n"));
+    }
+
     #[actix_web::test]
     async fn test_put_config_toml_invalid() {
         let config = Arc::new(RwLock::new(Config::default()));
-        let app = test::init_service(
+        let app = init_service(
             App::new()
                 .app_data(Data::new(config.clone()))
                 .service(put_config_toml),
@@ -216,11 +1205,11 @@ mod tests {
         });
         drop(config_guard);
 
-        let req = test::TestRequest::put()
+        let req = TestRequest::put()
             .uri("/api/config/toml")
             .set_payload("test")
             .to_request();
-        let resp = test::call_service(&app, req).await;
+        let resp = call_service(&app, req).await;
 
         assert_eq!(resp.status(), 400); // Invalid config file, but API is allowed
     }
@@ -228,7 +1217,7 @@ mod tests {
     #[actix_web::test]
     async fn test_put_config_toml_forbidden() {
         let config = Arc::new(RwLock::new(Config::default()));
-        let app = test::init_service(
+        let app = init_service(
             App::new()
                 .app_data(Data::new(config.clone()))
                 .service(put_config_toml),
@@ -242,11 +1231,11 @@ mod tests {
         });
         drop(config_guard);
 
-        let req = test::TestRequest::put()
+        let req = TestRequest::put()
             .uri("/api/config/toml")
             .set_payload("test")
             .to_request();
-        let resp = test::call_service(&app, req).await;
+        let resp = call_service(&app, req).await;
 
         assert_eq!(resp.status(), 403);
     }
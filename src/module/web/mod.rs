@@ -1,24 +1,30 @@
-use super::{recorder::YTAStatus, Message, Module, Task};
+use super::{
+    recorder::YTAStatus, store::StoredTasks, Message, Module, Notification, RecordingStatus, Task,
+    TaskOutput,
+};
 use crate::{
     config::{Config, WebserverConfig},
     msgbus::BusTx,
 };
-use actix_web::{web::Data, App, HttpServer};
+use actix_web::{middleware::from_fn, web::Data, App, HttpServer};
 use anyhow::{Context, Result};
 use async_trait::async_trait;
 use serde::Serialize;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::sync::Arc;
 use tokio::{
     select,
-    sync::{mpsc, RwLock},
+    sync::{broadcast, mpsc, RwLock},
 };
 use ts_rs::TS;
 
+mod auth;
 mod handler;
 
 pub struct WebServer {
     config: Arc<RwLock<Config>>,
+    output: broadcast::Sender<TaskOutput>,
+    stored: StoredTasks,
 }
 
 #[derive(Debug, Clone, TS, Serialize)]
@@ -26,11 +32,52 @@ pub struct WebServer {
 pub struct TaskWithStatus {
     pub task: Task,
     pub status: YTAStatus,
+    /// Number of tasks ahead of this one in the recorder queue, if queued.
+    pub queue_position: Option<usize>,
+    /// Why the task is held in the queue, if it is.
+    pub held_reason: Option<String>,
+}
+
+impl TaskWithStatus {
+    /// Returns true if the recorder is done with the task: it's not queued,
+    /// and won't be retried or recorded with another backend.
+    fn is_finished(&self) -> bool {
+        self.queue_position.is_none() && self.status.is_final()
+    }
 }
 
 type TaskMap = Data<RwLock<HashMap<String, TaskWithStatus>>>;
 
+/// An update about a task, streamed to clients of the events endpoint.
+#[derive(Debug, Clone, TS, Serialize)]
+#[serde(tag = "type", content = "data")]
+#[ts(export)]
+pub enum TaskEvent {
+    RecordingStatus(RecordingStatus),
+    Notification(Notification),
+}
+
+type EventSender = Data<broadcast::Sender<TaskEvent>>;
+
+/// The most recent lines of the log of each task.
+type LogMap = Data<RwLock<HashMap<String, VecDeque<String>>>>;
+
+type LogSender = Data<broadcast::Sender<TaskOutput>>;
+
 impl WebServer {
+    /// Serves the task output streamed to the given channel.
+    pub fn with_output(mut self, output: broadcast::Sender<TaskOutput>) -> Self {
+        self.output = output;
+        self
+    }
+
+    /// Lists the tasks of the task store in the history, including the ones
+    /// that were pruned from memory.
+    pub fn with_stored_tasks(mut self, stored: StoredTasks) -> Self {
+        self.stored = stored;
+        self
+    }
+
     /// Return the webserver configuration
     async fn get_wsconfig(&self) -> Option<WebserverConfig> {
         let config = self.config.read().await;
@@ -40,32 +87,135 @@ impl WebServer {
     async fn bus_listen_loop(
         &self,
         rx: &mut mpsc::Receiver<Message>,
+        task_output: &mut broadcast::Receiver<TaskOutput>,
         tasks: TaskMap,
+        events: EventSender,
+        logs: LogMap,
+        output: LogSender,
     ) -> Result<()> {
-        while let Some(msg) = rx.recv().await {
+        loop {
+            // Output comes first, so that the lines of a task are sent to
+            // its followers before its final status
+            let msg = select! {
+                biased;
+                line = task_output.recv() => {
+                    match line {
+                        Ok(line) => self.buffer_output(&logs, &output, line).await,
+                        Err(broadcast::error::RecvError::Lagged(n)) => {
+                            warn!("Skipped {} lines of task output", n)
+                        }
+                        // The module holds a sender, so the channel stays open
+                        Err(broadcast::error::RecvError::Closed) => unreachable!(),
+                    }
+                    continue;
+                }
+                msg = rx.recv() => match msg {
+                    Some(msg) => msg,
+                    None => break,
+                },
+            };
             match msg {
                 Message::RecordingStatus(recstat) => {
                     let id = recstat.task.video_id.clone();
                     let mut tasks = tasks.write().await;
-                    tasks.insert(
-                        id,
-                        TaskWithStatus {
-                            task: recstat.task,
-                            status: recstat.status,
-                        },
-                    );
+                    let task = TaskWithStatus {
+                        task: recstat.task.clone(),
+                        status: recstat.status.clone(),
+                        queue_position: None,
+                        held_reason: None,
+                    };
+                    let just_finished =
+                        task.is_finished() && !tasks.get(&id).is_some_and(|old| old.is_finished());
+                    tasks.insert(id, task);
+
+                    // Sent once the task is updated, so that log followers
+                    // that find it unfinished get its final status. Sending
+                    // only fails if nobody is listening.
+                    let _ = events.send(TaskEvent::RecordingStatus(recstat));
+
+                    if just_finished {
+                        let limit = self
+                            .config
+                            .read()
+                            .await
+                            .webserver
+                            .clone()
+                            .unwrap_or_default()
+                            .history_limit;
+                        let mut logs = logs.write().await;
+                        for id in prune_history(&tasks, limit) {
+                            tasks.remove(&id);
+                            logs.remove(&id);
+                        }
+                    }
+                }
+                Message::QueueStatus(questat) => {
+                    let id = questat.task.video_id.clone();
+                    let mut tasks = tasks.write().await;
+                    let task = tasks.entry(id).or_insert_with(|| TaskWithStatus {
+                        task: questat.task,
+                        status: YTAStatus::new(),
+                        queue_position: None,
+                        held_reason: None,
+                    });
+                    task.queue_position = questat.position;
+                    task.held_reason = questat.held_reason;
+                }
+                Message::ToNotify(notification) => {
+                    let _ = events.send(TaskEvent::Notification(notification));
                 }
                 _ => (),
             }
         }
         Ok(())
     }
+
+    /// Keeps a line of task output in the buffer of the task and sends it to
+    /// the followers of its log.
+    async fn buffer_output(&self, logs: &LogMap, output: &LogSender, line: TaskOutput) {
+        let max_lines = self.config.read().await.ytarchive.task_log.buffer_lines;
+        // Followers subscribe while holding the lock, so that they get each
+        // line either from the buffer or the channel
+        let mut logs = logs.write().await;
+        let buffer = logs.entry(line.video_id.clone()).or_default();
+        buffer.push_back(line.line.clone());
+        while buffer.len() > max_lines {
+            buffer.pop_front();
+        }
+        let _ = output.send(line);
+    }
+}
+
+/// Returns the IDs of the tasks that finished first, beyond the `limit` most
+/// recently finished ones.
+fn prune_history(tasks: &HashMap<String, TaskWithStatus>, limit: usize) -> Vec<String> {
+    let mut finished: Vec<_> = tasks
+        .values()
+        .filter(|t| t.is_finished())
+        .map(|t| (t.status.finished_at(), &t.task.video_id))
+        .collect();
+    if finished.len() <= limit {
+        return vec![];
+    }
+    // Tasks without an end time come first
+    finished.sort();
+    let excess = finished.len() - limit;
+    finished
+        .into_iter()
+        .take(excess)
+        .map(|(_, id)| id.clone())
+        .collect()
 }
 
 #[async_trait]
 impl Module for WebServer {
     fn new(config: Arc<RwLock<Config>>) -> Self {
-        Self { config }
+        let (output, _) = broadcast::channel(1);
+        Self {
+            config,
+            output,
+            stored: Default::default(),
+        }
     }
 
     async fn run(&self, tx: &BusTx<Message>, rx: &mut mpsc::Receiver<Message>) -> Result<()> {
@@ -84,18 +234,41 @@ impl Module for WebServer {
         // Create a HashMap to hold the tasks
         let tasks = Data::new(RwLock::new(HashMap::new()));
 
+        // Create a channel to stream events to clients
+        let (events, _) = broadcast::channel(1024);
+        let events = Data::new(events);
+
+        // Keep the recent output of each task, and stream it to followers
+        let mut task_output = self.output.subscribe();
+        let logs = Data::new(RwLock::new(HashMap::new()));
+        let (output, _) = broadcast::channel(1024);
+        let output = Data::new(output);
+
         // Listen to the bus
-        let busll = self.bus_listen_loop(rx, tasks.clone());
+        let busll = self.bus_listen_loop(
+            rx,
+            &mut task_output,
+            tasks.clone(),
+            events.clone(),
+            logs.clone(),
+            output.clone(),
+        );
 
         // Set up webserver
         let config = Data::new(self.config.clone());
         let tx = Data::new(tx.clone());
+        let stored = Data::new(self.stored.clone());
         let ws = {
             let mut server = HttpServer::new(move || {
                 App::new()
                     .app_data(config.clone())
                     .app_data(tx.clone())
                     .app_data(tasks.clone())
+                    .app_data(events.clone())
+                    .app_data(logs.clone())
+                    .app_data(output.clone())
+                    .app_data(stored.clone())
+                    .wrap(from_fn(auth::authenticate))
                     .configure(handler::configure)
             })
             .disable_signals();
@@ -131,3 +304,50 @@ impl Module for WebServer {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prune_history() {
+        let task =
+            |video_id: &str, state: &str, finished_at: Option<&str>, is_final| TaskWithStatus {
+                task: Task::test(video_id),
+                status: serde_json::from_value(serde_json::json!({
+                    "state": state,
+                    "last_update": "2024-05-01T00:00:00Z",
+                    "finished_at": finished_at,
+                    "is_final": is_final,
+                }))
+                .unwrap(),
+                queue_position: None,
+                held_reason: None,
+            };
+        let tasks: HashMap<_, _> = [
+            task(
+                "AAAAAAAAAAA",
+                "Finished",
+                Some("2024-05-02T00:00:00Z"),
+                true,
+            ),
+            task("BBBBBBBBBBB", "Errored", Some("2024-05-01T00:00:00Z"), true),
+            task("CCCCCCCCCCC", "Finished", None, true),
+            task("DDDDDDDDDDD", "Recording", None, false),
+            // Waiting to be retried
+            task(
+                "EEEEEEEEEEE",
+                "Errored",
+                Some("2024-04-01T00:00:00Z"),
+                false,
+            ),
+        ]
+        .into_iter()
+        .map(|t| (t.task.video_id.clone(), t))
+        .collect();
+
+        assert!(prune_history(&tasks, 3).is_empty());
+        assert_eq!(prune_history(&tasks, 1), vec!["CCCCCCCCCCC", "BBBBBBBBBBB"]);
+        assert_eq!(prune_history(&tasks, 0).len(), 3);
+    }
+}
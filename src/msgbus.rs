@@ -1,3 +1,4 @@
+use crate::metrics;
 use std::fmt::Debug;
 use tokio::sync::mpsc;
 
@@ -16,10 +17,11 @@ pub struct MessageBus<T: Debug + Clone + Sync> {
     mix_tx: Vec<mpsc::Sender<T>>,
 }
 
-impl<T: Debug + Clone + Sync> MessageBus<T> {
+impl<T: Debug + Clone + Sync + Send + 'static> MessageBus<T> {
     /// Creates a new MessageBus with the given capacity.
     pub fn new(capacity: usize) -> Self {
         let (tx, mix_rx) = mpsc::channel(capacity);
+        Self::collect_depth("bus", &tx);
         Self {
             capacity,
             tx,
@@ -34,13 +36,25 @@ impl<T: Debug + Clone + Sync> MessageBus<T> {
         BusTx { tx }
     }
 
-    /// Returns a new Receiver that can be used to receive messages.
-    pub fn add_rx(&mut self) -> mpsc::Receiver<T> {
+    /// Returns a new Receiver that can be used to receive messages. The name
+    /// identifies the queue in the metrics.
+    pub fn add_rx(&mut self, name: &str) -> mpsc::Receiver<T> {
         let (tx, rx) = mpsc::channel(self.capacity);
+        Self::collect_depth(name, &tx);
         self.mix_tx.push(tx);
         rx
     }
 
+    /// Reports the number of messages waiting in the queue of the sender, for
+    /// as long as the queue exists.
+    fn collect_depth<M: Send + 'static>(name: &str, tx: &mpsc::Sender<M>) {
+        let tx = tx.downgrade();
+        metrics::BUS_QUEUE_DEPTH.collect(&[("queue", name)], move || {
+            let tx = tx.upgrade()?;
+            Some((tx.max_capacity() - tx.capacity()) as f64)
+        });
+    }
+
     /// Starts the message bus. This will continue running until the bus is
     /// closed.
     pub async fn start(&mut self) {
@@ -80,6 +94,11 @@ impl<T: Debug + Clone + Sync> BusTx<T> {
             })
     }
 
+    /// Waits until the message bus has stopped.
+    pub async fn closed(&self) {
+        self.tx.closed().await
+    }
+
     pub async fn close(&self) -> Result<(), mpsc::error::SendError<()>> {
         self.tx
             .send(BusMessage::Close)
@@ -1,6 +1,7 @@
 use anyhow::{anyhow, Context, Result};
 use lazy_static::lazy_static;
 use reqwest::Client;
+use serde_json::Value;
 
 pub async fn fetch_picture_url(client: Client, channel_id: &str) -> Result<String> {
     // Fetch the channel page
@@ -30,3 +31,198 @@ pub async fn fetch_picture_url(client: Client, channel_id: &str) -> Result<Strin
 
     Ok(picture_url.to_owned())
 }
+
+/// A live or upcoming video listed on a channel's streams tab
+#[derive(Debug, PartialEq)]
+pub struct StreamVideo {
+    pub video_id: String,
+    pub title: String,
+    pub thumbnail: String,
+    pub description: String,
+    /// Whether the video is live now, as opposed to scheduled
+    pub live: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ChannelStreams {
+    pub channel_name: Option<String>,
+    pub videos: Vec<StreamVideo>,
+}
+
+pub async fn fetch_streams(client: Client, channel_id: &str) -> Result<ChannelStreams> {
+    let url = format!("https://www.youtube.com/channel/{}/streams", channel_id);
+    let html = client
+        .get(&url)
+        .send()
+        .await
+        .context("Failed to fetch channel streams page")?
+        .error_for_status()
+        .context("Channel streams page returned error")?
+        .text()
+        .await
+        .context("Failed to read channel streams page")?;
+
+    parse_streams(&html)
+}
+
+/// Finds the live and upcoming videos in the `ytInitialData` embedded in a
+/// channel's streams page. Past streams are ignored.
+fn parse_streams(html: &str) -> Result<ChannelStreams> {
+    let start = html
+        .find("ytInitialData = ")
+        .ok_or(anyhow!("Failed to find the initial data"))?;
+    let data = serde_json::Deserializer::from_str(&html[start + "ytInitialData = ".len()..])
+        .into_iter::<Value>()
+        .next()
+        .ok_or(anyhow!("Failed to find the initial data"))?
+        .context("Failed to parse the initial data")?;
+
+    let channel_name = data
+        .pointer("/metadata/channelMetadataRenderer/title")
+        .and_then(Value::as_str)
+        .map(str::to_owned);
+
+    let mut renderers = vec![];
+    find_video_renderers(&data, &mut renderers);
+    let videos = renderers
+        .into_iter()
+        .filter_map(|renderer| {
+            let live = is_live(renderer);
+            if !live && renderer.get("upcomingEventData").is_none() {
+                return None;
+            }
+            Some(StreamVideo {
+                video_id: renderer.get("videoId")?.as_str()?.to_owned(),
+                title: text(renderer.get("title")?),
+                thumbnail: renderer
+                    .pointer("/thumbnail/thumbnails")
+                    .and_then(Value::as_array)
+                    .and_then(|thumbnails| thumbnails.last())
+                    .and_then(|thumbnail| thumbnail.get("url"))
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_owned(),
+                description: renderer
+                    .get("descriptionSnippet")
+                    .map(text)
+                    .unwrap_or_default(),
+                live,
+            })
+        })
+        .collect();
+
+    Ok(ChannelStreams {
+        channel_name,
+        videos,
+    })
+}
+
+/// Collects every `videoRenderer` object, wherever it is nested.
+fn find_video_renderers<'a>(value: &'a Value, out: &mut Vec<&'a Value>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                if key == "videoRenderer" {
+                    out.push(value);
+                } else {
+                    find_video_renderers(value, out);
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                find_video_renderers(item, out);
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Live videos are marked either with a badge or a thumbnail overlay.
+fn is_live(renderer: &Value) -> bool {
+    let badge = renderer
+        .get("badges")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|badge| badge.pointer("/metadataBadgeRenderer/style"))
+        .any(|style| style == "BADGE_STYLE_TYPE_LIVE_NOW");
+    let overlay = renderer
+        .get("thumbnailOverlays")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|overlay| overlay.pointer("/thumbnailOverlayTimeStatusRenderer/style"))
+        .any(|style| style == "LIVE");
+    badge || overlay
+}
+
+/// Reads a text object, which is either made of runs or a simple text.
+fn text(value: &Value) -> String {
+    if let Some(runs) = value.get("runs").and_then(Value::as_array) {
+        runs.iter()
+            .filter_map(|run| run.get("text").and_then(Value::as_str))
+            .collect()
+    } else {
+        value
+            .get("simpleText")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_streams() {
+        let html = r#"<script nonce="x">var ytInitialData = {
+            "metadata": {"channelMetadataRenderer": {"title": "Channel"}},
+            "contents": {"tabs": [{"tabRenderer": {"content": {"richGridRenderer": {"contents": [
+                {"richItemRenderer": {"content": {"videoRenderer": {
+                    "videoId": "upcoming000",
+                    "title": {"runs": [{"text": "Upcoming "}, {"text": "stream"}]},
+                    "thumbnail": {"thumbnails": [{"url": "small.jpg"}, {"url": "large.jpg"}]},
+                    "descriptionSnippet": {"runs": [{"text": "Karaoke!"}]},
+                    "upcomingEventData": {"startTime": "1700000000"}
+                }}}},
+                {"richItemRenderer": {"content": {"videoRenderer": {
+                    "videoId": "livenow0000",
+                    "title": {"simpleText": "Live stream; now {with} braces"},
+                    "badges": [{"metadataBadgeRenderer": {"style": "BADGE_STYLE_TYPE_LIVE_NOW"}}]
+                }}}},
+                {"richItemRenderer": {"content": {"videoRenderer": {
+                    "videoId": "pastvideo00",
+                    "title": {"runs": [{"text": "Past stream"}]},
+                    "thumbnailOverlays": [{"thumbnailOverlayTimeStatusRenderer": {"style": "DEFAULT"}}]
+                }}}}
+            ]}}}}]}
+        };</script>"#;
+
+        let streams = parse_streams(html).unwrap();
+        assert_eq!(streams.channel_name.as_deref(), Some("Channel"));
+        assert_eq!(
+            streams.videos,
+            vec![
+                StreamVideo {
+                    video_id: "upcoming000".into(),
+                    title: "Upcoming stream".into(),
+                    thumbnail: "large.jpg".into(),
+                    description: "Karaoke!".into(),
+                    live: false,
+                },
+                StreamVideo {
+                    video_id: "livenow0000".into(),
+                    title: "Live stream; now {with} braces".into(),
+                    thumbnail: "".into(),
+                    description: "".into(),
+                    live: true,
+                },
+            ]
+        );
+
+        assert!(parse_streams("<html></html>").is_err());
+    }
+}
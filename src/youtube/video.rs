@@ -7,6 +7,7 @@ use serde::Deserialize;
 pub struct InitialPlayerResponse {
     #[serde(rename = "videoDetails")]
     pub video_details: InitialPlayerResponseVideoDetails,
+    pub microformat: Option<InitialPlayerResponseMicroformat>,
 }
 #[derive(Deserialize)]
 pub struct InitialPlayerResponseVideoDetails {
@@ -17,6 +18,10 @@ pub struct InitialPlayerResponseVideoDetails {
     pub channel_id: String,
     pub author: String,
     pub thumbnail: InitialPlayerResponseVideoDetailsThumbnail,
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    #[serde(rename = "shortDescription", default)]
+    pub short_description: String,
 }
 #[derive(Deserialize)]
 pub struct InitialPlayerResponseVideoDetailsThumbnail {
@@ -28,6 +33,33 @@ pub struct InitialPlayerResponseVideoDetailsThumbnailThumbnail {
     pub width: u32,
     pub height: u32,
 }
+#[derive(Deserialize)]
+pub struct InitialPlayerResponseMicroformat {
+    #[serde(rename = "playerMicroformatRenderer")]
+    pub player_microformat_renderer: InitialPlayerResponseMicroformatRenderer,
+}
+#[derive(Deserialize)]
+pub struct InitialPlayerResponseMicroformatRenderer {
+    #[serde(rename = "liveBroadcastDetails")]
+    pub live_broadcast_details: Option<InitialPlayerResponseLiveBroadcastDetails>,
+}
+#[derive(Deserialize)]
+pub struct InitialPlayerResponseLiveBroadcastDetails {
+    #[serde(rename = "startTimestamp")]
+    pub start_timestamp: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl InitialPlayerResponse {
+    /// The time the stream is scheduled to start, or started at
+    pub fn start_timestamp(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.microformat
+            .as_ref()?
+            .player_microformat_renderer
+            .live_broadcast_details
+            .as_ref()?
+            .start_timestamp
+    }
+}
 
 pub async fn fetch_initial_player_response(
     client: Client,
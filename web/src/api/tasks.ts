@@ -14,6 +14,24 @@ export const stateKey = (state: YTAState) =>
   typeof state === 'object'
     ? (Object.keys(state) as (keyof typeof state)[])[0]
     : state;
+export const formatBytes = (bytes: number) => {
+  const units = ['B', 'KiB', 'MiB', 'GiB', 'TiB'];
+  let i = 0;
+  while (bytes >= 1024 && i < units.length - 1) {
+    bytes /= 1024;
+    i++;
+  }
+  return bytes.toFixed(i === 0 ? 0 : 2) + units[i];
+};
+export const formatDuration = (seconds: number) => {
+  const s = Math.round(seconds);
+  const hh = Math.floor(s / 3600);
+  const mm = String(Math.floor((s % 3600) / 60)).padStart(2, '0');
+  const ss = String(s % 60).padStart(2, '0');
+  return `${hh}:${mm}:${ss}`;
+};
+export const isActiveState = (state: YTAState) =>
+  ['Idle', 'Waiting', 'Recording'].includes(stateKey(state));
 
 const stateSort: ReturnType<typeof stateKey>[] = [
   'Recording',
@@ -24,6 +42,7 @@ const stateSort: ReturnType<typeof stateKey>[] = [
   'Ended',
   'AlreadyProcessed',
   'Interrupted',
+  'Stalled',
 ];
 export const useQueryTasks = () =>
   useQuery(
@@ -60,3 +79,18 @@ export const useMutateCreateTask = () => {
     }
   );
 };
+
+export const useMutateCancelTask = () => {
+  const queryClient = useQueryClient();
+  return useMutation(
+    (videoId: string) =>
+      fetch('/api/task/' + encodeURIComponent(videoId), {
+        method: 'DELETE',
+      }).then(rejectError),
+    {
+      onSuccess: () => {
+        queryClient.invalidateQueries(['tasks']);
+      },
+    }
+  );
+};
@@ -17,7 +17,15 @@ import {
   Title,
 } from '@mantine/core';
 import React from 'react';
-import { stateString, useMutateCreateTask, useQueryTasks } from '../api/tasks';
+import {
+  formatBytes,
+  formatDuration,
+  isActiveState,
+  stateString,
+  useMutateCancelTask,
+  useMutateCreateTask,
+  useQueryTasks,
+} from '../api/tasks';
 import { TaskWithStatus } from '../bindings/TaskWithStatus';
 import { SuspenseLoader } from '../shared/SuspenseLoader';
 import { IconPlus } from '@tabler/icons';
@@ -39,7 +47,7 @@ const TaskStateBadge = ({ state }: { state: YTAState }) => (
         ? 'yellow'
         : state === 'Idle' || state === 'AlreadyProcessed' || state === 'Ended'
         ? 'gray'
-        : state === 'Interrupted' || state === 'Errored'
+        : state === 'Interrupted' || state === 'Errored' || state === 'Stalled'
         ? 'red'
         : 'violet'
     }
@@ -49,7 +57,36 @@ const TaskStateBadge = ({ state }: { state: YTAState }) => (
   </Badge>
 );
 
-const rowElements = ({ task, status }: TaskWithStatus) => [
+const CancelTaskButton = ({ videoId }: { videoId: string }) => {
+  const mCancelTask = useMutateCancelTask();
+  return (
+    <Button
+      compact
+      color="red"
+      variant="subtle"
+      loading={mCancelTask.isLoading}
+      onClick={() =>
+        mCancelTask.mutateAsync(videoId, {
+          onError() {
+            showNotification({
+              message: 'Failed to cancel task',
+              color: 'red',
+            });
+          },
+        })
+      }
+    >
+      Cancel
+    </Button>
+  );
+};
+
+const rowElements = ({
+  task,
+  status,
+  queue_position,
+  held_reason,
+}: TaskWithStatus) => [
   <Image width={160} height={90} radius="md" src={task.video_picture} />,
   <>
     <Anchor
@@ -66,14 +103,39 @@ const rowElements = ({ task, status }: TaskWithStatus) => [
       {task.channel_name}
     </Anchor>
   </>,
-  <TaskStateBadge state={status.state} />,
+  <Group spacing="xs">
+    {queue_position === null ? (
+      <TaskStateBadge state={status.state} />
+    ) : held_reason !== null ? (
+      <Badge color="orange" variant="filled" title={held_reason}>
+        Held (#{queue_position + 1})
+      </Badge>
+    ) : (
+      <Badge color="gray" variant="filled">
+        Queued (#{queue_position + 1})
+      </Badge>
+    )}
+    {(queue_position !== null || isActiveState(status.state)) && (
+      <CancelTaskButton videoId={task.video_id} />
+    )}
+  </Group>,
   <>
-    {status.total_size === null ? (
+    {status.state === 'Muxing' && status.muxing_percent !== null ? (
+      <>
+        Muxing: {status.muxing_percent.toFixed(0)}%
+        {status.eta_seconds !== null &&
+          ` / ETA: ${formatDuration(status.eta_seconds)}`}
+      </>
+    ) : status.total_size === null ? (
       'None'
     ) : (
       <>
         V: {status.video_fragments || '?'} / A: {status.audio_fragments || '?'}{' '}
         / DL: {status.total_size || '?'}
+        {status.download_rate !== null &&
+          ` (${formatBytes(status.download_rate)}/s)`}
+        {status.recorded_seconds !== null &&
+          ` / Length: ${formatDuration(status.recorded_seconds)}`}
       </>
     )}
   </>,
@@ -91,7 +153,12 @@ const AddVideoModal = () => {
 
   React.useEffect(() => {
     if (!qConfig.data) return;
-    const outPaths = new Set(qConfig.data.channel.map((ch) => ch.outpath));
+    const { channel, group } = qConfig.data;
+    const outPaths = new Set(
+      channel
+        .map((ch) => ch.outpath ?? (ch.group ? group[ch.group]?.outpath : null))
+        .filter((path): path is string => !!path)
+    );
     if (outPaths.size > destPaths.length) {
       setDestPaths(
         Array.from(outPaths).map((path) => ({ value: path, label: path }))
@@ -211,9 +278,9 @@ const TasksPage = () => {
             </tr>
           </thead>
           <tbody>
-            {tasks.map(({ task, status }) => (
-              <tr key={task.video_id}>
-                {rowElements({ task, status }).map((row, idx) => (
+            {tasks.map((t) => (
+              <tr key={t.task.video_id}>
+                {rowElements(t).map((row, idx) => (
                   <td key={idx}>{row}</td>
                 ))}
               </tr>
@@ -231,8 +298,9 @@ const TasksPage = () => {
             { maxWidth: 'xs', cols: 1, spacing: 'sm' },
           ]}
         >
-          {tasks.map(({ task, status }) => {
-            const [_, title, state, progres] = rowElements({ task, status });
+          {tasks.map((t) => {
+            const { task } = t;
+            const [_, title, state, progres] = rowElements(t);
             return (
               <Card key={task.video_id}>
                 <Card.Section>